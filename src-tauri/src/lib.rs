@@ -1,4 +1,5 @@
 mod media;
+mod pdf_crypt;
 
 use std::{
     path::{Path, PathBuf},
@@ -137,13 +138,14 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_fs::init())
-        .setup(|_app| {
-            crate::media::init_pdf_worker();
+        .setup(|app| {
+            crate::media::init_pdf_worker(app.handle().clone());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             frontend_ready,
             media::analyze_media,
+            media::analyze_media_batch,
             media::image_read,
             media::compress_image,
             media::compress_pdf_lossless,
@@ -151,6 +153,10 @@ pub fn run() {
             media::image_to_pdf,
             media::pdf_open,
             media::pdf_close,
+            media::pdf_doc_state,
+            media::recovery_list,
+            media::recovery_restore,
+            media::recovery_discard,
             media::pdf_render_page,
             media::pdf_render_page_async,
             media::pdf_page_size,
@@ -163,6 +169,44 @@ pub fn run() {
             media::pdf_export_page_image,
             media::pdf_export_page_pdf,
             media::pdf_rotate_page_relative,
+            media::pdf_duplicate_pages,
+            media::pdf_reverse_pages,
+            media::pdf_interleave_pages,
+            media::pdf_insert_from_file,
+            media::pdf_set_page_boxes,
+            media::pdf_normalize_page_size,
+            media::pdf_impose,
+            media::pdf_add_watermark,
+            media::pdf_add_header_footer,
+            media::pdf_rotate_pages,
+            media::pdf_page_rotations,
+            media::pdf_auto_orient,
+            media::pdf_deskew,
+            media::pdf_deskew_preview,
+            media::pdf_detect_blank_pages,
+            media::pdf_extract_text,
+            media::pdf_export_text,
+            media::pdf_search,
+            media::pdf_search_cancel,
+            media::pdf_page_text_layer,
+            media::pdf_outline_get,
+            media::pdf_outline_set,
+            media::pdf_outline_add,
+            media::pdf_outline_rename,
+            media::pdf_outline_move,
+            media::pdf_outline_delete,
+            media::pdf_outline_generate,
+            media::pdf_get_metadata,
+            media::pdf_set_metadata,
+            media::pdf_security_info,
+            media::pdf_remove_encryption,
+            media::pdf_form_fields,
+            media::pdf_form_fill,
+            media::pdf_form_flatten,
+            media::pdf_annotations,
+            media::pdf_annotation_add,
+            media::pdf_annotation_delete,
+            media::pdf_annotations_flatten,
         ]);
 
     let app = builder
@@ -209,6 +253,9 @@ pub fn run() {
                 debug!("No supported files found in Opened event URLs");
             }
         }
+        tauri::RunEvent::Exit => {
+            media::shutdown_pdf_worker();
+        }
         _ => {}
     });
 }
@@ -4,7 +4,7 @@ use image::ImageEncoder;
 use log::warn;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::io::{Cursor, Read};
 use std::path::{Path, PathBuf};
@@ -13,9 +13,14 @@ use std::sync::{
     atomic::{AtomicU64, Ordering},
     mpsc,
 };
-// no timestamp usage currently
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{Emitter, Manager};
 
-#[derive(Serialize)]
+use crate::pdf_crypt::{
+    PdfCrypt, decrypt_document, encrypt_document, read_pdf_crypt, reencrypt_document,
+};
+
+#[derive(Serialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct MediaError {
     pub code: String,
@@ -23,7 +28,7 @@ pub struct MediaError {
 }
 
 impl MediaError {
-    fn new(code: &str, message: impl Into<String>) -> Self {
+    pub(crate) fn new(code: &str, message: impl Into<String>) -> Self {
         Self {
             code: code.to_string(),
             message: message.into(),
@@ -47,9 +52,16 @@ pub struct MediaDescriptor {
     pub name: String,
     pub size: Option<u64>,
     pub pages: Option<usize>,
-    pub width: Option<u32>,
+    pub width: Option<u32>, // 影像為像素；PDF 為第一頁寬度（pt，四捨五入）
     pub height: Option<u32>,
-    pub orientation: Option<u8>,
+    pub orientation: Option<u8>, // EXIF orientation（1-8）
+    #[serde(rename = "colorType")]
+    pub color_type: Option<String>,
+    pub encrypted: Option<bool>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    #[serde(rename = "pdfVersion")]
+    pub pdf_version: Option<String>,
 }
 
 // =====================
@@ -61,8 +73,8 @@ pub struct MediaDescriptor {
 pub struct CompressImageArgs {
     pub src_path: String,
     pub dest_path: String,
-    pub format: Option<String>,        // 'jpeg' | 'png' | 'webp' | 'preserve'
-    pub quality: Option<u8>,           // 1-100 (jpeg/webp only)
+    pub format: Option<String>, // 'jpeg' | 'png' | 'webp' | 'preserve'
+    pub quality: Option<u8>,    // 1-100 (jpeg/webp only)
     pub max_width: Option<u32>,
     pub max_height: Option<u32>,
 }
@@ -83,14 +95,20 @@ pub async fn compress_image(args: CompressImageArgs) -> Result<CompressImageResu
     tokio::task::spawn_blocking(move || -> Result<CompressImageResult, MediaError> {
         let src = Path::new(&args.src_path);
         if !src.exists() {
-            return Err(MediaError::new("not_found", format!("來源檔案不存在: {}", args.src_path)));
+            return Err(MediaError::new(
+                "not_found",
+                format!("來源檔案不存在: {}", args.src_path),
+            ));
         }
-        let before_meta = fs::metadata(src).map_err(|e| MediaError::new("io_error", format!("讀取來源檔案資訊失敗: {e}")))?;
+        let before_meta = fs::metadata(src)
+            .map_err(|e| MediaError::new("io_error", format!("讀取來源檔案資訊失敗: {e}")))?;
         let before_size = before_meta.len();
 
         // decode
-        let bytes = fs::read(src).map_err(|e| MediaError::new("io_error", format!("讀取來源檔案失敗: {e}")))?;
-        let mut img = image::load_from_memory(&bytes).map_err(|e| MediaError::new("decode_error", format!("解碼影像失敗: {e}")))?;
+        let bytes = fs::read(src)
+            .map_err(|e| MediaError::new("io_error", format!("讀取來源檔案失敗: {e}")))?;
+        let mut img = image::load_from_memory(&bytes)
+            .map_err(|e| MediaError::new("decode_error", format!("解碼影像失敗: {e}")))?;
         let (mut w, mut h) = img.dimensions();
 
         // downscale if needed
@@ -103,35 +121,54 @@ pub async fn compress_image(args: CompressImageArgs) -> Result<CompressImageResu
                     let new_w = ((w as f32) * scale).floor().max(1.0) as u32;
                     let new_h = ((h as f32) * scale).floor().max(1.0) as u32;
                     img = img.resize(new_w, new_h, image::imageops::FilterType::Triangle);
-                    w = new_w; h = new_h;
+                    w = new_w;
+                    h = new_h;
                 }
             }
-        } else if let Some(max_w) = args.max_width { // only width cap
+        } else if let Some(max_w) = args.max_width {
+            // only width cap
             if max_w > 0 && w > max_w {
                 let scale = (max_w as f32) / (w as f32);
                 let new_w = max_w;
                 let new_h = ((h as f32) * scale).floor().max(1.0) as u32;
                 img = img.resize(new_w, new_h, image::imageops::FilterType::Triangle);
-                w = new_w; h = new_h;
+                w = new_w;
+                h = new_h;
             }
-        } else if let Some(max_h) = args.max_height { // only height cap
+        } else if let Some(max_h) = args.max_height {
+            // only height cap
             if max_h > 0 && h > max_h {
                 let scale = (max_h as f32) / (h as f32);
                 let new_h = max_h;
                 let new_w = ((w as f32) * scale).floor().max(1.0) as u32;
                 img = img.resize(new_w, new_h, image::imageops::FilterType::Triangle);
-                w = new_w; h = new_h;
+                w = new_w;
+                h = new_h;
             }
         }
 
-        let req_fmt = args.format.unwrap_or_else(|| "preserve".to_string()).to_lowercase();
+        let req_fmt = args
+            .format
+            .unwrap_or_else(|| "preserve".to_string())
+            .to_lowercase();
         let chosen_fmt = if req_fmt == "preserve" {
-            let ext = src.extension().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
-            if ext == "jpg" || ext == "jpeg" { "jpeg".to_string() }
-            else if ext == "png" { "png".to_string() }
-            else if ext == "webp" { "webp".to_string() }
-            else { "jpeg".to_string() }
-        } else { req_fmt };
+            let ext = src
+                .extension()
+                .and_then(|s| s.to_str())
+                .unwrap_or("")
+                .to_lowercase();
+            if ext == "jpg" || ext == "jpeg" {
+                "jpeg".to_string()
+            } else if ext == "png" {
+                "png".to_string()
+            } else if ext == "webp" {
+                "webp".to_string()
+            } else {
+                "jpeg".to_string()
+            }
+        } else {
+            req_fmt
+        };
 
         let mut out: Vec<u8> = Vec::new();
         match chosen_fmt.as_str() {
@@ -140,11 +177,13 @@ pub async fn compress_image(args: CompressImageArgs) -> Result<CompressImageResu
                 // flatten alpha onto white background if exists
                 let rgba = img.to_rgba8();
                 let (iw, ih) = (rgba.width(), rgba.height());
-                let mut bg = image::RgbaImage::from_pixel(iw, ih, image::Rgba([255, 255, 255, 255]));
+                let mut bg =
+                    image::RgbaImage::from_pixel(iw, ih, image::Rgba([255, 255, 255, 255]));
                 image::imageops::overlay(&mut bg, &rgba, 0, 0);
                 let rgb = image::DynamicImage::ImageRgba8(bg).to_rgb8();
                 let q = args.quality.unwrap_or(82).clamp(1, 100);
-                let enc = image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut out), q);
+                let enc =
+                    image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut out), q);
                 enc.write_image(&rgb, rgb.width(), rgb.height(), ColorType::Rgb8.into())
                     .map_err(|e| MediaError::new("encode_error", format!("JPEG 編碼失敗: {e}")))?;
             }
@@ -167,13 +206,18 @@ pub async fn compress_image(args: CompressImageArgs) -> Result<CompressImageResu
                 out = encoded.to_vec();
             }
             other => {
-                return Err(MediaError::new("invalid_input", format!("不支援的輸出格式: {other}")));
+                return Err(MediaError::new(
+                    "invalid_input",
+                    format!("不支援的輸出格式: {other}"),
+                ));
             }
         }
 
         // write
-        fs::write(&args.dest_path, &out).map_err(|e| MediaError::new("io_error", format!("寫入輸出檔失敗: {e}")))?;
-        let after_meta = fs::metadata(&args.dest_path).map_err(|e| MediaError::new("io_error", format!("讀取輸出檔資訊失敗: {e}")))?;
+        fs::write(&args.dest_path, &out)
+            .map_err(|e| MediaError::new("io_error", format!("寫入輸出檔失敗: {e}")))?;
+        let after_meta = fs::metadata(&args.dest_path)
+            .map_err(|e| MediaError::new("io_error", format!("讀取輸出檔資訊失敗: {e}")))?;
         Ok(CompressImageResult {
             path: args.dest_path,
             before_size,
@@ -182,7 +226,9 @@ pub async fn compress_image(args: CompressImageArgs) -> Result<CompressImageResu
             height: h,
             format: chosen_fmt,
         })
-    }).await.map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))?
+    })
+    .await
+    .map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))?
 }
 
 #[derive(Deserialize)]
@@ -190,6 +236,7 @@ pub async fn compress_image(args: CompressImageArgs) -> Result<CompressImageResu
 pub struct CompressPdfLosslessArgs {
     pub src_path: String,
     pub dest_path: String,
+    pub encryption: Option<PdfEncryptArgs>, // 輸出時套用 AES-256 加密
 }
 
 #[derive(Serialize)]
@@ -201,27 +248,63 @@ pub struct CompressPdfLosslessResult {
 }
 
 #[tauri::command]
-pub async fn compress_pdf_lossless(args: CompressPdfLosslessArgs) -> Result<CompressPdfLosslessResult, MediaError> {
+pub async fn compress_pdf_lossless(
+    args: CompressPdfLosslessArgs,
+) -> Result<CompressPdfLosslessResult, MediaError> {
     // 純 Rust v1 佔位：暫以安全複製檔案實作，之後將改為使用 pdf/pdf-writer 重寫結構與重壓 streams。
     tokio::task::spawn_blocking(move || -> Result<CompressPdfLosslessResult, MediaError> {
         let src = Path::new(&args.src_path);
         if !src.exists() {
-            return Err(MediaError::new("not_found", format!("來源檔案不存在: {}", args.src_path)));
+            return Err(MediaError::new(
+                "not_found",
+                format!("來源檔案不存在: {}", args.src_path),
+            ));
         }
-        let before_meta = fs::metadata(src).map_err(|e| MediaError::new("io_error", format!("讀取來源檔案資訊失敗: {e}")))?;
+        let before_meta = fs::metadata(src)
+            .map_err(|e| MediaError::new("io_error", format!("讀取來源檔案資訊失敗: {e}")))?;
         let before_size = before_meta.len();
 
+        if let Some(enc) = &args.encryption {
+            let mut doc = lopdf::Document::load(src)
+                .map_err(|e| MediaError::new("parse_error", format!("讀取 PDF 失敗: {e}")))?;
+            encrypt_document(&mut doc, enc)?;
+            // 目標可能與來源相同，先寫入暫存檔再替換
+            write_file_atomic(Path::new(&args.dest_path), false, |file| {
+                doc.save_to(file)
+                    .map(|_| ())
+                    .map_err(|e| MediaError::new("io_error", format!("寫入 PDF 失敗: {e}")))
+            })?;
+            let after_meta = fs::metadata(&args.dest_path)
+                .map_err(|e| MediaError::new("io_error", format!("讀取輸出檔資訊失敗: {e}")))?;
+            return Ok(CompressPdfLosslessResult {
+                path: args.dest_path,
+                before_size,
+                after_size: after_meta.len(),
+            });
+        }
+
         // 若目標與來源相同，避免覆寫：回傳原檔資訊
         if args.dest_path == args.src_path {
-            return Ok(CompressPdfLosslessResult { path: args.dest_path, before_size, after_size: before_size });
+            return Ok(CompressPdfLosslessResult {
+                path: args.dest_path,
+                before_size,
+                after_size: before_size,
+            });
         }
 
         // 直接複製（no-op 最小可行版本）
         fs::copy(&args.src_path, &args.dest_path)
             .map_err(|e| MediaError::new("io_error", format!("寫入輸出檔失敗: {e}")))?;
-        let after_meta = fs::metadata(&args.dest_path).map_err(|e| MediaError::new("io_error", format!("讀取輸出檔資訊失敗: {e}")))?;
-        Ok(CompressPdfLosslessResult { path: args.dest_path, before_size, after_size: after_meta.len() })
-    }).await.map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))?
+        let after_meta = fs::metadata(&args.dest_path)
+            .map_err(|e| MediaError::new("io_error", format!("讀取輸出檔資訊失敗: {e}")))?;
+        Ok(CompressPdfLosslessResult {
+            path: args.dest_path,
+            before_size,
+            after_size: after_meta.len(),
+        })
+    })
+    .await
+    .map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))?
 }
 
 // ------- v1 Smart compression (JPEG/Flate + basic structure optimize) -------
@@ -232,12 +315,13 @@ pub struct CompressPdfSmartArgs {
     pub src_path: String,
     pub dest_path: String,
     pub target_effective_dpi: Option<f32>,
-    pub downsample_rule: Option<String>,      // 'always' | 'whenAbove' （v1 暫不套用）
+    pub downsample_rule: Option<String>, // 'always' | 'whenAbove' （v1 暫不套用）
     pub threshold_effective_dpi: Option<f32>, // v1 暫不套用
-    pub format: Option<String>,               // 'jpeg' | 'keep'
-    pub quality: Option<u8>,                  // 1-100（僅 JPEG 生效）
+    pub format: Option<String>,          // 'jpeg' | 'keep'
+    pub quality: Option<u8>,             // 1-100（僅 JPEG 生效）
     pub lossless_optimize: Option<bool>,
     pub remove_metadata: Option<bool>,
+    pub encryption: Option<PdfEncryptArgs>, // 輸出時套用 AES-256 加密
 }
 
 #[derive(Serialize)]
@@ -256,7 +340,7 @@ enum PdfColorSpaceKind {
     Rgb,
 }
 
-fn resolve_object(doc: &lopdf::Document, obj: &lopdf::Object) -> Option<lopdf::Object> {
+pub(crate) fn resolve_object(doc: &lopdf::Document, obj: &lopdf::Object) -> Option<lopdf::Object> {
     let mut current = obj.clone();
     for _ in 0..8 {
         if let lopdf::Object::Reference(id) = current {
@@ -268,7 +352,10 @@ fn resolve_object(doc: &lopdf::Document, obj: &lopdf::Object) -> Option<lopdf::O
     None
 }
 
-fn resolve_dict(doc: &lopdf::Document, obj: &lopdf::Object) -> Option<lopdf::Dictionary> {
+pub(crate) fn resolve_dict(
+    doc: &lopdf::Document,
+    obj: &lopdf::Object,
+) -> Option<lopdf::Dictionary> {
     match resolve_object(doc, obj)? {
         lopdf::Object::Dictionary(dict) => Some(dict),
         lopdf::Object::Stream(stream) => Some(stream.dict),
@@ -285,7 +372,11 @@ fn resolve_dict(doc: &lopdf::Document, obj: &lopdf::Object) -> Option<lopdf::Dic
     }
 }
 
-fn dict_get_i64(doc: &lopdf::Document, dict: &lopdf::Dictionary, key: &[u8]) -> Option<i64> {
+pub(crate) fn dict_get_i64(
+    doc: &lopdf::Document,
+    dict: &lopdf::Dictionary,
+    key: &[u8],
+) -> Option<i64> {
     let raw = dict.get(key).ok()?;
     match resolve_object(doc, raw)? {
         lopdf::Object::Integer(v) => Some(v),
@@ -298,7 +389,10 @@ fn dict_get_usize(doc: &lopdf::Document, dict: &lopdf::Dictionary, key: &[u8]) -
     dict_get_i64(doc, dict, key).and_then(|v| if v > 0 { usize::try_from(v).ok() } else { None })
 }
 
-fn infer_color_space_kind(doc: &lopdf::Document, color_space: Option<lopdf::Object>) -> Option<PdfColorSpaceKind> {
+fn infer_color_space_kind(
+    doc: &lopdf::Document,
+    color_space: Option<lopdf::Object>,
+) -> Option<PdfColorSpaceKind> {
     match color_space {
         None => Some(PdfColorSpaceKind::Gray), // default fallback per spec
         Some(obj) => match obj {
@@ -333,8 +427,12 @@ fn infer_color_space_kind(doc: &lopdf::Document, color_space: Option<lopdf::Obje
                             None
                         }
                     }
-                    lopdf::Object::Name(name) if name.as_slice() == b"CalRGB" => Some(PdfColorSpaceKind::Rgb),
-                    lopdf::Object::Name(name) if name.as_slice() == b"CalGray" => Some(PdfColorSpaceKind::Gray),
+                    lopdf::Object::Name(name) if name.as_slice() == b"CalRGB" => {
+                        Some(PdfColorSpaceKind::Rgb)
+                    }
+                    lopdf::Object::Name(name) if name.as_slice() == b"CalGray" => {
+                        Some(PdfColorSpaceKind::Gray)
+                    }
                     _ => None,
                 }
             }
@@ -614,13 +712,18 @@ fn decode_flate_image_stream(
 }
 
 #[tauri::command]
-pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPdfSmartResult, MediaError> {
+pub async fn compress_pdf_smart(
+    args: CompressPdfSmartArgs,
+) -> Result<CompressPdfSmartResult, MediaError> {
     tokio::task::spawn_blocking(move || -> Result<CompressPdfSmartResult, MediaError> {
         use lopdf::{Document, Object};
 
         let src = Path::new(&args.src_path);
         if !src.exists() {
-            return Err(MediaError::new("not_found", format!("來源檔案不存在: {}", args.src_path)));
+            return Err(MediaError::new(
+                "not_found",
+                format!("來源檔案不存在: {}", args.src_path),
+            ));
         }
 
         let before_meta = fs::metadata(src)
@@ -646,7 +749,10 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
         let pages = pages_map.len();
         let mut changed_images: usize = 0;
 
-        let requested_fmt = args.format.unwrap_or_else(|| "jpeg".to_string()).to_lowercase();
+        let requested_fmt = args
+            .format
+            .unwrap_or_else(|| "jpeg".to_string())
+            .to_lowercase();
         let jpeg_quality = args.quality.unwrap_or(82).clamp(1, 100);
 
         // 逐頁處理 XObject 影像（僅處理 Subtype=Image 且 ColorSpace=DeviceRGB/DeviceGray）
@@ -665,7 +771,9 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
             let resources_dict = match &resources_obj {
                 Object::Reference(id) => doc
                     .get_object(*id)
-                    .map_err(|e| MediaError::new("parse_error", format!("讀取 Resources 失敗: {e}")))?
+                    .map_err(|e| {
+                        MediaError::new("parse_error", format!("讀取 Resources 失敗: {e}"))
+                    })?
                     .as_dict()
                     .map_err(|_| MediaError::new("parse_error", "Resources 非字典"))?
                     .clone(),
@@ -697,11 +805,15 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
                 }
                 for op in content.operations {
                     match op.operator.as_str() {
-                        "q" => { stack.push(cur); }
-                        "Q" => { cur = stack.pop().unwrap_or([1.0,0.0,0.0,1.0,0.0,0.0]); }
+                        "q" => {
+                            stack.push(cur);
+                        }
+                        "Q" => {
+                            cur = stack.pop().unwrap_or([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+                        }
                         "cm" => {
                             if op.operands.len() >= 6 {
-                                let mut nums = [0f32;6];
+                                let mut nums = [0f32; 6];
                                 for i in 0..6 {
                                     nums[i] = match &op.operands[i] {
                                         Object::Integer(v) => *v as f32,
@@ -716,11 +828,15 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
                         "Do" => {
                             if let Some(name_obj) = op.operands.get(0) {
                                 if let Ok(n) = name_obj.as_name() {
-                                    let w_pt = (cur[0]*cur[0] + cur[2]*cur[2]).sqrt();
-                                    let h_pt = (cur[1]*cur[1] + cur[3]*cur[3]).sqrt();
+                                    let w_pt = (cur[0] * cur[0] + cur[2] * cur[2]).sqrt();
+                                    let h_pt = (cur[1] * cur[1] + cur[3] * cur[3]).sqrt();
                                     let entry = name_usage.entry(n.to_vec()).or_insert((0.0, 0.0));
-                                    if w_pt > entry.0 { entry.0 = w_pt; }
-                                    if h_pt > entry.1 { entry.1 = h_pt; }
+                                    if w_pt > entry.0 {
+                                        entry.0 = w_pt;
+                                    }
+                                    if h_pt > entry.1 {
+                                        entry.1 = h_pt;
+                                    }
                                 }
                             }
                         }
@@ -730,7 +846,10 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
             }
 
             // XObject dict
-            let xobj_dict_obj = match resources_dict.get(b"XObject") { Ok(o) => o, Err(_) => continue };
+            let xobj_dict_obj = match resources_dict.get(b"XObject") {
+                Ok(o) => o,
+                Err(_) => continue,
+            };
             let xobj_dict = match xobj_dict_obj {
                 Object::Reference(id) => doc
                     .get_object(*id)
@@ -743,7 +862,11 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
             };
 
             for (name, maybe_ref) in xobj_dict.iter() {
-                let obj_id = if let Object::Reference(id) = maybe_ref { *id } else { continue };
+                let obj_id = if let Object::Reference(id) = maybe_ref {
+                    *id
+                } else {
+                    continue;
+                };
                 // 只處理影像 XObject
                 let is_image = {
                     if let Ok(obj) = doc.get_object(obj_id) {
@@ -752,19 +875,25 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
                                 Ok(Object::Name(n)) if n.as_slice() == b"Image" => true,
                                 _ => false,
                             }
-                        } else { false }
-                    } else { false }
+                        } else {
+                            false
+                        }
+                    } else {
+                        false
+                    }
                 };
-                if !is_image { continue; }
+                if !is_image {
+                    continue;
+                }
 
                 if requested_fmt != "jpeg" {
                     continue;
                 }
 
                 let (mut dyn_img, img_w_px, img_h_px) = {
-                    let stream_obj = doc
-                        .get_object(obj_id)
-                        .map_err(|e| MediaError::new("parse_error", format!("讀取影像物件失敗: {e}")))?;
+                    let stream_obj = doc.get_object(obj_id).map_err(|e| {
+                        MediaError::new("parse_error", format!("讀取影像物件失敗: {e}"))
+                    })?;
                     let stream_ro = match stream_obj.as_stream() {
                         Ok(s) => s,
                         Err(_) => continue,
@@ -772,7 +901,9 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
 
                     let filters = stream_ro.filters().unwrap_or_default();
                     let has_dct = filters.iter().any(|f| f == "DCTDecode");
-                    let flate_only = !has_dct && !filters.is_empty() && filters.iter().all(|f| f == "FlateDecode");
+                    let flate_only = !has_dct
+                        && !filters.is_empty()
+                        && filters.iter().all(|f| f == "FlateDecode");
 
                     let mut width = stream_ro
                         .dict
@@ -846,7 +977,8 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
                         };
                         if need {
                             use image::imageops::FilterType;
-                            let target_w = ((disp_w_pt / 72.0) * tgt_dpi as f32).round().max(1.0) as u32;
+                            let target_w =
+                                ((disp_w_pt / 72.0) * tgt_dpi as f32).round().max(1.0) as u32;
                             let target_h = if disp_h_pt > 0.0 {
                                 ((disp_h_pt / 72.0) * tgt_dpi as f32).round().max(1.0) as u32
                             } else {
@@ -865,8 +997,10 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
                 use image::ColorType;
                 let rgb = dyn_img.to_rgb8();
                 let mut out: Vec<u8> = Vec::new();
-                let enc =
-                    image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut out), jpeg_quality);
+                let enc = image::codecs::jpeg::JpegEncoder::new_with_quality(
+                    Cursor::new(&mut out),
+                    jpeg_quality,
+                );
                 if enc
                     .write_image(&rgb, rgb.width(), rgb.height(), ColorType::Rgb8.into())
                     .is_err()
@@ -874,18 +1008,22 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
                     continue;
                 }
 
-                let stream_ref = doc
-                    .get_object_mut(obj_id)
-                    .map_err(|e| MediaError::new("parse_error", format!("讀取影像物件失敗: {e}")))?;
+                let stream_ref = doc.get_object_mut(obj_id).map_err(|e| {
+                    MediaError::new("parse_error", format!("讀取影像物件失敗: {e}"))
+                })?;
                 let stream = match stream_ref.as_stream_mut() {
                     Ok(s) => s,
                     Err(_) => continue,
                 };
 
                 stream.set_content(out);
-                stream.dict.set(b"Filter", Object::Name(b"DCTDecode".to_vec()));
+                stream
+                    .dict
+                    .set(b"Filter", Object::Name(b"DCTDecode".to_vec()));
                 let _ = stream.dict.remove(b"DecodeParms");
-                stream.dict.set(b"ColorSpace", Object::Name(b"DeviceRGB".to_vec()));
+                stream
+                    .dict
+                    .set(b"ColorSpace", Object::Name(b"DeviceRGB".to_vec()));
                 stream.dict.set(b"BitsPerComponent", 8);
                 stream.dict.set(b"Width", rgb.width() as i64);
                 stream.dict.set(b"Height", rgb.height() as i64);
@@ -898,6 +1036,11 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
             doc.compress();
         }
 
+        // 加密需在壓縮之後進行
+        if let Some(enc) = &args.encryption {
+            encrypt_document(&mut doc, enc)?;
+        }
+
         // 保存
         doc.save(&args.dest_path)
             .map_err(|e| MediaError::new("io_error", format!("寫入 PDF 失敗: {e}")))?;
@@ -912,7 +1055,9 @@ pub async fn compress_pdf_smart(args: CompressPdfSmartArgs) -> Result<CompressPd
             pages,
             changed_images,
         })
-    }).await.map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))?
+    })
+    .await
+    .map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))?
 }
 
 // Removed PdfInfo and pdf_info(): use pdf_open() result and pdf_page_size() instead.
@@ -990,6 +1135,10 @@ fn resolve_pdfium_dir() -> Option<PathBuf> {
         candidates.push(cwd.join("src-tauri/resources"));
     }
 
+    // 測試時 current_exe 位於 target/debug/deps
+    #[cfg(test)]
+    candidates.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources"));
+
     for root in candidates {
         let base = root.join("pdfium");
         if base.exists() {
@@ -1039,16 +1188,70 @@ fn get_pdfium() -> Result<pdfium_render::prelude::Pdfium, MediaError> {
 // 單執行緒 Worker：長駐 Pdfium 與 PdfDocument（避免跨執行緒 Send/Sync 問題）
 static WORKER_TX: Lazy<Mutex<Option<mpsc::Sender<PdfRequest>>>> = Lazy::new(|| Mutex::new(None));
 static NEXT_DOC_ID: AtomicU64 = AtomicU64::new(1);
+// 搜尋在 worker 內執行，取消需繞過 worker 佇列：docId → 最小有效 gen
+static SEARCH_MIN_GEN: Lazy<Mutex<HashMap<u64, u64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+enum AnnotationEdit {
+    Add(Box<PdfAddAnnotationArgs>),
+    Delete(Vec<PdfAnnotationRef>),
+    Flatten,
+}
+
+enum FormEdit {
+    Fill(HashMap<String, PdfFormValue>),
+    Flatten,
+}
+
+// 書籤編輯操作；路徑為各層子項目索引（0-based）
+enum OutlineEdit {
+    Set(Vec<PdfOutlineItem>),
+    Add {
+        parent_path: Vec<u32>,
+        index: Option<u32>,
+        item: PdfOutlineItem,
+    },
+    Rename {
+        path: Vec<u32>,
+        title: String,
+    },
+    Move {
+        path: Vec<u32>,
+        new_parent_path: Vec<u32>,
+        index: Option<u32>,
+    },
+    Delete {
+        path: Vec<u32>,
+    },
+}
 
 enum PdfRequest {
+    // 正常結束：移除本次執行的復原檔後停止 worker
+    Shutdown {
+        reply: mpsc::Sender<()>,
+    },
+    Security {
+        doc_id: u64,
+        reply: mpsc::Sender<Result<PdfSecurityInfo, MediaError>>,
+    },
     Open {
         path: String,
+        password: Option<String>,
         reply: mpsc::Sender<Result<PdfOpenResult, MediaError>>,
     },
     Close {
         doc_id: u64,
+        force: bool,
         reply: mpsc::Sender<Result<(), MediaError>>,
     },
+    DocState {
+        doc_id: Option<u64>,
+        reply: mpsc::Sender<Result<Vec<PdfDocState>, MediaError>>,
+    },
+    RecoveryRestore {
+        id: String,
+        password: Option<String>,
+        reply: mpsc::Sender<Result<PdfOpenResult, MediaError>>,
+    },
     Render {
         args: PdfRenderArgs,
         reply: mpsc::Sender<Result<PageRender, MediaError>>,
@@ -1098,6 +1301,63 @@ enum PdfRequest {
         rotate_deg: u16,
         reply: mpsc::Sender<Result<(), MediaError>>,
     },
+    RotatePages {
+        args: PdfRotatePagesArgs,
+        reply: mpsc::Sender<Result<Vec<u16>, MediaError>>,
+    },
+    Metadata {
+        doc_id: u64,
+        set: Option<PdfSetMetadataArgs>, // None 為讀取
+        reply: mpsc::Sender<Result<PdfDocMetadata, MediaError>>,
+    },
+    Annotations {
+        doc_id: u64,
+        page_index: Option<u32>,      // 讀取時限定頁面
+        edit: Option<AnnotationEdit>, // None 為讀取
+        reply: mpsc::Sender<Result<Vec<PdfAnnotationInfo>, MediaError>>,
+    },
+    Form {
+        doc_id: u64,
+        edit: Option<FormEdit>,
+        reply: mpsc::Sender<Result<Vec<PdfFormFieldInfo>, MediaError>>,
+    },
+    Outline {
+        doc_id: u64,
+        edit: Option<OutlineEdit>, // None 為讀取
+        reply: mpsc::Sender<Result<Vec<PdfOutlineItem>, MediaError>>,
+    },
+    TextLayer {
+        args: PdfTextLayerArgs,
+        reply: mpsc::Sender<Result<PdfTextLayer, MediaError>>,
+    },
+    Search {
+        args: PdfSearchArgs,
+        reply: mpsc::Sender<Result<PdfSearchSummary, MediaError>>,
+    },
+    ExtractText {
+        args: PdfExtractTextArgs,
+        reply: mpsc::Sender<Result<Vec<PdfPageTextResult>, MediaError>>,
+    },
+    DetectBlankPages {
+        args: PdfDetectBlankArgs,
+        reply: mpsc::Sender<Result<PdfBlankPagesResult, MediaError>>,
+    },
+    Deskew {
+        args: PdfDeskewArgs,
+        reply: mpsc::Sender<Result<Vec<PdfDeskewResult>, MediaError>>,
+    },
+    DeskewPreview {
+        args: PdfDeskewPreviewArgs,
+        reply: mpsc::Sender<Result<PageRender, MediaError>>,
+    },
+    AutoOrient {
+        args: PdfAutoOrientArgs,
+        reply: mpsc::Sender<Result<Vec<PdfOrientationGuess>, MediaError>>,
+    },
+    PageRotations {
+        doc_id: u64,
+        reply: mpsc::Sender<Result<Vec<u16>, MediaError>>,
+    },
     RotatePageRelative {
         doc_id: u64,
         index: u32,
@@ -1115,636 +1375,8687 @@ enum PdfRequest {
         doc_id: u64,
         dest_path: Option<String>,
         overwrite: Option<bool>,
-        reply: mpsc::Sender<Result<(String, usize), MediaError>>,
+        backup: bool,
+        encryption: Option<PdfEncryptArgs>,
+        flatten_forms: bool,
+        reply: mpsc::Sender<Result<PdfSaveResult, MediaError>>,
+    },
+    RemoveEncryption {
+        doc_id: u64,
+        owner_password: Option<String>,
+        reply: mpsc::Sender<Result<PdfSecurityInfo, MediaError>>,
     },
     ImageToPdf {
         src_path: String,
         dest_path: String,
         reply: mpsc::Sender<Result<String, MediaError>>,
     },
+    DuplicatePages {
+        doc_id: u64,
+        range: String,
+        dest_index: Option<u32>,
+        copies: u32,
+        reply: mpsc::Sender<Result<usize, MediaError>>,
+    },
+    ReversePages {
+        doc_id: u64,
+        range: Option<String>,
+        reply: mpsc::Sender<Result<usize, MediaError>>,
+    },
+    InterleavePages {
+        doc_id: u64,
+        other_doc_id: Option<u64>,
+        reverse_second: bool,
+        reply: mpsc::Sender<Result<usize, MediaError>>,
+    },
+    SetPageBoxes {
+        args: PdfSetPageBoxesArgs,
+        reply: mpsc::Sender<Result<usize, MediaError>>,
+    },
+    NormalizePageSize {
+        args: PdfNormalizePageSizeArgs,
+        reply: mpsc::Sender<Result<usize, MediaError>>,
+    },
+    Impose {
+        args: PdfImposeArgs,
+        reply: mpsc::Sender<Result<PdfOpenResult, MediaError>>,
+    },
+    Watermark {
+        args: PdfWatermarkArgs,
+        reply: mpsc::Sender<Result<usize, MediaError>>,
+    },
+    HeaderFooter {
+        args: PdfHeaderFooterArgs,
+        reply: mpsc::Sender<Result<PdfHeaderFooterResult, MediaError>>,
+    },
+    InsertFromFile {
+        doc_id: u64,
+        src_paths: Vec<String>,
+        src_password: Option<String>, // 來源 PDF 的開啟密碼
+        range: Option<String>,
+        index: u32,
+        reply: mpsc::Sender<Result<usize, MediaError>>,
+    },
 }
 
-pub fn init_pdf_worker() {
-    let (tx, rx) = mpsc::channel::<PdfRequest>();
-    *WORKER_TX.lock().unwrap() = Some(tx);
-    std::thread::spawn(move || {
-        use pdfium_render::prelude::*;
-        let pdfium = match get_pdfium() {
-            Ok(p) => p,
-            Err(e) => {
-                // 無法載入 PDFium，工作執行緒退場
-                eprintln!("PDF worker init failed: {}", e.message);
-                return;
+impl PdfRequest {
+    // 不變更頁面結構也不序列化文件的請求，暫存書籤可延後寫入
+    fn keeps_outline_pending(&self) -> bool {
+        matches!(
+            self,
+            PdfRequest::Outline { .. }
+                | PdfRequest::Security { .. }
+                | PdfRequest::Open { .. }
+                | PdfRequest::Close { .. }
+                | PdfRequest::DocState { .. }
+                | PdfRequest::Render { .. }
+                | PdfRequest::Size { .. }
+                | PdfRequest::Cancel { .. }
+                | PdfRequest::ExportImage { .. }
+                | PdfRequest::TextLayer { .. }
+                | PdfRequest::Search { .. }
+                | PdfRequest::ExtractText { .. }
+                | PdfRequest::PageRotations { .. }
+                | PdfRequest::Shutdown { .. }
+        )
+    }
+}
+
+// 解析 1-based 頁碼範圍字串（如 "1,3,5-7"、"8-5"、"3-"、"odd"、"even"），回傳 0-based 索引。
+// 保留輸入順序且允許重複；空字串或 "all" 代表全部頁面。
+fn parse_page_range(spec: &str, page_count: u32) -> Result<Vec<u32>, MediaError> {
+    let spec = spec.trim();
+    if spec.is_empty() || spec.eq_ignore_ascii_case("all") {
+        return Ok((0..page_count).collect());
+    }
+    let parse_num = |t: &str| -> Result<u32, MediaError> {
+        let n: u32 = t
+            .trim()
+            .parse()
+            .map_err(|_| MediaError::new("invalid_input", format!("無效的頁碼: {}", t.trim())))?;
+        if n == 0 || n > page_count {
+            return Err(MediaError::new(
+                "invalid_input",
+                format!("頁碼超出範圍: {} (1-{})", n, page_count),
+            ));
+        }
+        Ok(n - 1)
+    };
+    let mut out: Vec<u32> = Vec::new();
+    for part in spec.split(',') {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        if part.eq_ignore_ascii_case("odd") {
+            out.extend((0..page_count).step_by(2));
+        } else if part.eq_ignore_ascii_case("even") {
+            out.extend((1..page_count).step_by(2));
+        } else if let Some((a, b)) = part.split_once('-') {
+            let start = parse_num(a)?;
+            let end = if b.trim().is_empty() {
+                page_count.saturating_sub(1)
+            } else {
+                parse_num(b)?
+            };
+            if start <= end {
+                out.extend(start..=end);
+            } else {
+                out.extend((end..=start).rev());
+            }
+        } else {
+            out.push(parse_num(part)?);
+        }
+    }
+    if out.is_empty() {
+        return Err(MediaError::new("invalid_input", "頁碼範圍為空"));
+    }
+    Ok(out)
+}
+
+// 依指定順序（0-based，可重複）自來源複製頁面為新文件
+fn rebuild_document<'a>(
+    pdfium: &'a pdfium_render::prelude::Pdfium,
+    src: &pdfium_render::prelude::PdfDocument,
+    order: &[u32],
+) -> Result<pdfium_render::prelude::PdfDocument<'a>, MediaError> {
+    let new_doc = pdfium
+        .create_new_pdf()
+        .map_err(|e| MediaError::new("io_error", format!("建立新 PDF 失敗: {e}")))?;
+    import_pages(&new_doc, src, order, 0)?;
+    Ok(new_doc)
+}
+
+// 刪除指定頁（0-based）並重建文件，一併回傳待寫入的書籤；至少需保留一頁
+fn delete_document_pages<'a>(
+    pdfium: &'a pdfium_render::prelude::Pdfium,
+    old: &pdfium_render::prelude::PdfDocument,
+    crypt: Option<&PdfCrypt>,
+    password: Option<&str>,
+    mut indices: Vec<u32>,
+) -> Result<
+    (
+        pdfium_render::prelude::PdfDocument<'a>,
+        Option<Vec<PdfOutlineItem>>,
+    ),
+    MediaError,
+> {
+    let page_count = total_pages(old);
+    if page_count == 0 {
+        return Err(MediaError::new("invalid_input", "文件沒有任何頁面"));
+    }
+    if indices.is_empty() {
+        return Err(MediaError::new("invalid_input", "缺少要刪除的頁索引"));
+    }
+    indices.sort_unstable();
+    indices.dedup();
+    if let Some(max) = indices.last() {
+        if *max >= page_count {
+            return Err(MediaError::new(
+                "invalid_input",
+                format!("頁索引超出範圍: {} >= {}", max, page_count),
+            ));
+        }
+    }
+    let mut keep: Vec<u32> = (0..page_count).collect();
+    let del: HashSet<u32> = indices.into_iter().collect();
+    keep.retain(|i| !del.contains(i));
+    if keep.is_empty() {
+        return Err(MediaError::new(
+            "invalid_input",
+            "無法刪除所有頁面，至少需保留一頁",
+        ));
+    }
+    let new_doc = restore_encryption(
+        pdfium,
+        crypt,
+        password,
+        rebuild_document(pdfium, old, &keep)?,
+    )?;
+    let outline = carry_outline(&[(old, password, 0)], &keep)?;
+    Ok((new_doc, outline))
+}
+
+// 將圖片檔加入為文件最後一頁，圖片鋪滿整頁
+fn append_image_page(
+    doc: &mut pdfium_render::prelude::PdfDocument,
+    p: &Path,
+) -> Result<(), MediaError> {
+    use pdfium_render::prelude::*;
+
+    if !p.exists() {
+        return Err(MediaError::new(
+            "not_found",
+            format!("圖片檔案不存在: {}", p.display()),
+        ));
+    }
+
+    // 讀取圖片以取得尺寸
+    let bytes =
+        fs::read(p).map_err(|e| MediaError::new("io_error", format!("讀取圖片失敗: {e}")))?;
+    let dyn_img = image::load_from_memory(&bytes)
+        .map_err(|e| MediaError::new("decode_error", format!("解碼圖片失敗: {e}")))?;
+    let (w_px, h_px) = GenericImageView::dimensions(&dyn_img);
+
+    // 經驗法則：以 72 DPI 對應 1 px = 1 pt，避免不必要縮放
+    let width_pt = w_px as f32;
+    let height_pt = h_px as f32;
+
+    let size = PdfPagePaperSize::Custom(PdfPoints::new(width_pt), PdfPoints::new(height_pt));
+    let mut page = doc
+        .pages_mut()
+        .create_page_at_end(size)
+        .map_err(|e| MediaError::new("io_error", format!("建立頁面失敗: {e}")))?;
+
+    // 以 0,0 為左下角，指定輸出寬高為整頁；create_image_object 會自動加入頁面物件集合
+    page.objects_mut()
+        .create_image_object(
+            PdfPoints::new(0.0),
+            PdfPoints::new(0.0),
+            &dyn_img,
+            Some(PdfPoints::new(width_pt)),
+            Some(PdfPoints::new(height_pt)),
+        )
+        .map_err(|e| MediaError::new("unsupported", format!("建立影像物件失敗: {e}")))?;
+    Ok(())
+}
+
+// PdfPageRenderRotation ↔ 角度（0/90/180/270）
+fn degrees_to_rotation(deg: u16) -> Option<pdfium_render::prelude::PdfPageRenderRotation> {
+    use pdfium_render::prelude::PdfPageRenderRotation;
+    match deg {
+        0 => Some(PdfPageRenderRotation::None),
+        90 => Some(PdfPageRenderRotation::Degrees90),
+        180 => Some(PdfPageRenderRotation::Degrees180),
+        270 => Some(PdfPageRenderRotation::Degrees270),
+        _ => None,
+    }
+}
+
+fn rotation_to_degrees(rot: pdfium_render::prelude::PdfPageRenderRotation) -> u16 {
+    use pdfium_render::prelude::PdfPageRenderRotation;
+    match rot {
+        PdfPageRenderRotation::None => 0,
+        PdfPageRenderRotation::Degrees90 => 90,
+        PdfPageRenderRotation::Degrees180 => 180,
+        PdfPageRenderRotation::Degrees270 => 270,
+    }
+}
+
+fn pdf_box_from_rect(rect: &pdfium_render::prelude::PdfRect) -> PdfBox {
+    PdfBox {
+        left: rect.left().value,
+        bottom: rect.bottom().value,
+        right: rect.right().value,
+        top: rect.top().value,
+    }
+}
+
+fn rect_from_pdf_box(b: &PdfBox) -> Result<pdfium_render::prelude::PdfRect, MediaError> {
+    if !(b.right > b.left && b.top > b.bottom) {
+        return Err(MediaError::new(
+            "invalid_input",
+            format!(
+                "無效的頁面框: [{}, {}, {}, {}]",
+                b.left, b.bottom, b.right, b.top
+            ),
+        ));
+    }
+    Ok(pdfium_render::prelude::PdfRect::new_from_values(
+        b.bottom, b.left, b.top, b.right,
+    ))
+}
+
+// 以低解析度渲染頁面並找出非白色像素的外框，換算回頁面座標（pt，未旋轉的使用者空間）。
+// 空白頁回傳 None。
+fn detect_content_box(
+    page: &pdfium_render::prelude::PdfPage,
+    dpi: f32,
+    tolerance: u8,
+) -> Result<Option<PdfBox>, MediaError> {
+    use pdfium_render::prelude::*;
+
+    let visible = page
+        .boundaries()
+        .crop()
+        .or_else(|_| page.boundaries().media())
+        .map_err(|e| MediaError::new("parse_error", format!("讀取頁面框失敗: {e}")))?
+        .bounds;
+    let rotation = page.rotation().map(rotation_to_degrees).unwrap_or(0);
+
+    let width_px = ((page.width().value * dpi / 72.0).ceil() as i32).max(1);
+    let cfg = PdfRenderConfig::new().set_target_width(width_px);
+    let bitmap = page
+        .render_with_config(&cfg)
+        .map_err(|e| MediaError::new("parse_error", format!("渲染失敗: {e}")))?;
+    let luma = bitmap.as_image().to_luma8();
+    let w = luma.width();
+
+    let cutoff = 255u8.saturating_sub(tolerance);
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0u32, 0u32);
+    for (x, y, px) in luma.enumerate_pixels() {
+        if px.0[0] < cutoff {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x + 1);
+            max_y = max_y.max(y + 1);
+        }
+    }
+    if min_x == u32::MAX {
+        return Ok(None);
+    }
+
+    // 顯示座標（左上為原點，已套用 /Rotate）→ 使用者空間
+    let s = w as f32 / page.width().value.max(0.01);
+    let (l, b, r, t) = (
+        visible.left().value,
+        visible.bottom().value,
+        visible.right().value,
+        visible.top().value,
+    );
+    let to_user = |dx: u32, dy: u32| -> (f32, f32) {
+        let (dx, dy) = (dx as f32 / s, dy as f32 / s);
+        match rotation {
+            90 => (l + dy, b + dx),
+            180 => (r - dx, b + dy),
+            270 => (r - dy, t - dx),
+            _ => (l + dx, t - dy),
+        }
+    };
+    let (x1, y1) = to_user(min_x, min_y);
+    let (x2, y2) = to_user(max_x, max_y);
+    Ok(Some(PdfBox {
+        left: x1.min(x2),
+        bottom: y1.min(y2),
+        right: x1.max(x2),
+        top: y1.max(y2),
+    }))
+}
+
+// 紙張預設（與前端 insertPaper 一致），回傳直式尺寸（pt）
+fn paper_size_pt(paper: &str) -> Option<(f32, f32)> {
+    let (w_mm, h_mm) = match paper.to_ascii_lowercase().as_str() {
+        "a4" => (210.0, 297.0),
+        "letter" => (215.9, 279.4),
+        "a5" => (148.0, 210.0),
+        "legal" => (215.9, 355.6),
+        "tabloid" => (279.4, 431.8),
+        _ => return None,
+    };
+    let mm_to_pt = |mm: f32| mm * 72.0 / 25.4;
+    Some((mm_to_pt(w_mm), mm_to_pt(h_mm)))
+}
+
+// 來源頁面（未旋轉使用者空間的可見框 + /Rotate）縮放 scale 後，左下角放到 (x, y) 的轉換矩陣 [a b c d e f]。
+// 用於以 XObject 放置頁面：XObject 不含來源頁的旋轉，需在此補上。
+fn page_placement_matrix(visible: &PdfBox, rotation: u16, scale: f32, x: f32, y: f32) -> [f32; 6] {
+    let w0 = visible.right - visible.left;
+    let s = scale;
+    match rotation {
+        90 => [
+            0.0,
+            -s,
+            s,
+            0.0,
+            x - s * visible.bottom,
+            y + s * (w0 + visible.left),
+        ],
+        180 => [-s, 0.0, 0.0, -s, x + s * visible.right, y + s * visible.top],
+        270 => [0.0, s, -s, 0.0, x + s * visible.top, y - s * visible.left],
+        _ => [s, 0.0, 0.0, s, x - s * visible.left, y - s * visible.bottom],
+    }
+}
+
+// 騎馬釘小冊子頁序：每張紙正面 [末, 首]、背面 [次, 次末]；None 為補空白
+fn booklet_order(pages: &[u32]) -> Vec<Option<u32>> {
+    let padded = pages.len().div_ceil(4) * 4;
+    let at = |i: usize| pages.get(i).copied();
+    let mut out = Vec::with_capacity(padded);
+    for sheet in 0..padded / 4 {
+        let (lo, hi) = (sheet * 2, padded - 1 - sheet * 2);
+        out.extend([at(hi), at(lo), at(lo + 1), at(hi - 1)]);
+    }
+    out
+}
+
+// pdfium-render 0.8 以 u16（PdfPageIndex）定址頁面，pages().len() 也會把頁數截斷為 u16。
+// 頁數、算繪、尺寸、插入、刪除、旋轉、複製與匯出改以原生 API（i32 索引）處理，不受 65,535 頁限制；
+// 其餘經由 PdfPage 的功能（註解、文字、表單等）僅能處理前 65,535 頁
+fn to_page_index(index: u32) -> Result<u16, MediaError> {
+    u16::try_from(index).map_err(|_| {
+        MediaError::new(
+            "page_limit",
+            format!("此功能僅支援前 {} 頁: {}", u16::MAX, index),
+        )
+    })
+}
+
+// 文件實際頁數（不截斷）
+fn total_pages(doc: &pdfium_render::prelude::PdfDocument) -> u32 {
+    let bindings = doc.bindings();
+    bindings
+        .FPDF_GetPageCount(bindings.get_handle_from_document(doc))
+        .max(0) as u32
+}
+
+// 以原生 API 載入的頁面，離開作用域時關閉
+struct RawPage<'a> {
+    bindings: &'a dyn pdfium_render::prelude::PdfiumLibraryBindings,
+    handle: pdfium_render::prelude::FPDF_PAGE,
+}
+
+impl Drop for RawPage<'_> {
+    fn drop(&mut self) {
+        self.bindings.FPDF_ClosePage(self.handle);
+    }
+}
+
+fn load_raw_page<'a>(
+    doc: &'a pdfium_render::prelude::PdfDocument,
+    index: u32,
+) -> Result<RawPage<'a>, MediaError> {
+    let bindings = doc.bindings();
+    let not_found = || MediaError::new("not_found", format!("頁索引不存在: {}", index));
+    if index >= total_pages(doc) {
+        return Err(not_found());
+    }
+    let handle = bindings.FPDF_LoadPage(bindings.get_handle_from_document(doc), index as i32);
+    if handle.is_null() {
+        return Err(not_found());
+    }
+    Ok(RawPage { bindings, handle })
+}
+
+// 頁面 /Rotate（0/90/180/270）
+fn page_rotation(doc: &pdfium_render::prelude::PdfDocument, index: u32) -> Result<u16, MediaError> {
+    let page = load_raw_page(doc, index)?;
+    Ok((page
+        .bindings
+        .FPDFPage_GetRotation(page.handle)
+        .rem_euclid(4)
+        * 90) as u16)
+}
+
+fn set_page_rotation(
+    doc: &pdfium_render::prelude::PdfDocument,
+    index: u32,
+    degrees: u16,
+) -> Result<(), MediaError> {
+    if !degrees.is_multiple_of(90) {
+        return Err(MediaError::new(
+            "invalid_input",
+            "旋轉角度只接受 0|90|180|270",
+        ));
+    }
+    let page = load_raw_page(doc, index)?;
+    page.bindings
+        .FPDFPage_SetRotation(page.handle, (degrees / 90 % 4) as i32);
+    Ok(())
+}
+
+// 頁面尺寸（已套用 /Rotate）與各頁面框
+fn page_size_info(
+    doc: &pdfium_render::prelude::PdfDocument,
+    index: u32,
+) -> Result<PdfPageSize, MediaError> {
+    let page = load_raw_page(doc, index)?;
+    let (bindings, handle) = (page.bindings, page.handle);
+    let read_box = |kind: &str| {
+        let (mut left, mut bottom, mut right, mut top) = (0.0, 0.0, 0.0, 0.0);
+        let ok = match kind {
+            "media" => {
+                bindings.FPDFPage_GetMediaBox(handle, &mut left, &mut bottom, &mut right, &mut top)
             }
+            "crop" => {
+                bindings.FPDFPage_GetCropBox(handle, &mut left, &mut bottom, &mut right, &mut top)
+            }
+            "trim" => {
+                bindings.FPDFPage_GetTrimBox(handle, &mut left, &mut bottom, &mut right, &mut top)
+            }
+            "bleed" => {
+                bindings.FPDFPage_GetBleedBox(handle, &mut left, &mut bottom, &mut right, &mut top)
+            }
+            _ => bindings.FPDFPage_GetArtBox(handle, &mut left, &mut bottom, &mut right, &mut top),
         };
-        let mut docs: HashMap<u64, PdfDocument> = HashMap::new();
-        let mut paths: HashMap<u64, String> = HashMap::new();
-        // 最小允許世代：小於此值的渲染將被立刻忽略（最佳努力取消）
-        let mut min_gen: HashMap<(u64, u32), u64> = HashMap::new();
-        loop {
-            match rx.recv() {
-                Ok(PdfRequest::Open { path, reply }) => {
-                    let res = (|| {
-                        let document = pdfium.load_pdf_from_file(&path, None).map_err(|e| {
-                            MediaError::new("parse_error", format!("開啟 PDF 失敗: {e}"))
-                        })?;
-                        let pages = document.pages().len() as usize;
-                        let id = NEXT_DOC_ID.fetch_add(1, Ordering::SeqCst);
-                        docs.insert(id, document);
-                        paths.insert(id, path);
-                        Ok(PdfOpenResult { doc_id: id, pages })
-                    })();
-                    let _ = reply.send(res);
-                }
-                Ok(PdfRequest::Close { doc_id, reply }) => {
-                    let _ = docs.remove(&doc_id);
-                    let _ = paths.remove(&doc_id);
-                    let _ = reply.send(Ok(()));
-                }
-                Ok(PdfRequest::Render { args, reply }) => {
-                    let res = (|| -> Result<PageRender, MediaError> {
-                        let doc = docs.get(&args.doc_id).ok_or_else(|| {
-                            MediaError::new("not_found", format!("未知的 docId: {}", args.doc_id))
-                        })?;
-                        // best-effort 取消：若 gen 比 min_gen 小則直接丟棄
-                        let g_req = args.r#gen.unwrap_or(0);
-                        let key = (args.doc_id, args.page_index);
-                        if let Some(g_min) = min_gen.get(&key) {
-                            if g_req < *g_min {
-                                return Err(MediaError::new("canceled", "render canceled"));
-                            }
-                        }
-                        render_page_for_document(doc, &args)
-                    })();
-                    let _ = reply.send(res);
-                }
-                Ok(PdfRequest::Size {
-                    doc_id,
-                    page_index,
-                    reply,
-                }) => {
-                    let res = (|| -> Result<PdfPageSize, MediaError> {
-                        let doc = docs.get(&doc_id).ok_or_else(|| {
-                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
-                        })?;
-                        let idx_u16: u16 = page_index.try_into().map_err(|_| {
-                            MediaError::new("invalid_input", format!("頁索引過大: {}", page_index))
-                        })?;
-                        let page = doc.pages().get(idx_u16).map_err(|_| {
-                            MediaError::new("not_found", format!("頁索引不存在: {}", page_index))
-                        })?;
-                        Ok(PdfPageSize {
-                            width_pt: page.width().value as f32,
-                            height_pt: page.height().value as f32,
-                        })
-                    })();
-                    let _ = reply.send(res);
+        bindings.is_true(ok).then_some(PdfBox {
+            left,
+            bottom,
+            right,
+            top,
+        })
+    };
+    let (width_pt, height_pt) = (
+        bindings.FPDF_GetPageWidthF(handle),
+        bindings.FPDF_GetPageHeightF(handle),
+    );
+    Ok(PdfPageSize {
+        width_pt,
+        height_pt,
+        rotation: (bindings.FPDFPage_GetRotation(handle).rem_euclid(4) * 90) as u16,
+        media_box: read_box("media").unwrap_or(PdfBox {
+            left: 0.0,
+            bottom: 0.0,
+            right: width_pt,
+            top: height_pt,
+        }),
+        crop_box: read_box("crop"),
+        trim_box: read_box("trim"),
+        bleed_box: read_box("bleed"),
+        art_box: read_box("art"),
+    })
+}
+
+// 依序（可重複）將來源頁面插入目標文件的 at 位置
+fn import_pages(
+    dest: &pdfium_render::prelude::PdfDocument,
+    src: &pdfium_render::prelude::PdfDocument,
+    indices: &[u32],
+    at: u32,
+) -> Result<(), MediaError> {
+    let (src_count, dest_count) = (total_pages(src), total_pages(dest));
+    if let Some(bad) = indices.iter().find(|i| **i >= src_count) {
+        return Err(MediaError::new(
+            "not_found",
+            format!("頁索引不存在: {}", bad),
+        ));
+    }
+    if at > dest_count {
+        return Err(MediaError::new(
+            "invalid_input",
+            format!("頁索引超出範圍: {} > {}", at, dest_count),
+        ));
+    }
+    let bindings = dest.bindings();
+    let ok = bindings.FPDF_ImportPagesByIndex_vec(
+        bindings.get_handle_from_document(dest),
+        bindings.get_handle_from_document(src),
+        indices.iter().map(|i| *i as i32).collect(),
+        at as i32,
+    );
+    if !bindings.is_true(ok) {
+        return Err(MediaError::new("io_error", "複製頁面失敗"));
+    }
+    Ok(())
+}
+
+// 於 index 插入空白頁，回傳插入後的頁數
+fn insert_blank_page(
+    doc: &pdfium_render::prelude::PdfDocument,
+    index: u32,
+    width_pt: f32,
+    height_pt: f32,
+) -> Result<u32, MediaError> {
+    let count = total_pages(doc);
+    if index > count {
+        return Err(MediaError::new(
+            "invalid_input",
+            format!("頁索引超出範圍: {} > {}", index, count),
+        ));
+    }
+    let bindings = doc.bindings();
+    let handle = bindings.FPDFPage_New(
+        bindings.get_handle_from_document(doc),
+        index as i32,
+        width_pt as f64,
+        height_pt as f64,
+    );
+    if handle.is_null() {
+        return Err(MediaError::new("io_error", "插入空白頁失敗"));
+    }
+    bindings.FPDF_ClosePage(handle);
+    Ok(total_pages(doc))
+}
+
+// 以記憶體載入：Pdfium 不持有檔案控制代碼，儲存時才能以 rename 覆蓋原檔（Windows 會拒絕替換開啟中的檔案）
+fn load_pdf_file<'a>(
+    pdfium: &'a pdfium_render::prelude::Pdfium,
+    path: &str,
+    password: Option<&str>,
+    context: &str,
+) -> Result<pdfium_render::prelude::PdfDocument<'a>, MediaError> {
+    let bytes =
+        fs::read(path).map_err(|e| MediaError::new("io_error", format!("{context}: {e}")))?;
+    load_pdf_bytes(pdfium, bytes, password, context)
+}
+
+fn load_pdf_bytes<'a>(
+    pdfium: &'a pdfium_render::prelude::Pdfium,
+    bytes: Vec<u8>,
+    password: Option<&str>,
+    context: &str,
+) -> Result<pdfium_render::prelude::PdfDocument<'a>, MediaError> {
+    pdfium
+        .load_pdf_from_byte_vec(bytes, password)
+        .map_err(|e| pdf_load_error(e, password, context))
+}
+
+// 載入失敗時區分「需要密碼」與「密碼錯誤」，供前端提示輸入
+fn pdf_load_error(
+    e: pdfium_render::prelude::PdfiumError,
+    password: Option<&str>,
+    context: &str,
+) -> MediaError {
+    match e {
+        pdfium_render::prelude::PdfiumError::PdfiumLibraryInternalError(
+            pdfium_render::prelude::PdfiumInternalError::PasswordError,
+        ) => match password {
+            Some(_) => MediaError::new("wrong_password", "密碼錯誤"),
+            None => MediaError::new("password_required", "此 PDF 需要密碼才能開啟"),
+        },
+        e => MediaError::new("parse_error", format!("{context}: {e}")),
+    }
+}
+
+// 加密資訊：權限取自 Pdfium（以擁有者密碼開啟時為全部允許），加密方式取自開啟時讀取的加密參數
+fn read_security_info(
+    doc: &pdfium_render::prelude::PdfDocument,
+    crypt: Option<&PdfCrypt>,
+) -> PdfSecurityInfo {
+    use pdfium_render::prelude::{PdfSecurityHandlerRevision, PdfiumError};
+    let perms = doc.permissions();
+    let revision = perms.security_handler_revision();
+    let encrypted = !matches!(revision, Ok(PdfSecurityHandlerRevision::Unprotected));
+    let allowed = |r: Result<bool, PdfiumError>| r.unwrap_or(true);
+    let mut info = PdfSecurityInfo {
+        encrypted,
+        method: None,
+        revision: match revision {
+            Ok(PdfSecurityHandlerRevision::Revision2) => Some(2),
+            Ok(PdfSecurityHandlerRevision::Revision3) => Some(3),
+            Ok(PdfSecurityHandlerRevision::Revision4) => Some(4),
+            _ => None,
+        },
+        permissions: PdfPermissionFlags {
+            print: allowed(perms.can_print_high_quality())
+                || allowed(perms.can_print_only_low_quality()),
+            print_high_quality: allowed(perms.can_print_high_quality()),
+            copy: allowed(perms.can_extract_text_and_graphics()),
+            modify: allowed(perms.can_modify_document_content()),
+            annotate: allowed(perms.can_add_or_modify_text_annotations()),
+            fill_forms: allowed(perms.can_fill_existing_interactive_form_fields()),
+            assemble: allowed(perms.can_assemble_document()),
+        },
+    };
+    if encrypted && let Some(crypt) = crypt {
+        info.method = crypt.method.clone();
+        // Pdfium 不回報 R5/R6（AES-256）
+        info.revision = Some(crypt.revision as u32);
+    }
+    info
+}
+
+// 讀取加密文件的加密參數（未加密或無法解析時為 None）；開啟時讀取一次，
+// 供安全性查詢與重建頁面後重新加密使用，不必再序列化文件
+fn read_document_crypt(
+    doc: &pdfium_render::prelude::PdfDocument,
+    bytes: impl FnOnce() -> Option<Vec<u8>>,
+    password: Option<&str>,
+) -> Option<PdfCrypt> {
+    use pdfium_render::prelude::PdfSecurityHandlerRevision;
+    if matches!(
+        doc.permissions().security_handler_revision(),
+        Ok(PdfSecurityHandlerRevision::Unprotected)
+    ) {
+        return None;
+    }
+    let lo = lopdf::Document::load_mem(&bytes()?).ok()?;
+    read_pdf_crypt(&lo, password.unwrap_or("")).ok()
+}
+
+// 以文字字元角度判斷頁面方向（數位產生或已 OCR 的頁面）。
+// 回傳需順時針補轉的角度與信心值；可用字元不足時回傳 None。
+fn orientation_from_text(page: &pdfium_render::prelude::PdfPage) -> Option<(u16, f32)> {
+    let text = page.text().ok()?;
+    let mut buckets = [0u32; 4];
+    for ch in text.chars().iter() {
+        if ch.unicode_char().is_none_or(|c| c.is_whitespace()) {
+            continue;
+        }
+        // 字元角度為使用者空間逆時針弧度，取最接近的 90 度
+        if let Ok(deg) = ch.angle_degrees() {
+            let q = ((deg / 90.0).round() as i32).rem_euclid(4) as usize;
+            buckets[q] += 1;
+        }
+    }
+    let total: u32 = buckets.iter().sum();
+    if total < 20 {
+        return None;
+    }
+    let (q, &n) = buckets.iter().enumerate().max_by_key(|(_, n)| **n)?;
+    // 文字基線逆時針 θ 時，頁面 /Rotate 設為 θ 即為正向
+    let current = page.rotation().map(rotation_to_degrees).unwrap_or(0);
+    let correction = ((q as i32 * 90 - current as i32).rem_euclid(360)) as u16;
+    Some((correction, n as f32 / total as f32))
+}
+
+// 以渲染影像判斷掃描頁方向：投影輪廓判斷文字行為橫向或直向，
+// 再以行內上下筆畫分佈（上伸部多於下伸部）與左右邊界齊整度判斷是否顛倒。
+// 回傳需順時針補轉的角度與信心值；幾乎空白時回傳 None。
+fn orientation_from_image(luma: &image::GrayImage) -> Option<(u16, f32)> {
+    let (w, h) = luma.dimensions();
+    let ink = |px: &image::Luma<u8>| px.0[0] < 128;
+    let mut rows = vec![0u32; h as usize];
+    let mut cols = vec![0u32; w as usize];
+    for (x, y, px) in luma.enumerate_pixels() {
+        if ink(px) {
+            rows[y as usize] += 1;
+            cols[x as usize] += 1;
+        }
+    }
+    let inked: u32 = rows.iter().sum();
+    if (inked as f32) < (w * h) as f32 * 0.002 {
+        return None;
+    }
+    // 變異係數：文字行方向的投影呈明顯的行／行距交替
+    let cv = |p: &[u32]| -> f32 {
+        let n = p.len().max(1) as f32;
+        let mean = p.iter().sum::<u32>() as f32 / n;
+        if mean <= 0.0 {
+            return 0.0;
+        }
+        let var = p.iter().map(|v| (*v as f32 - mean).powi(2)).sum::<f32>() / n;
+        var.sqrt() / mean
+    };
+    let (row_cv, col_cv) = (cv(&rows), cv(&cols));
+    let sideways = col_cv > row_cv;
+    let axis_conf = ((row_cv - col_cv).abs() / row_cv.max(col_cv).max(0.01)).min(1.0);
+
+    // 轉成文字行為橫向後再判斷上下
+    let upright_img = if sideways {
+        image::imageops::rotate90(luma)
+    } else {
+        luma.clone()
+    };
+    let (w, h) = upright_img.dimensions();
+    let mut rows = vec![0u32; h as usize];
+    for (_, y, px) in upright_img.enumerate_pixels() {
+        if ink(px) {
+            rows[y as usize] += 1;
+        }
+    }
+    let line_min = (w / 200).max(1);
+    let (mut top, mut bottom) = (0u64, 0u64);
+    let (mut lefts, mut rights) = (Vec::new(), Vec::new());
+    let mut y = 0u32;
+    while y < h {
+        if rows[y as usize] < line_min {
+            y += 1;
+            continue;
+        }
+        let start = y;
+        while y < h && rows[y as usize] >= line_min {
+            y += 1;
+        }
+        let line_h = y - start;
+        if line_h < 4 {
+            continue;
+        }
+        let third = line_h / 3;
+        top += rows[start as usize..(start + third) as usize]
+            .iter()
+            .map(|v| *v as u64)
+            .sum::<u64>();
+        bottom += rows[(y - third) as usize..y as usize]
+            .iter()
+            .map(|v| *v as u64)
+            .sum::<u64>();
+        let (mut l, mut r) = (u32::MAX, 0u32);
+        for yy in start..y {
+            for x in 0..w {
+                if ink(upright_img.get_pixel(x, yy)) {
+                    l = l.min(x);
+                    r = r.max(x);
                 }
-                Ok(PdfRequest::Cancel {
-                    doc_id,
-                    page_index,
-                    min_gen: g,
-                    reply,
-                }) => {
-                    let key = (doc_id, page_index);
-                    let entry = min_gen.entry(key).or_insert(0);
-                    if g > *entry {
-                        *entry = g;
+            }
+        }
+        if l != u32::MAX {
+            lefts.push(l as f32);
+            rights.push(r as f32);
+        }
+    }
+    if lefts.len() < 2 {
+        return None;
+    }
+    let shape = (top as f32 - bottom as f32) / (top + bottom).max(1) as f32;
+    let std = |v: &[f32]| -> f32 {
+        let mean = v.iter().sum::<f32>() / v.len() as f32;
+        (v.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / v.len() as f32).sqrt()
+    };
+    let (sl, sr) = (std(&lefts), std(&rights));
+    let margin = (sr - sl) / (sl + sr).max(1.0);
+    let score = 0.5 * shape + 0.5 * margin;
+
+    // rotate90 為順時針：文字因此變正向時，頁面需補轉 90 度
+    let correction = match (sideways, score >= 0.0) {
+        (false, true) => 0,
+        (false, false) => 180,
+        (true, true) => 90,
+        (true, false) => 270,
+    };
+    let confidence = (axis_conf * score.abs().min(1.0)).sqrt();
+    Some((correction, confidence))
+}
+
+// 以投影輪廓估計掃描影像的傾斜角（度，影像座標 y 向下）。
+// 在 ±max_angle 內搜尋使文字行投影最集中的角度；文字行為直向時以 90 度為基準。
+fn detect_skew_angle(luma: &image::GrayImage, max_angle: f32) -> Option<(f32, f32)> {
+    // 大圖先縮小，降低運算量
+    let small;
+    let luma = if luma.width() > 1000 {
+        let h = (luma.height() as u64 * 1000 / luma.width() as u64).max(1) as u32;
+        small = image::imageops::resize(luma, 1000, h, image::imageops::FilterType::Triangle);
+        &small
+    } else {
+        luma
+    };
+    let (w, h) = luma.dimensions();
+    let mut points: Vec<(f32, f32)> = luma
+        .enumerate_pixels()
+        .filter(|(_, _, px)| px.0[0] < 128)
+        .map(|(x, y, _)| (x as f32 - w as f32 / 2.0, y as f32 - h as f32 / 2.0))
+        .collect();
+    if points.len() < 200 {
+        return None;
+    }
+    if points.len() > 150_000 {
+        let step = points.len() / 150_000 + 1;
+        points = points.into_iter().step_by(step).collect();
+    }
+    let diag = ((w * w + h * h) as f32).sqrt();
+    let mut bins = vec![0u32; diag as usize + 2];
+    let mut score = |deg: f32| -> f64 {
+        let (s, c) = deg.to_radians().sin_cos();
+        bins.iter_mut().for_each(|b| *b = 0);
+        for (x, y) in &points {
+            let v = -x * s + y * c + diag / 2.0;
+            if let Some(b) = bins.get_mut(v.max(0.0) as usize) {
+                *b += 1;
+            }
+        }
+        bins.iter().map(|b| (*b as f64).powi(2)).sum()
+    };
+
+    let steps = (max_angle / 0.1).round() as i32;
+    let (mut best_base, mut best_a, mut best, mut worst) = (0.0f32, 0.0f32, 0.0f64, f64::MAX);
+    for base in [0.0f32, 90.0] {
+        for i in -steps..=steps {
+            let a = i as f32 * 0.1;
+            let v = score(base + a);
+            worst = worst.min(v);
+            if v > best {
+                (best_base, best_a, best) = (base, a, v);
+            }
+        }
+    }
+    // 細部搜尋
+    let coarse = best_a;
+    for i in -5..=5 {
+        let a = coarse + i as f32 * 0.02;
+        if a.abs() > max_angle {
+            continue;
+        }
+        let v = score(best_base + a);
+        if v > best {
+            (best_a, best) = (a, v);
+        }
+    }
+    if best <= 0.0 {
+        return None;
+    }
+    Some((best_a, ((best - worst) / best) as f32))
+}
+
+// 校正後重新編碼頁面影像的 JPEG 品質
+const DESKEW_JPEG_QUALITY: u8 = 90;
+
+// 將影像旋轉小角度（度，與 detect_skew_angle 同向），尺寸不變，露出的角落補白
+fn rotate_image_small(img: &image::DynamicImage, deg: f32, gray: bool) -> image::DynamicImage {
+    // 灰階維持 Luma8、彩色輸出 Rgb8，不帶 alpha 以免寫回時多出 SMask
+    if gray {
+        image::DynamicImage::ImageLuma8(rotate_buffer(&img.to_luma8(), deg))
+    } else {
+        image::DynamicImage::ImageRgb8(rotate_buffer(&img.to_rgb8(), deg))
+    }
+}
+
+fn rotate_buffer<P: image::Pixel<Subpixel = u8>>(
+    src: &image::ImageBuffer<P, Vec<u8>>,
+    deg: f32,
+) -> image::ImageBuffer<P, Vec<u8>> {
+    let (w, h) = src.dimensions();
+    let n = P::CHANNEL_COUNT as usize;
+    let (s, c) = deg.to_radians().sin_cos();
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    // 超出原圖範圍補白
+    let at = |x: i64, y: i64, i: usize| -> f32 {
+        if x < 0 || y < 0 || x >= w as i64 || y >= h as i64 {
+            return 255.0;
+        }
+        src.get_pixel(x as u32, y as u32).channels()[i] as f32
+    };
+    image::ImageBuffer::from_fn(w, h, |x, y| {
+        let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+        let sx = cx + dx * c - dy * s - 0.5;
+        let sy = cy + dx * s + dy * c - 0.5;
+        let (x0, y0) = (sx.floor(), sy.floor());
+        let (fx, fy) = (sx - x0, sy - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let mut px = [0u8; 4];
+        for (i, v) in px.iter_mut().enumerate().take(n) {
+            let top = at(x0, y0, i) * (1.0 - fx) + at(x0 + 1, y0, i) * fx;
+            let bottom = at(x0, y0 + 1, i) * (1.0 - fx) + at(x0 + 1, y0 + 1, i) * fx;
+            *v = (top * (1.0 - fy) + bottom * fy).round().clamp(0.0, 255.0) as u8;
+        }
+        *P::from_slice(&px[..n])
+    })
+}
+
+// Pdfium 建立影像只接受 JPEG 或 BGRA 點陣圖：無損影像先以 lopdf 建立只含該影像的單位方形頁面
+// （Flate 壓縮，bilevel 時為 1 bit），再以 FPDF_NewXObjectFromPage 帶入 doc 成為表單物件並套用 matrix
+fn flate_image_form_object(
+    doc: &pdfium_render::prelude::PdfDocument,
+    img: &image::DynamicImage,
+    bilevel: bool,
+    matrix: &pdfium_render::prelude::PdfMatrix,
+) -> Result<pdfium_render::prelude::FPDF_PAGEOBJECT, MediaError> {
+    use lopdf::{Dictionary, Object, Stream, dictionary};
+    use pdfium_render::prelude::*;
+    let map_err =
+        |e: lopdf::Error| MediaError::new("encode_error", format!("壓縮頁面影像失敗: {e}"));
+    let (w, h) = (img.width(), img.height());
+    let (color_space, bits, data): (&[u8], i64, Vec<u8>) = if bilevel {
+        // DeviceGray 1 bit：1 為白，每列補足整數位元組
+        let luma = img.to_luma8();
+        let row = w.div_ceil(8) as usize;
+        let mut packed = vec![0u8; row * h as usize];
+        for (x, y, p) in luma.enumerate_pixels() {
+            if p[0] >= 128 {
+                packed[y as usize * row + x as usize / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        (b"DeviceGray", 1, packed)
+    } else if img.color().has_color() {
+        (b"DeviceRGB", 8, img.to_rgb8().into_raw())
+    } else {
+        (b"DeviceGray", 8, img.to_luma8().into_raw())
+    };
+    let mut image = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => w as i64,
+            "Height" => h as i64,
+            "ColorSpace" => Object::Name(color_space.to_vec()),
+            "BitsPerComponent" => bits,
+        },
+        data,
+    );
+    image.compress().map_err(map_err)?;
+
+    let mut lo = lopdf::Document::with_version("1.7");
+    let image_id = lo.add_object(image);
+    let content_id = lo.add_object(Stream::new(Dictionary::new(), b"/Im0 Do".to_vec()));
+    let pages_id = lo.new_object_id();
+    let page_id = lo.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => vec![0.into(), 0.into(), 1.into(), 1.into()],
+        "Resources" => dictionary! { "XObject" => dictionary! { "Im0" => image_id } },
+        "Contents" => content_id,
+    });
+    lo.objects.insert(
+        pages_id,
+        Object::Dictionary(
+            dictionary! { "Type" => "Pages", "Kids" => vec![page_id.into()], "Count" => 1 },
+        ),
+    );
+    let catalog_id = lo.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    lo.trailer.set("Root", catalog_id);
+    let mut bytes = Vec::new();
+    lo.save_to(&mut bytes)
+        .map_err(|e| MediaError::new("encode_error", format!("建立頁面影像失敗: {e}")))?;
+
+    let bindings = doc.bindings();
+    let src = bindings.FPDF_LoadMemDocument64(&bytes, None);
+    if src.is_null() {
+        return Err(MediaError::new("io_error", "建立頁面影像失敗"));
+    }
+    // 匯出時已將物件深拷貝進 doc，來源文件可立即關閉
+    let xobject = bindings.FPDF_NewXObjectFromPage(bindings.get_handle_from_document(doc), src, 0);
+    let object = match xobject.is_null() {
+        true => std::ptr::null_mut(),
+        false => bindings.FPDF_NewFormObjectFromXObject(xobject),
+    };
+    if !xobject.is_null() {
+        bindings.FPDF_CloseXObject(xobject);
+    }
+    bindings.FPDF_CloseDocument(src);
+    if object.is_null() {
+        return Err(MediaError::new("io_error", "建立頁面影像失敗"));
+    }
+    let fs_matrix = FS_MATRIX {
+        a: matrix.a(),
+        b: matrix.b(),
+        c: matrix.c(),
+        d: matrix.d(),
+        e: matrix.e(),
+        f: matrix.f(),
+    };
+    if !bindings.is_true(bindings.FPDFPageObj_SetMatrix(object, &fs_matrix)) {
+        bindings.FPDFPageObj_Destroy(object);
+        return Err(MediaError::new("io_error", "設定影像矩陣失敗"));
+    }
+    Ok(object)
+}
+
+// 對僅含單一影像的頁面偵測傾斜並（選擇性）重寫影像；angle 指定時略過偵測。
+// apply 為 Some 時套用校正，頁面一經改動即設為 true（之後失敗仍需標記文件）
+fn deskew_page<'a>(
+    doc: &pdfium_render::prelude::PdfDocument<'a>,
+    page: &mut pdfium_render::prelude::PdfPage<'a>,
+    index: u32,
+    max_angle: f32,
+    min_angle: f32,
+    angle: Option<f32>,
+    apply: Option<&mut bool>,
+) -> Result<PdfDeskewResult, MediaError> {
+    use pdfium_render::prelude::*;
+    let mut result = PdfDeskewResult {
+        index,
+        angle: None,
+        confidence: 0.0,
+        applied: false,
+        reason: None,
+    };
+    let objects = page.objects();
+    let mut image_index = None;
+    for (i, obj) in objects.iter().enumerate() {
+        if obj.object_type() != PdfPageObjectType::Image || image_index.is_some() {
+            result.reason = Some("not_image_page".to_string());
+            return Ok(result);
+        }
+        image_index = Some(i);
+    }
+    let Some(image_index) = image_index else {
+        result.reason = Some("not_image_page".to_string());
+        return Ok(result);
+    };
+    let mut obj = objects
+        .get(image_index)
+        .map_err(|e| MediaError::new("parse_error", format!("讀取影像物件失敗: {e}")))?;
+    let Some(image_obj) = obj.as_image_object_mut() else {
+        result.reason = Some("not_image_page".to_string());
+        return Ok(result);
+    };
+    let raw = image_obj
+        .get_raw_image()
+        .map_err(|e| MediaError::new("decode_error", format!("讀取頁面影像失敗: {e}")))?;
+    let (deg, confidence) = match angle {
+        Some(a) => (a, 1.0),
+        None => match detect_skew_angle(&raw.to_luma8(), max_angle) {
+            Some(found) => found,
+            None => {
+                result.reason = Some("no_content".to_string());
+                return Ok(result);
+            }
+        },
+    };
+    result.angle = Some(deg);
+    result.confidence = confidence;
+    if deg.abs() > max_angle {
+        result.reason = Some("above_max".to_string());
+        return Ok(result);
+    }
+    if deg.abs() < min_angle {
+        result.reason = Some("below_min".to_string());
+        return Ok(result);
+    }
+    if let Some(touched) = apply {
+        // 僅 DCT 來源以 JPEG 重新編碼；1 bit 來源（CCITT、JBIG2、二值影像）維持 1 bit，其餘以 Flate 無損寫入
+        let filters: Vec<String> = image_obj
+            .filters()
+            .iter()
+            .map(|f| f.name().to_string())
+            .collect();
+        let bilevel = matches!(image_obj.bits_per_pixel(), Ok(1))
+            || filters
+                .iter()
+                .any(|f| f == "CCITTFaxDecode" || f == "JBIG2Decode");
+        let dct = !bilevel && filters.iter().any(|f| f == "DCTDecode");
+        let gray = !raw.color().has_color()
+            || matches!(image_obj.color_space(), Ok(PdfColorSpace::DeviceGray));
+        let rotated = rotate_image_small(&raw, deg, gray || bilevel);
+        let matrix = image_obj
+            .matrix()
+            .map_err(|e| MediaError::new("parse_error", format!("讀取影像矩陣失敗: {e}")))?;
+        drop(obj);
+        let bindings = doc.bindings();
+        let page_handle = bindings.get_handle_from_page(page);
+        if dct {
+            // set_image 一律寫入 BGRA（多出 SMask），改以 JPEG 重新編碼後替換影像物件
+            let mut jpeg = Vec::new();
+            image::codecs::jpeg::JpegEncoder::new_with_quality(
+                Cursor::new(&mut jpeg),
+                DESKEW_JPEG_QUALITY,
+            )
+            .encode_image(&rotated)
+            .map_err(|e| MediaError::new("encode_error", format!("JPEG 編碼失敗: {e}")))?;
+            let mut replacement = PdfPageImageObject::new_from_jpeg_reader(doc, Cursor::new(jpeg))
+                .map_err(|e| MediaError::new("io_error", format!("建立頁面影像失敗: {e}")))?;
+            replacement
+                .apply_matrix(matrix)
+                .map_err(|e| MediaError::new("io_error", format!("設定影像矩陣失敗: {e}")))?;
+            page.objects_mut()
+                .add_image_object(replacement)
+                .map_err(|e| MediaError::new("io_error", format!("寫入頁面影像失敗: {e}")))?;
+        } else {
+            let object = flate_image_form_object(doc, &rotated, bilevel, &matrix)?;
+            bindings.FPDFPage_InsertObject(page_handle, object);
+        }
+        // 新物件加在最後，原影像索引不變
+        *touched = true;
+        page.objects_mut()
+            .remove_object_at_index(image_index)
+            .map_err(|e| MediaError::new("io_error", format!("移除原頁面影像失敗: {e}")))?;
+        page.regenerate_content()
+            .map_err(|e| MediaError::new("io_error", format!("更新頁面內容失敗: {e}")))?;
+        result.applied = true;
+    }
+    Ok(result)
+}
+
+// 空白頁評分：忽略邊緣（掃描陰影）後，以 4x4 區塊過濾零星雜點，回傳墨跡覆蓋率（0~1）
+fn blank_page_coverage(
+    luma: &image::GrayImage,
+    tolerance: u8,
+    noise: f32,
+    edge_margin: f32,
+) -> f32 {
+    const BLOCK: u32 = 4;
+    let (w, h) = luma.dimensions();
+    let (mx, my) = (
+        (w as f32 * edge_margin) as u32,
+        (h as f32 * edge_margin) as u32,
+    );
+    let (x0, y0, x1, y1) = (mx, my, w.saturating_sub(mx), h.saturating_sub(my));
+    if x1 <= x0 || y1 <= y0 {
+        return 0.0;
+    }
+    let cutoff = 255u8.saturating_sub(tolerance);
+    let mut inked = 0u64;
+    let mut by = y0;
+    while by < y1 {
+        let mut bx = x0;
+        while bx < x1 {
+            let (ex, ey) = ((bx + BLOCK).min(x1), (by + BLOCK).min(y1));
+            let mut n = 0u32;
+            for y in by..ey {
+                for x in bx..ex {
+                    if luma.get_pixel(x, y).0[0] < cutoff {
+                        n += 1;
                     }
-                    let _ = reply.send(Ok(()));
                 }
-                Ok(PdfRequest::ExportImage {
-                    doc_id,
-                    page_index,
-                    dest_path,
-                    format,
-                    target_width,
-                    dpi,
-                    quality,
-                    reply,
-                }) => {
-                    let res = (|| -> Result<(String, u32, u32, String), MediaError> {
-                        let doc = docs.get(&doc_id).ok_or_else(|| {
-                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
-                        })?;
-                        let fmt = match format.to_lowercase().as_str() {
-                            "jpeg" | "jpg" => "jpeg",
-                            _ => "png",
-                        };
-                        let args = PdfRenderArgs {
-                            doc_id,
-                            page_index,
-                            scale: None,
-                            dpi,
-                            format: Some(fmt.to_string()),
-                            target_width,
-                            quality,
-                            r#gen: None,
-                        };
-                        let page = render_page_for_document(doc, &args)?;
-                        std::fs::write(&dest_path, &page.image_bytes).map_err(|e| {
-                            MediaError::new("io_error", format!("寫入影像失敗: {e}"))
-                        })?;
-                        Ok((dest_path, page.width_px, page.height_px, page.format))
-                    })();
-                    let _ = reply.send(res);
+            }
+            if n as f32 >= ((ex - bx) * (ey - by)) as f32 * noise {
+                inked += n as u64;
+            }
+            bx += BLOCK;
+        }
+        by += BLOCK;
+    }
+    inked as f32 / ((x1 - x0) as u64 * (y1 - y0) as u64) as f32
+}
+
+// 逐字取出頁面文字與外框（頁面座標 pt）；tight 外框取不到時改用 loose
+fn page_text_chars(
+    text: &pdfium_render::prelude::PdfPageText,
+    tight: bool,
+) -> Vec<(char, Option<PdfBox>)> {
+    decode_text_units(text.chars().iter().map(|ch| {
+        let bounds = if tight {
+            ch.tight_bounds().or_else(|_| ch.loose_bounds())
+        } else {
+            ch.loose_bounds()
+        };
+        (
+            ch.unicode_value(),
+            bounds.ok().map(|r| pdf_box_from_rect(&r)),
+        )
+    }))
+}
+
+// FPDFText_GetUnicode 在 wchar_t 為 16 位元的平台回傳 UTF-16 單元，BMP 以外的字元會拆成代理對；
+// 此處組回單一字元並合併外框，無法配對的代理以 U+FFFD 取代
+fn decode_text_units(
+    units: impl IntoIterator<Item = (u32, Option<PdfBox>)>,
+) -> Vec<(char, Option<PdfBox>)> {
+    let mut out: Vec<(char, Option<PdfBox>)> = Vec::new();
+    let mut pending: Option<(u16, Option<PdfBox>)> = None;
+    for (unit, bounds) in units {
+        let surrogate = u16::try_from(unit)
+            .ok()
+            .filter(|u| (0xD800..0xE000).contains(u));
+        if let Some((high, high_bounds)) = pending.take() {
+            if let Some(low) = surrogate.filter(|u| *u >= 0xDC00)
+                && let Some(Ok(c)) = char::decode_utf16([high, low]).next()
+            {
+                let merged = match (high_bounds, bounds) {
+                    (Some(a), Some(b)) => Some(PdfBox {
+                        left: a.left.min(b.left),
+                        bottom: a.bottom.min(b.bottom),
+                        right: a.right.max(b.right),
+                        top: a.top.max(b.top),
+                    }),
+                    (a, b) => a.or(b),
+                };
+                out.push((c, merged));
+                continue;
+            }
+            out.push(('\u{FFFD}', high_bounds));
+        }
+        match surrogate {
+            Some(high) if high < 0xDC00 => pending = Some((high, bounds)),
+            _ => out.push((char::from_u32(unit).unwrap_or('\u{FFFD}'), bounds)),
+        }
+    }
+    if let Some((_, b)) = pending {
+        out.push(('\u{FFFD}', b));
+    }
+    out
+}
+
+// 擷取單頁文字；granularity 為 'char' | 'word' 時附帶外框（頁面使用者空間，單位 pt）
+fn extract_page_text(
+    page: &pdfium_render::prelude::PdfPage,
+    index: u32,
+    granularity: &str,
+) -> Result<PdfPageTextResult, MediaError> {
+    let text = page
+        .text()
+        .map_err(|e| MediaError::new("parse_error", format!("讀取頁面文字失敗: {e}")))?;
+    let mut entry = PdfPageTextResult {
+        index,
+        text: text.all(),
+        chars: None,
+        words: None,
+    };
+    if granularity != "char" && granularity != "word" {
+        return Ok(entry);
+    }
+    let mut chars = Vec::new();
+    for (c, bbox) in page_text_chars(&text, true) {
+        let Some(bbox) = bbox else { continue };
+        chars.push(PdfTextItem {
+            text: c.to_string(),
+            bbox,
+        });
+    }
+    if granularity == "char" {
+        entry.chars = Some(chars);
+        return Ok(entry);
+    }
+    // 以空白（含 Pdfium 自動補上的空白與換行）切分單字，外框取聯集
+    let mut words: Vec<PdfTextItem> = Vec::new();
+    let mut current: Option<PdfTextItem> = None;
+    for item in chars {
+        if item.text.chars().all(char::is_whitespace) {
+            words.extend(current.take());
+            continue;
+        }
+        match current.as_mut() {
+            Some(word) => {
+                word.text.push_str(&item.text);
+                word.bbox.left = word.bbox.left.min(item.bbox.left);
+                word.bbox.bottom = word.bbox.bottom.min(item.bbox.bottom);
+                word.bbox.right = word.bbox.right.max(item.bbox.right);
+                word.bbox.top = word.bbox.top.max(item.bbox.top);
+            }
+            None => current = Some(item),
+        }
+    }
+    words.extend(current);
+    entry.words = Some(words);
+    Ok(entry)
+}
+
+// 在單頁文字中搜尋，回傳符合文字與 QuadPoints（每行一組：左上、右上、左下、右下，頁面座標 pt）
+fn search_page_text(
+    page: &pdfium_render::prelude::PdfPage,
+    re: &regex::Regex,
+) -> Result<Vec<PdfSearchHit>, MediaError> {
+    let text = page
+        .text()
+        .map_err(|e| MediaError::new("parse_error", format!("讀取頁面文字失敗: {e}")))?;
+    let mut content = String::new();
+    let mut offsets = Vec::new();
+    let mut bounds: Vec<Option<PdfBox>> = Vec::new();
+    for (c, bbox) in page_text_chars(&text, false) {
+        offsets.push(content.len());
+        content.push(c);
+        bounds.push(if c.is_whitespace() { None } else { bbox });
+    }
+    let mut hits = Vec::new();
+    for m in re.find_iter(&content) {
+        if m.as_str().is_empty() {
+            continue;
+        }
+        let first = offsets.partition_point(|o| *o < m.start());
+        let last = offsets.partition_point(|o| *o < m.end());
+        // 依行合併字元外框：垂直中心落在目前行範圍內且未往回折行者視為同一行
+        let mut lines: Vec<PdfBox> = Vec::new();
+        for b in bounds[first..last].iter().flatten() {
+            let mid = (b.bottom + b.top) / 2.0;
+            match lines.last_mut() {
+                Some(l) if mid >= l.bottom && mid <= l.top && b.left >= l.left => {
+                    l.left = l.left.min(b.left);
+                    l.bottom = l.bottom.min(b.bottom);
+                    l.right = l.right.max(b.right);
+                    l.top = l.top.max(b.top);
                 }
-                Ok(PdfRequest::ExportPdf {
-                    doc_id,
-                    page_index,
-                    dest_path,
-                    reply,
-                }) => {
-                    let res = (|| -> Result<String, MediaError> {
-                        let doc = docs.get(&doc_id).ok_or_else(|| {
-                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
-                        })?;
-                        let idx_u16: u16 = page_index.try_into().map_err(|_| {
-                            MediaError::new("invalid_input", format!("頁索引過大: {}", page_index))
-                        })?;
-                        // 建立新 PDF 並以向量方式複製該頁，不變更原文件
-                        let mut new_doc = pdfium.create_new_pdf().map_err(|e| {
-                            MediaError::new("io_error", format!("建立新 PDF 失敗: {e}"))
-                        })?;
-                        new_doc
-                            .pages_mut()
-                            .copy_page_from_document(doc, idx_u16, 0)
-                            .map_err(|e| {
-                                MediaError::new("io_error", format!("複製頁面失敗: {e}"))
-                            })?;
-                        new_doc.save_to_file(&dest_path).map_err(|e| {
-                            MediaError::new("io_error", format!("寫入 PDF 失敗: {e}"))
-                        })?;
-                        Ok(dest_path)
-                    })();
-                    let _ = reply.send(res);
-                }
-                Ok(PdfRequest::InsertBlank {
-                    doc_id,
-                    index,
-                    width_pt,
-                    height_pt,
-                    reply,
-                }) => {
-                    let res = (|| -> Result<usize, MediaError> {
-                        let doc = docs.get_mut(&doc_id).ok_or_else(|| {
-                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
-                        })?;
-                        let size = PdfPagePaperSize::Custom(
-                            PdfPoints::new(width_pt),
-                            PdfPoints::new(height_pt),
-                        );
-                        let idx_u16: u16 = index.try_into().map_err(|_| {
-                            MediaError::new("invalid_input", format!("頁索引過大: {}", index))
-                        })?;
-                        doc.pages_mut()
-                            .create_page_at_index(size, idx_u16)
-                            .map_err(|e| {
-                                MediaError::new("io_error", format!("插入空白頁失敗: {e}"))
-                            })?;
-                        Ok(doc.pages().len() as usize)
-                    })();
-                    let _ = reply.send(res);
-                }
-                Ok(PdfRequest::DeletePages {
-                    doc_id,
-                    mut indices,
-                    reply,
-                }) => {
-                    let res = (|| -> Result<usize, MediaError> {
-                        // 取出文件所有權以避免與 HashMap 的借用衝突
-                        let old = docs.remove(&doc_id).ok_or_else(|| {
-                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
-                        })?;
-                        let page_count = old.pages().len();
-                        if page_count == 0 {
-                            return Err(MediaError::new("invalid_input", "文件沒有任何頁面"));
-                        }
-                        if indices.is_empty() {
-                            return Err(MediaError::new("invalid_input", "缺少要刪除的頁索引"));
-                        }
-                        indices.sort_unstable();
-                        indices.dedup();
-                        if let Some(max) = indices.last() {
-                            if *max >= page_count as u32 {
-                                return Err(MediaError::new(
-                                    "invalid_input",
-                                    format!("頁索引超出範圍: {} >= {}", max, page_count),
-                                ));
-                            }
-                        }
-                        // 構建保留頁碼 spec（1-based, e.g. "1,3,5-7"）
-                        let mut keep: Vec<u32> = (0..(page_count as u32)).collect();
-                        let del: HashSet<u32> = indices.into_iter().collect();
-                        keep.retain(|i| !del.contains(i));
-                        if keep.is_empty() {
-                            return Err(MediaError::new(
-                                "invalid_input",
-                                "無法刪除所有頁面，至少需保留一頁",
-                            ));
-                        }
-                        let mut pages_1: Vec<u32> = keep.into_iter().map(|i| i + 1).collect();
-                        pages_1.sort_unstable();
-                        let mut ranges: Vec<(u32, u32)> = Vec::new();
-                        for p in pages_1 {
-                            if let Some(last) = ranges.last_mut() {
-                                if p == last.1 + 1 {
-                                    last.1 = p;
-                                    continue;
-                                }
-                            }
-                            ranges.push((p, p));
-                        }
-                        let mut spec = String::new();
-                        for (i, (a, b)) in ranges.iter().enumerate() {
-                            if i > 0 {
-                                spec.push(',');
-                            }
-                            if a == b {
-                                spec.push_str(&format!("{}", a));
-                            } else {
-                                spec.push_str(&format!("{}-{}", a, b));
-                            }
-                        }
-                        let mut new_doc = pdfium.create_new_pdf().map_err(|e| {
-                            MediaError::new("io_error", format!("建立新 PDF 失敗: {e}"))
-                        })?;
-                        new_doc
-                            .pages_mut()
-                            .copy_pages_from_document(&old, &spec, 0)
-                            .map_err(|e| {
-                                MediaError::new("io_error", format!("複製頁面失敗: {e}"))
-                            })?;
-                        let pages_after = new_doc.pages().len() as usize;
-                        // 替換文件
-                        docs.insert(doc_id, new_doc);
-                        Ok(pages_after)
-                    })();
-                    let _ = reply.send(res);
-                }
-                Ok(PdfRequest::RotatePage {
-                    doc_id,
-                    index,
-                    rotate_deg,
-                    reply,
-                }) => {
-                    let res = (|| -> Result<(), MediaError> {
-                        use pdfium_render::prelude::*;
-                        let doc = docs.get_mut(&doc_id).ok_or_else(|| {
-                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
-                        })?;
-                        let rot = match rotate_deg {
-                            90 => PdfPageRenderRotation::Degrees90,
-                            180 => PdfPageRenderRotation::Degrees180,
-                            270 => PdfPageRenderRotation::Degrees270,
-                            0 => PdfPageRenderRotation::None,
-                            _ => {
-                                return Err(MediaError::new(
-                                    "invalid_input",
-                                    "旋轉角度只接受 0|90|180|270",
-                                ));
-                            }
-                        };
-                        let idx_u16: u16 = index.try_into().map_err(|_| {
-                            MediaError::new("invalid_input", format!("頁索引過大: {}", index))
-                        })?;
-                        let mut page = doc.pages_mut().get(idx_u16).map_err(|_| {
-                            MediaError::new("not_found", format!("頁索引不存在: {}", index))
-                        })?;
-                        page.set_rotation(rot);
-                        Ok(())
-                    })();
-                    let _ = reply.send(res);
+                _ => lines.push(*b),
+            }
+        }
+        hits.push(PdfSearchHit {
+            text: m.as_str().to_string(),
+            quads: lines
+                .iter()
+                .map(|l| {
+                    [
+                        l.left, l.top, l.right, l.top, l.left, l.bottom, l.right, l.bottom,
+                    ]
+                })
+                .collect(),
+        });
+    }
+    Ok(hits)
+}
+
+// 頁面使用者空間 (x, y) → 顯示像素座標（左上為原點，已套用可見框與 /Rotate；s 為 px/pt）
+fn page_point_to_display(visible: &PdfBox, rotation: u16, s: f32, x: f32, y: f32) -> (f32, f32) {
+    match rotation {
+        90 => ((y - visible.bottom) * s, (x - visible.left) * s),
+        180 => ((visible.right - x) * s, (y - visible.bottom) * s),
+        270 => ((visible.top - y) * s, (visible.right - x) * s),
+        _ => ((x - visible.left) * s, (visible.top - y) * s),
+    }
+}
+
+// Pdfium 無法寫入的結構（書籤等）改以 lopdf 處理：序列化目前文件後解析，加密文件以開啟密碼解密
+fn load_lopdf(
+    doc: &pdfium_render::prelude::PdfDocument,
+    password: Option<&str>,
+) -> Result<lopdf::Document, MediaError> {
+    Ok(load_lopdf_decrypted(doc, password)?.0)
+}
+
+// 同 load_lopdf，另回傳原加密參數供寫回時重新加密
+fn load_lopdf_decrypted(
+    doc: &pdfium_render::prelude::PdfDocument,
+    password: Option<&str>,
+) -> Result<(lopdf::Document, Option<PdfCrypt>), MediaError> {
+    let bytes = doc
+        .save_to_bytes()
+        .map_err(|e| MediaError::new("io_error", format!("序列化 PDF 失敗: {e}")))?;
+    let mut lo = lopdf::Document::load_mem(&bytes)
+        .map_err(|e| MediaError::new("parse_error", format!("解析 PDF 結構失敗: {e}")))?;
+    if !lo.is_encrypted() {
+        return Ok((lo, None));
+    }
+    let crypt = decrypt_document(&mut lo, password.unwrap_or(""))?;
+    Ok((lo, Some(crypt)))
+}
+
+// 以 lopdf 修改後重新載入為 Pdfium 文件；原已加密者以相同參數重新加密
+fn edit_with_lopdf<'a, R>(
+    pdfium: &'a pdfium_render::prelude::Pdfium,
+    doc: &pdfium_render::prelude::PdfDocument,
+    password: Option<&str>,
+    f: impl FnOnce(&mut lopdf::Document) -> Result<R, MediaError>,
+) -> Result<(pdfium_render::prelude::PdfDocument<'a>, R), MediaError> {
+    let (mut lo, crypt) = load_lopdf_decrypted(doc, password)?;
+    let out = f(&mut lo)?;
+    if let Some(crypt) = &crypt {
+        reencrypt_document(&mut lo, crypt)?;
+    }
+    let mut bytes = Vec::new();
+    lo.save_to(&mut bytes)
+        .map_err(|e| MediaError::new("io_error", format!("寫入 PDF 結構失敗: {e}")))?;
+    let reloaded = pdfium
+        .load_pdf_from_byte_vec(bytes, crypt.as_ref().and(password))
+        .map_err(|e| MediaError::new("parse_error", format!("重新載入 PDF 失敗: {e}")))?;
+    Ok((reloaded, out))
+}
+
+// 重建（複製頁面）的文件不含加密：原文件已加密時以其開啟時的加密參數加密後重新載入
+fn restore_encryption<'a>(
+    pdfium: &'a pdfium_render::prelude::Pdfium,
+    crypt: Option<&PdfCrypt>,
+    password: Option<&str>,
+    new_doc: pdfium_render::prelude::PdfDocument<'a>,
+) -> Result<pdfium_render::prelude::PdfDocument<'a>, MediaError> {
+    let Some(crypt) = crypt else {
+        return Ok(new_doc);
+    };
+    let (mut lo, _) = load_lopdf_decrypted(&new_doc, None)?;
+    reencrypt_document(&mut lo, crypt)?;
+    let mut bytes = Vec::new();
+    lo.save_to(&mut bytes)
+        .map_err(|e| MediaError::new("io_error", format!("寫入 PDF 結構失敗: {e}")))?;
+    pdfium
+        .load_pdf_from_byte_vec(bytes, password)
+        .map_err(|e| MediaError::new("parse_error", format!("重新載入 PDF 失敗: {e}")))
+}
+
+// PDF 文字字串：UTF-16BE（含 BOM）或 PDFDocEncoding（以 Latin-1 近似）
+fn decode_pdf_text(bytes: &[u8]) -> String {
+    if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = rest
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+    bytes.iter().map(|b| *b as char).collect()
+}
+
+fn encode_pdf_text(text: &str) -> lopdf::Object {
+    if text.is_ascii() {
+        return lopdf::Object::string_literal(text);
+    }
+    let mut bytes = vec![0xFE, 0xFF];
+    for unit in text.encode_utf16() {
+        bytes.extend(unit.to_be_bytes());
+    }
+    lopdf::Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+}
+
+fn pdf_number(obj: &lopdf::Object) -> Option<f32> {
+    match obj {
+        lopdf::Object::Integer(v) => Some(*v as f32),
+        lopdf::Object::Real(v) => Some(*v),
+        _ => None,
+    }
+}
+
+// 名稱樹（/Names /Dests）查找
+fn lookup_name_tree(
+    doc: &lopdf::Document,
+    node: &lopdf::Dictionary,
+    name: &[u8],
+    depth: u32,
+) -> Option<lopdf::Object> {
+    if depth > 32 {
+        return None;
+    }
+    if let Some(lopdf::Object::Array(pairs)) =
+        node.get(b"Names").ok().and_then(|o| resolve_object(doc, o))
+    {
+        for pair in pairs.chunks(2) {
+            if let [lopdf::Object::String(key, _), value] = pair
+                && key.as_slice() == name
+            {
+                return resolve_object(doc, value);
+            }
+        }
+    }
+    if let Some(lopdf::Object::Array(kids)) =
+        node.get(b"Kids").ok().and_then(|o| resolve_object(doc, o))
+    {
+        for kid in kids.iter() {
+            if let Some(kid) = resolve_dict(doc, kid)
+                && let Some(found) = lookup_name_tree(doc, &kid, name, depth + 1)
+            {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
+// 解析目的地（陣列、具名目的地或含 /D 的字典）→ (頁索引, left, top, zoom)
+fn parse_outline_dest(
+    doc: &lopdf::Document,
+    obj: &lopdf::Object,
+    pages: &HashMap<lopdf::ObjectId, u32>,
+) -> (Option<u32>, Option<f32>, Option<f32>, Option<f32>) {
+    let none = (None, None, None, None);
+    let mut dest = match resolve_object(doc, obj) {
+        Some(d) => d,
+        None => return none,
+    };
+    for _ in 0..4 {
+        dest = match dest {
+            lopdf::Object::Name(n) | lopdf::Object::String(n, _) => {
+                let catalog = match doc.catalog() {
+                    Ok(c) => c,
+                    Err(_) => return none,
+                };
+                // PDF 1.1 的 /Dests 字典，其次為 /Names /Dests 名稱樹
+                let legacy = catalog
+                    .get(b"Dests")
+                    .ok()
+                    .and_then(|o| resolve_dict(doc, o))
+                    .and_then(|d| d.get(&n).ok().and_then(|v| resolve_object(doc, v)));
+                let found = legacy.or_else(|| {
+                    let names = catalog
+                        .get(b"Names")
+                        .ok()
+                        .and_then(|o| resolve_dict(doc, o))?;
+                    let tree = names
+                        .get(b"Dests")
+                        .ok()
+                        .and_then(|o| resolve_dict(doc, o))?;
+                    lookup_name_tree(doc, &tree, &n, 0)
+                });
+                match found {
+                    Some(d) => d,
+                    None => return none,
                 }
-                Ok(PdfRequest::RotatePageRelative {
-                    doc_id,
-                    index,
-                    delta_deg,
-                    reply,
-                }) => {
-                    let res = (|| -> Result<u16, MediaError> {
-                        use pdfium_render::prelude::*;
-                        let doc = docs.get_mut(&doc_id).ok_or_else(|| {
-                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
-                        })?;
-                        let idx_u16: u16 = index.try_into().map_err(|_| {
-                            MediaError::new("invalid_input", format!("頁索引過大: {}", index))
-                        })?;
-                        let mut page = doc.pages_mut().get(idx_u16).map_err(|_| {
-                            MediaError::new("not_found", format!("頁索引不存在: {}", index))
-                        })?;
-                        let cur = page.rotation().map_err(|e| {
-                            MediaError::new("io_error", format!("取得頁面旋轉失敗: {e}"))
-                        })?;
-                        let cur_deg: i16 = match cur {
-                            PdfPageRenderRotation::None => 0,
-                            PdfPageRenderRotation::Degrees90 => 90,
-                            PdfPageRenderRotation::Degrees180 => 180,
-                            PdfPageRenderRotation::Degrees270 => 270,
-                        };
-                        // 規範到 0/90/180/270
-                        let add: i16 = ((delta_deg % 360) + 360) % 360;
-                        let next: i16 = ((cur_deg + add) % 360 + 360) % 360;
-                        let set_to = match next {
-                            0 => PdfPageRenderRotation::None,
-                            90 => PdfPageRenderRotation::Degrees90,
-                            180 => PdfPageRenderRotation::Degrees180,
-                            270 => PdfPageRenderRotation::Degrees270,
-                            _ => PdfPageRenderRotation::None,
-                        };
-                        page.set_rotation(set_to);
-                        Ok(match set_to {
-                            PdfPageRenderRotation::None => 0,
-                            PdfPageRenderRotation::Degrees90 => 90,
-                            PdfPageRenderRotation::Degrees180 => 180,
-                            PdfPageRenderRotation::Degrees270 => 270,
-                        })
-                    })();
-                    let _ = reply.send(res);
+            }
+            lopdf::Object::Dictionary(d) => {
+                match d.get(b"D").ok().and_then(|v| resolve_object(doc, v)) {
+                    Some(v) => v,
+                    None => return none,
                 }
-                Ok(PdfRequest::CopyPage {
-                    src_doc_id,
-                    src_index,
-                    dest_doc_id,
-                    dest_index,
-                    reply,
-                }) => {
-                    let res = (|| -> Result<usize, MediaError> {
-                        if src_doc_id == dest_doc_id {
-                            // 同文件複製：先取出文件所有權，避免 HashMap 借用衝突
-                            let mut doc = docs.remove(&src_doc_id).ok_or_else(|| {
-                                MediaError::new(
-                                    "not_found",
-                                    format!("未知的 docId: {}", src_doc_id),
-                                )
-                            })?;
-                            // 用暫存文件承接來源頁，避免同時 &mut 與 & 的借用衝突
-                            let mut tmp = pdfium.create_new_pdf().map_err(|e| {
-                                MediaError::new("io_error", format!("建立暫存 PDF 失敗: {e}"))
-                            })?;
-                            let idx_src_u16: u16 = src_index.try_into().map_err(|_| {
-                                MediaError::new(
-                                    "invalid_input",
-                                    format!("頁索引過大: {}", src_index),
-                                )
-                            })?;
-                            {
-                                let src_ref = &doc;
-                                tmp.pages_mut()
-                                    .copy_page_from_document(src_ref, idx_src_u16, 0)
-                                    .map_err(|e| {
-                                        MediaError::new("io_error", format!("複製來源頁失敗: {e}"))
-                                    })?;
-                            }
-                            let idx_dest_u16: u16 = dest_index.try_into().map_err(|_| {
-                                MediaError::new(
-                                    "invalid_input",
-                                    format!("頁索引過大: {}", dest_index),
-                                )
-                            })?;
-                            doc.pages_mut()
-                                .copy_pages_from_document(&tmp, "1", idx_dest_u16)
-                                .map_err(|e| {
-                                    MediaError::new("io_error", format!("插入頁面失敗: {e}"))
-                                })?;
-                            let pages_after = doc.pages().len() as usize;
-                            docs.insert(src_doc_id, doc);
-                            Ok(pages_after)
-                        } else {
-                            // 跨文件：先取出目標文件以避免與來源借用衝突
-                            let mut dest = docs.remove(&dest_doc_id).ok_or_else(|| {
-                                MediaError::new(
-                                    "not_found",
-                                    format!("未知的 docId: {}", dest_doc_id),
-                                )
-                            })?;
-                            let src = docs.get(&src_doc_id).ok_or_else(|| {
-                                MediaError::new(
-                                    "not_found",
-                                    format!("未知的 docId: {}", src_doc_id),
-                                )
-                            })?;
-                            let idx_src_u16: u16 = src_index.try_into().map_err(|_| {
-                                MediaError::new(
-                                    "invalid_input",
-                                    format!("頁索引過大: {}", src_index),
-                                )
-                            })?;
-                            let idx_dest_u16: u16 = dest_index.try_into().map_err(|_| {
-                                MediaError::new(
-                                    "invalid_input",
-                                    format!("頁索引過大: {}", dest_index),
-                                )
-                            })?;
-                            dest.pages_mut()
-                                .copy_page_from_document(src, idx_src_u16, idx_dest_u16)
-                                .map_err(|e| {
-                                    MediaError::new("io_error", format!("複製頁面失敗: {e}"))
-                                })?;
-                            let pages_after = dest.pages().len() as usize;
-                            docs.insert(dest_doc_id, dest);
-                            Ok(pages_after)
-                        }
-                    })();
-                    let _ = reply.send(res);
+            }
+            other => other,
+        };
+        if matches!(dest, lopdf::Object::Array(_)) {
+            break;
+        }
+    }
+    let lopdf::Object::Array(items) = dest else {
+        return none;
+    };
+    let page = items
+        .first()
+        .and_then(|p| p.as_reference().ok())
+        .and_then(|id| pages.get(&id).copied());
+    let num = |i: usize| items.get(i).and_then(pdf_number);
+    match items.get(1).and_then(|k| k.as_name().ok()) {
+        Some(b"XYZ") => (page, num(2), num(3), num(4).filter(|z| *z > 0.0)),
+        Some(b"FitH") | Some(b"FitBH") => (page, None, num(2), None),
+        _ => (page, None, None, None),
+    }
+}
+
+fn read_outline_level(
+    doc: &lopdf::Document,
+    first: Option<&lopdf::Object>,
+    pages: &HashMap<lopdf::ObjectId, u32>,
+    visited: &mut HashSet<lopdf::ObjectId>,
+    depth: u32,
+) -> Vec<PdfOutlineItem> {
+    let mut out = Vec::new();
+    let mut cur = first.and_then(|o| o.as_reference().ok());
+    while let Some(id) = cur {
+        // 防止循環參照
+        if depth > 64 || !visited.insert(id) {
+            break;
+        }
+        let Ok(dict) = doc.get_dictionary(id) else {
+            break;
+        };
+        let title = match dict.get(b"Title").ok().and_then(|o| resolve_object(doc, o)) {
+            Some(lopdf::Object::String(bytes, _)) => decode_pdf_text(&bytes),
+            _ => String::new(),
+        };
+        let mut item = PdfOutlineItem {
+            title,
+            page_index: None,
+            left: None,
+            top: None,
+            zoom: None,
+            uri: None,
+            open: dict_get_i64(doc, dict, b"Count").unwrap_or(0) > 0,
+            children: read_outline_level(doc, dict.get(b"First").ok(), pages, visited, depth + 1),
+        };
+        let action = dict.get(b"A").ok().and_then(|o| resolve_dict(doc, o));
+        let dest = dict.get(b"Dest").ok().cloned().or_else(|| {
+            let a = action.as_ref()?;
+            match a.get(b"S").ok()?.as_name().ok()? {
+                b"GoTo" => a.get(b"D").ok().cloned(),
+                _ => None,
+            }
+        });
+        if let Some(dest) = dest {
+            (item.page_index, item.left, item.top, item.zoom) =
+                parse_outline_dest(doc, &dest, pages);
+        } else if let Some(a) = action.as_ref()
+            && let Some(lopdf::Object::String(uri, _)) =
+                a.get(b"URI").ok().and_then(|o| resolve_object(doc, o))
+        {
+            item.uri = Some(String::from_utf8_lossy(&uri).into_owned());
+        }
+        out.push(item);
+        cur = dict.get(b"Next").ok().and_then(|o| o.as_reference().ok());
+    }
+    out
+}
+
+fn read_outline(doc: &lopdf::Document) -> Vec<PdfOutlineItem> {
+    let pages: HashMap<lopdf::ObjectId, u32> = doc
+        .get_pages()
+        .into_iter()
+        .map(|(n, id)| (id, n - 1))
+        .collect();
+    let Some(root) = doc
+        .catalog()
+        .ok()
+        .and_then(|c| c.get(b"Outlines").ok())
+        .and_then(|o| resolve_dict(doc, o))
+    else {
+        return Vec::new();
+    };
+    read_outline_level(doc, root.get(b"First").ok(), &pages, &mut HashSet::new(), 0)
+}
+
+// 展開時可見的子孫數
+fn outline_visible_count(items: &[PdfOutlineItem]) -> i64 {
+    items
+        .iter()
+        .map(|i| {
+            1 + if i.open {
+                outline_visible_count(&i.children)
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+// 寫入同層項目，回傳 (第一項, 最後一項)
+fn write_outline_level(
+    doc: &mut lopdf::Document,
+    items: &[PdfOutlineItem],
+    parent: lopdf::ObjectId,
+    pages: &[lopdf::ObjectId],
+) -> Option<(lopdf::ObjectId, lopdf::ObjectId)> {
+    use lopdf::{Dictionary, Object};
+    let ids: Vec<lopdf::ObjectId> = items.iter().map(|_| doc.new_object_id()).collect();
+    for (i, item) in items.iter().enumerate() {
+        let mut dict = Dictionary::new();
+        dict.set("Title", encode_pdf_text(&item.title));
+        dict.set("Parent", Object::Reference(parent));
+        if i > 0 {
+            dict.set("Prev", Object::Reference(ids[i - 1]));
+        }
+        if let Some(next) = ids.get(i + 1) {
+            dict.set("Next", Object::Reference(*next));
+        }
+        if let Some((first, last)) = write_outline_level(doc, &item.children, ids[i], pages) {
+            dict.set("First", Object::Reference(first));
+            dict.set("Last", Object::Reference(last));
+            let count = outline_visible_count(&item.children);
+            dict.set("Count", if item.open { count } else { -count });
+        }
+        if let Some(page) = item.page_index.and_then(|p| pages.get(p as usize)) {
+            let opt = |v: Option<f32>| v.map(Object::Real).unwrap_or(Object::Null);
+            let dest = if item.left.is_some() || item.top.is_some() || item.zoom.is_some() {
+                vec![
+                    Object::Reference(*page),
+                    Object::Name(b"XYZ".to_vec()),
+                    opt(item.left),
+                    opt(item.top),
+                    opt(item.zoom),
+                ]
+            } else {
+                vec![Object::Reference(*page), Object::Name(b"Fit".to_vec())]
+            };
+            dict.set("Dest", Object::Array(dest));
+        } else if let Some(uri) = &item.uri {
+            let mut action = Dictionary::new();
+            action.set("S", Object::Name(b"URI".to_vec()));
+            action.set("URI", Object::string_literal(uri.as_bytes()));
+            dict.set("A", Object::Dictionary(action));
+        }
+        doc.objects.insert(ids[i], Object::Dictionary(dict));
+    }
+    Some((*ids.first()?, *ids.last()?))
+}
+
+// 以 items 取代整個書籤樹；空陣列代表移除書籤
+fn write_outline(doc: &mut lopdf::Document, items: &[PdfOutlineItem]) -> Result<(), MediaError> {
+    use lopdf::{Dictionary, Object};
+    let pages: Vec<lopdf::ObjectId> = doc.get_pages().into_values().collect();
+    let root_id = doc.new_object_id();
+    let range = write_outline_level(doc, items, root_id, &pages);
+    let catalog = doc
+        .catalog_mut()
+        .map_err(|e| MediaError::new("parse_error", format!("讀取文件目錄失敗: {e}")))?;
+    match range {
+        Some((first, last)) => {
+            catalog.set("Outlines", Object::Reference(root_id));
+            let mut root = Dictionary::new();
+            root.set("Type", Object::Name(b"Outlines".to_vec()));
+            root.set("First", Object::Reference(first));
+            root.set("Last", Object::Reference(last));
+            root.set("Count", outline_visible_count(items));
+            doc.objects.insert(root_id, Object::Dictionary(root));
+        }
+        None => {
+            catalog.remove(b"Outlines");
+        }
+    }
+    // 移除舊書籤物件
+    doc.prune_objects();
+    Ok(())
+}
+
+// 依頁索引對應表重新指向；目標頁已不存在的項目移除，其子項目上移一層
+fn remap_outline(items: Vec<PdfOutlineItem>, map: &HashMap<u32, u32>) -> Vec<PdfOutlineItem> {
+    let mut out = Vec::new();
+    for mut item in items {
+        let children = remap_outline(std::mem::take(&mut item.children), map);
+        match item.page_index {
+            Some(p) => match map.get(&p) {
+                Some(np) => {
+                    item.page_index = Some(*np);
+                    item.children = children;
+                    out.push(item);
                 }
-                Ok(PdfRequest::Save {
-                    doc_id,
-                    dest_path,
-                    overwrite,
-                    reply,
-                }) => {
-                    let res = (|| -> Result<(String, usize), MediaError> {
-                        let dest = match (dest_path, overwrite.unwrap_or(false)) {
-                            (Some(p), ow) => {
-                                if !ow && Path::new(&p).exists() {
-                                    return Err(MediaError::new(
-                                        "io_error",
-                                        format!("目的檔已存在：{}（overwrite=false）", p),
-                                    ));
-                                }
-                                p
-                            }
-                            (None, true) => paths.get(&doc_id).cloned().ok_or_else(|| {
-                                MediaError::new(
-                                    "invalid_input",
-                                    "未提供 destPath，且無可覆蓋之原始路徑",
-                                )
-                            })?,
-                            _ => {
-                                return Err(MediaError::new(
-                                    "invalid_input",
-                                    "請提供 destPath 或設定 overwrite=true 以覆蓋原檔",
-                                ));
-                            }
-                        };
-                        let doc = docs.get(&doc_id).ok_or_else(|| {
-                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
-                        })?;
-                        doc.save_to_file(&dest).map_err(|e| {
-                            MediaError::new("io_error", format!("寫入檔案失敗: {e}"))
-                        })?;
-                        paths.insert(doc_id, dest.clone());
-                        let pages = doc.pages().len() as usize;
-                        Ok((dest, pages))
-                    })();
-                    let _ = reply.send(res);
+                None => out.extend(children),
+            },
+            None => {
+                item.children = children;
+                out.push(item);
+            }
+        }
+    }
+    out
+}
+
+// 讀取文件中已寫入的書籤
+fn read_document_outline(
+    doc: &pdfium_render::prelude::PdfDocument,
+    password: Option<&str>,
+) -> Result<Vec<PdfOutlineItem>, MediaError> {
+    if doc.bookmarks().root().is_none() {
+        return Ok(Vec::new());
+    }
+    Ok(read_outline(&load_lopdf(doc, password)?))
+}
+
+// 重建文件（刪頁、反轉、交錯）後保留書籤：sources 為來源文件、其開啟密碼與其頁面在 order 中的起始索引，
+// order[i] 為新文件第 i 頁的來源頁索引。回傳待寫入新文件的書籤，無書籤時為 None
+fn carry_outline(
+    sources: &[(&pdfium_render::prelude::PdfDocument, Option<&str>, u32)],
+    order: &[u32],
+) -> Result<Option<Vec<PdfOutlineItem>>, MediaError> {
+    let mut position = HashMap::new();
+    for (new_idx, src_idx) in order.iter().enumerate() {
+        position.entry(*src_idx).or_insert(new_idx as u32);
+    }
+    let mut items = Vec::new();
+    for (doc, password, offset) in sources {
+        let map: HashMap<u32, u32> = position
+            .iter()
+            .filter_map(|(src, new)| src.checked_sub(*offset).map(|p| (p, *new)))
+            .collect();
+        items.extend(remap_outline(read_document_outline(doc, *password)?, &map));
+    }
+    Ok((!items.is_empty()).then_some(items))
+}
+
+// 將暫存的書籤編輯寫入文件（一次 lopdf 往返）；失敗時保留暫存，儲存時再回報
+fn flush_outline<'a>(
+    pdfium: &'a pdfium_render::prelude::Pdfium,
+    docs: &mut HashMap<u64, pdfium_render::prelude::PdfDocument<'a>>,
+    passwords: &HashMap<u64, String>,
+    outlines: &mut HashMap<u64, Vec<PdfOutlineItem>>,
+    doc_id: u64,
+) -> Result<(), MediaError> {
+    let Some(items) = outlines.get(&doc_id) else {
+        return Ok(());
+    };
+    let Some(doc) = docs.get(&doc_id) else {
+        outlines.remove(&doc_id);
+        return Ok(());
+    };
+    let password = passwords.get(&doc_id).map(String::as_str);
+    let (new_doc, ()) = edit_with_lopdf(pdfium, doc, password, |lo| write_outline(lo, items))?;
+    docs.insert(doc_id, new_doc);
+    outlines.remove(&doc_id);
+    Ok(())
+}
+
+fn set_pending_outline(
+    outlines: &mut HashMap<u64, Vec<PdfOutlineItem>>,
+    doc_id: u64,
+    items: Option<Vec<PdfOutlineItem>>,
+) {
+    match items {
+        Some(items) => {
+            outlines.insert(doc_id, items);
+        }
+        None => {
+            outlines.remove(&doc_id);
+        }
+    }
+}
+
+fn flush_pending_outlines<'a>(
+    pdfium: &'a pdfium_render::prelude::Pdfium,
+    docs: &mut HashMap<u64, pdfium_render::prelude::PdfDocument<'a>>,
+    passwords: &HashMap<u64, String>,
+    outlines: &mut HashMap<u64, Vec<PdfOutlineItem>>,
+) {
+    let pending: Vec<u64> = outlines.keys().copied().collect();
+    for doc_id in pending {
+        if let Err(err) = flush_outline(pdfium, docs, passwords, outlines, doc_id) {
+            warn!(
+                "Failed to write outline for doc {}: {}",
+                doc_id, err.message
+            );
+        }
+    }
+}
+
+// 依路徑（各層子項目索引）取得該節點的子項目清單；空路徑為根層
+fn outline_children_mut<'t>(
+    items: &'t mut Vec<PdfOutlineItem>,
+    path: &[u32],
+) -> Result<&'t mut Vec<PdfOutlineItem>, MediaError> {
+    let mut level = items;
+    for idx in path {
+        level = &mut level
+            .get_mut(*idx as usize)
+            .ok_or_else(|| MediaError::new("not_found", format!("書籤路徑不存在: {:?}", path)))?
+            .children;
+    }
+    Ok(level)
+}
+
+fn validate_outline_pages(items: &[PdfOutlineItem], page_count: u32) -> Result<(), MediaError> {
+    for item in items {
+        if let Some(p) = item.page_index
+            && p >= page_count
+        {
+            return Err(MediaError::new(
+                "invalid_input",
+                format!("書籤目標頁超出範圍: {} >= {}", p, page_count),
+            ));
+        }
+        validate_outline_pages(&item.children, page_count)?;
+    }
+    Ok(())
+}
+
+fn apply_outline_edit(
+    items: &mut Vec<PdfOutlineItem>,
+    edit: OutlineEdit,
+    page_count: u32,
+) -> Result<(), MediaError> {
+    match edit {
+        OutlineEdit::Set(new_items) => {
+            validate_outline_pages(&new_items, page_count)?;
+            *items = new_items;
+        }
+        OutlineEdit::Add {
+            parent_path,
+            index,
+            item,
+        } => {
+            validate_outline_pages(std::slice::from_ref(&item), page_count)?;
+            let level = outline_children_mut(items, &parent_path)?;
+            let at = index
+                .map(|i| i as usize)
+                .unwrap_or(level.len())
+                .min(level.len());
+            level.insert(at, item);
+        }
+        OutlineEdit::Rename { path, title } => {
+            let (last, parent) = path
+                .split_last()
+                .ok_or_else(|| MediaError::new("invalid_input", "缺少書籤路徑"))?;
+            let item = outline_children_mut(items, parent)?
+                .get_mut(*last as usize)
+                .ok_or_else(|| {
+                    MediaError::new("not_found", format!("書籤路徑不存在: {:?}", path))
+                })?;
+            item.title = title;
+        }
+        OutlineEdit::Move {
+            path,
+            new_parent_path,
+            index,
+        } => {
+            if new_parent_path.starts_with(&path) {
+                return Err(MediaError::new(
+                    "invalid_input",
+                    "無法將書籤移至自身的子項目下",
+                ));
+            }
+            let (last, parent) = path
+                .split_last()
+                .ok_or_else(|| MediaError::new("invalid_input", "缺少書籤路徑"))?;
+            let level = outline_children_mut(items, parent)?;
+            if *last as usize >= level.len() {
+                return Err(MediaError::new(
+                    "not_found",
+                    format!("書籤路徑不存在: {:?}", path),
+                ));
+            }
+            let item = level.remove(*last as usize);
+            // new_parent_path 與 index 以移除後的樹為準
+            let target = outline_children_mut(items, &new_parent_path)?;
+            let at = index
+                .map(|i| i as usize)
+                .unwrap_or(target.len())
+                .min(target.len());
+            target.insert(at, item);
+        }
+        OutlineEdit::Delete { path } => {
+            let (last, parent) = path
+                .split_last()
+                .ok_or_else(|| MediaError::new("invalid_input", "缺少書籤路徑"))?;
+            let level = outline_children_mut(items, parent)?;
+            if *last as usize >= level.len() {
+                return Err(MediaError::new(
+                    "not_found",
+                    format!("書籤路徑不存在: {:?}", path),
+                ));
+            }
+            level.remove(*last as usize);
+        }
+    }
+    Ok(())
+}
+
+// 檔頭 "%PDF-x.y"（規範允許前方有少量雜訊，取前 1KB 搜尋）
+fn read_pdf_header_version(path: &Path) -> Option<String> {
+    let mut buf = [0u8; 1024];
+    let n = fs::File::open(path)
+        .and_then(|mut f| f.read(&mut buf))
+        .ok()?;
+    let head = &buf[..n];
+    let pos = head.windows(5).position(|w| w == b"%PDF-")?;
+    let ver: String = head[pos + 5..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit() || **b == b'.')
+        .map(|b| *b as char)
+        .collect();
+    (!ver.is_empty()).then_some(ver)
+}
+
+// Info 字典的標準鍵；其餘字串值視為自訂欄位
+const INFO_STANDARD_KEYS: [&[u8]; 9] = [
+    b"Title",
+    b"Author",
+    b"Subject",
+    b"Keywords",
+    b"Creator",
+    b"Producer",
+    b"CreationDate",
+    b"ModDate",
+    b"Trapped",
+];
+
+fn read_info_dict(doc: &lopdf::Document) -> Option<lopdf::Dictionary> {
+    doc.trailer
+        .get(b"Info")
+        .ok()
+        .and_then(|o| resolve_dict(doc, o))
+}
+
+fn info_text(doc: &lopdf::Document, info: &lopdf::Dictionary, key: &[u8]) -> Option<String> {
+    match info.get(key).ok().and_then(|o| resolve_object(doc, o)) {
+        Some(lopdf::Object::String(bytes, _)) => {
+            let text = decode_pdf_text(&bytes);
+            (!text.is_empty()).then_some(text)
+        }
+        _ => None,
+    }
+}
+
+// PDF 日期 "D:YYYYMMDDHHmmSS+HH'mm'" → ISO 8601；缺少的欄位以最小值補齊
+fn pdf_date_to_iso(raw: &str) -> Option<String> {
+    let s = raw.trim();
+    let s = s.strip_prefix("D:").unwrap_or(s);
+    let digits: String = s.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.len() < 4 {
+        return None;
+    }
+    let field = |start: usize, default: &str| -> String {
+        digits.get(start..start + 2).unwrap_or(default).to_string()
+    };
+    let mut out = format!(
+        "{}-{}-{}T{}:{}:{}",
+        &digits[..4],
+        field(4, "01"),
+        field(6, "01"),
+        field(8, "00"),
+        field(10, "00"),
+        field(12, "00")
+    );
+    let tz = &s[digits.len()..];
+    match tz.chars().next() {
+        Some('Z') => out.push('Z'),
+        Some(sign @ ('+' | '-')) => {
+            let tz_digits: String = tz.chars().filter(|c| c.is_ascii_digit()).collect();
+            let hh = tz_digits.get(0..2).unwrap_or("00");
+            let mm = tz_digits.get(2..4).unwrap_or("00");
+            out.push_str(&format!("{sign}{hh}:{mm}"));
+        }
+        _ => {}
+    }
+    Some(out)
+}
+
+// ISO 8601（YYYY-MM-DD[THH:MM[:SS]][Z|±HH:MM]）→ PDF 日期
+fn iso_to_pdf_date(iso: &str) -> Result<String, MediaError> {
+    let invalid = || {
+        MediaError::new(
+            "invalid_input",
+            format!("日期格式錯誤（需為 ISO 8601）: {}", iso),
+        )
+    };
+    let re = regex::Regex::new(
+        r"^(\d{4})-(\d{2})-(\d{2})(?:[T ](\d{2}):(\d{2})(?::(\d{2}))?(?:\.\d+)?)?(Z|[+-]\d{2}:?\d{2})?$",
+    )
+    .map_err(|_| invalid())?;
+    let caps = re.captures(iso.trim()).ok_or_else(invalid)?;
+    let part = |i: usize, default: &'static str| caps.get(i).map(|m| m.as_str()).unwrap_or(default);
+    let mut out = format!(
+        "D:{}{}{}{}{}{}",
+        part(1, ""),
+        part(2, "01"),
+        part(3, "01"),
+        part(4, "00"),
+        part(5, "00"),
+        part(6, "00")
+    );
+    match caps.get(7).map(|m| m.as_str()) {
+        Some("Z") => out.push('Z'),
+        Some(tz) => {
+            let digits: String = tz.chars().filter(|c| c.is_ascii_digit()).collect();
+            out.push_str(&format!("{}{}'{}'", &tz[..1], &digits[..2], &digits[2..]));
+        }
+        None => {}
+    }
+    Ok(out)
+}
+
+// 目前時間（UTC）的 ISO 8601 字串
+fn now_iso_utc() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // civil-from-days（Howard Hinnant）
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+fn read_xmp(doc: &lopdf::Document) -> Option<String> {
+    let catalog = doc.catalog().ok()?;
+    let id = catalog.get(b"Metadata").ok()?.as_reference().ok()?;
+    let stream = doc.get_object(id).ok()?.as_stream().ok()?;
+    let bytes = stream
+        .decompressed_content()
+        .unwrap_or_else(|_| stream.content.clone());
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// 取 XMP 屬性值：支援元素（含 rdf:Alt/Seq/Bag 清單，以 "; " 串接）與屬性兩種寫法
+fn xmp_value(xmp: &str, tag: &str) -> Option<String> {
+    let tag = regex::escape(tag);
+    let element = regex::Regex::new(&format!(r"(?s)<{tag}(?:\s[^>]*)?>(.*?)</{tag}>")).ok()?;
+    if let Some(caps) = element.captures(xmp) {
+        let inner = caps.get(1)?.as_str();
+        let li = regex::Regex::new(r"(?s)<rdf:li(?:\s[^>]*)?>(.*?)</rdf:li>").ok()?;
+        let items: Vec<String> = li
+            .captures_iter(inner)
+            .filter_map(|c| c.get(1).map(|m| xml_unescape(m.as_str().trim())))
+            .collect();
+        let value = if items.is_empty() {
+            xml_unescape(inner.trim())
+        } else {
+            items.join("; ")
+        };
+        return (!value.is_empty()).then_some(value);
+    }
+    let attr = regex::Regex::new(&format!(r#"{tag}\s*=\s*"([^"]*)""#)).ok()?;
+    attr.captures(xmp)
+        .and_then(|c| c.get(1))
+        .map(|m| xml_unescape(m.as_str()))
+        .filter(|v| !v.is_empty())
+}
+
+const XMP_NS_DC: &str = "http://purl.org/dc/elements/1.1/";
+const XMP_NS_PDF: &str = "http://ns.adobe.com/pdf/1.3/";
+const XMP_NS_XMP: &str = "http://ns.adobe.com/xap/1.0/";
+
+// 由 Info 同步的 XMP 屬性：(命名空間, 慣用前綴, 本地名稱)
+const XMP_SYNCED_PROPS: [(&str, &str, &str); 9] = [
+    (XMP_NS_DC, "dc", "title"),
+    (XMP_NS_DC, "dc", "creator"),
+    (XMP_NS_DC, "dc", "description"),
+    (XMP_NS_PDF, "pdf", "Keywords"),
+    (XMP_NS_PDF, "pdf", "Producer"),
+    (XMP_NS_XMP, "xmp", "CreatorTool"),
+    (XMP_NS_XMP, "xmp", "CreateDate"),
+    (XMP_NS_XMP, "xmp", "ModifyDate"),
+    (XMP_NS_XMP, "xmp", "MetadataDate"),
+];
+
+// 以 Info 內容產生 XMP：沿用原封包時只替換同步的 dc/pdf/xmp 屬性，
+// 其餘內容（xmpMM:DocumentID、pdfaid、自訂 schema 等）原樣保留
+fn build_xmp(meta: &PdfDocMetadata, previous: Option<&str>) -> String {
+    let mut props = String::new();
+    let mut push = |line: String| {
+        props.push_str("   ");
+        props.push_str(&line);
+        props.push('\n');
+    };
+    if let Some(v) = &meta.title {
+        push(format!(
+            "<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>",
+            xml_escape(v)
+        ));
+    }
+    if let Some(v) = &meta.author {
+        push(format!(
+            "<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>",
+            xml_escape(v)
+        ));
+    }
+    if let Some(v) = &meta.subject {
+        push(format!(
+            "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>",
+            xml_escape(v)
+        ));
+    }
+    if let Some(v) = &meta.keywords {
+        push(format!("<pdf:Keywords>{}</pdf:Keywords>", xml_escape(v)));
+    }
+    if let Some(v) = &meta.producer {
+        push(format!("<pdf:Producer>{}</pdf:Producer>", xml_escape(v)));
+    }
+    if let Some(v) = &meta.creator {
+        push(format!(
+            "<xmp:CreatorTool>{}</xmp:CreatorTool>",
+            xml_escape(v)
+        ));
+    }
+    if let Some(v) = &meta.creation_date {
+        push(format!(
+            "<xmp:CreateDate>{}</xmp:CreateDate>",
+            xml_escape(v)
+        ));
+    }
+    if let Some(v) = &meta.mod_date {
+        push(format!(
+            "<xmp:ModifyDate>{}</xmp:ModifyDate>",
+            xml_escape(v)
+        ));
+        push(format!(
+            "<xmp:MetadataDate>{}</xmp:MetadataDate>",
+            xml_escape(v)
+        ));
+    }
+    if let Some(updated) = previous.and_then(|prev| update_xmp_packet(prev, &props)) {
+        return updated;
+    }
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+{}\
+ </rdf:RDF>\n\
+</x:xmpmeta>\n\
+<?xpacket end=\"w\"?>",
+        xmp_description(&props)
+    )
+}
+
+fn xmp_description(props: &str) -> String {
+    format!(
+        "  <rdf:Description rdf:about=\"\"\n    xmlns:dc=\"{XMP_NS_DC}\"\n    xmlns:pdf=\"{XMP_NS_PDF}\"\n    \
+         xmlns:xmp=\"{XMP_NS_XMP}\">\n{props}  </rdf:Description>\n"
+    )
+}
+
+// 移除原封包中的同步屬性（元素與屬性兩種寫法，含非慣用前綴），再於 rdf:RDF 末端加入新的 Description；
+// 封包結構無法辨識時回傳 None
+fn update_xmp_packet(previous: &str, props: &str) -> Option<String> {
+    let end = previous.rfind("</rdf:RDF>")?;
+    let mut body = previous[..end].to_string();
+    for (ns, default_prefix, name) in XMP_SYNCED_PROPS {
+        let declared = regex::Regex::new(&format!(
+            r#"xmlns:([A-Za-z_][\w.-]*)\s*=\s*["']{}["']"#,
+            regex::escape(ns)
+        ))
+        .ok()?;
+        let mut prefixes: Vec<String> = declared
+            .captures_iter(previous)
+            .map(|c| c[1].to_string())
+            .collect();
+        prefixes.push(default_prefix.to_string());
+        prefixes.sort();
+        prefixes.dedup();
+        for prefix in prefixes {
+            let tag = regex::escape(&format!("{prefix}:{name}"));
+            // 先移除自結束元素，其餘開始標籤必有對應的結束標籤
+            for pattern in [
+                format!(r"[ \t]*<{tag}(?:\s[^>]*)?/>[ \t]*\r?\n?"),
+                format!(r"(?s)[ \t]*<{tag}(?:\s[^>]*)?>.*?</{tag}>[ \t]*\r?\n?"),
+                format!(r#"\s+{tag}\s*=\s*(?:"[^"]*"|'[^']*')"#),
+            ] {
+                body = regex::Regex::new(&pattern)
+                    .ok()?
+                    .replace_all(&body, "")
+                    .into_owned();
+            }
+        }
+    }
+    // 新的 Description 插在 </rdf:RDF> 的縮排之前
+    let indent = body.len() - body.trim_end_matches([' ', '\t']).len();
+    let (head, tail) = body.split_at(body.len() - indent);
+    Some(format!(
+        "{head}{}{tail}{}",
+        xmp_description(props),
+        &previous[end..]
+    ))
+}
+
+// 讀取 Info 與 XMP；Info 缺少的欄位以 XMP 補上
+fn read_metadata(doc: &lopdf::Document) -> PdfDocMetadata {
+    let info = read_info_dict(doc);
+    let text = |key: &[u8]| info.as_ref().and_then(|d| info_text(doc, d, key));
+    let xmp = read_xmp(doc);
+    let from_xmp = |tag: &str| xmp.as_deref().and_then(|x| xmp_value(x, tag));
+    let mut custom = BTreeMap::new();
+    if let Some(d) = &info {
+        for (key, _) in d.iter() {
+            if INFO_STANDARD_KEYS.contains(&key.as_slice()) {
+                continue;
+            }
+            if let Some(v) = info_text(doc, d, key) {
+                custom.insert(String::from_utf8_lossy(key).into_owned(), v);
+            }
+        }
+    }
+    // 目錄的 /Version 可覆寫檔頭版本
+    let catalog_version = doc
+        .catalog()
+        .ok()
+        .and_then(|c| c.get(b"Version").ok())
+        .and_then(|v| v.as_name().ok())
+        .map(|n| String::from_utf8_lossy(n).into_owned());
+    let pdf_version = match catalog_version {
+        Some(v) if v.as_str() > doc.version.as_str() => v,
+        _ => doc.version.clone(),
+    };
+    PdfDocMetadata {
+        title: text(b"Title").or_else(|| from_xmp("dc:title")),
+        author: text(b"Author").or_else(|| from_xmp("dc:creator")),
+        subject: text(b"Subject").or_else(|| from_xmp("dc:description")),
+        keywords: text(b"Keywords").or_else(|| from_xmp("pdf:Keywords")),
+        creator: text(b"Creator").or_else(|| from_xmp("xmp:CreatorTool")),
+        producer: text(b"Producer").or_else(|| from_xmp("pdf:Producer")),
+        creation_date: text(b"CreationDate")
+            .and_then(|d| pdf_date_to_iso(&d))
+            .or_else(|| from_xmp("xmp:CreateDate")),
+        mod_date: text(b"ModDate")
+            .and_then(|d| pdf_date_to_iso(&d))
+            .or_else(|| from_xmp("xmp:ModifyDate")),
+        custom,
+        pdf_version,
+        has_xmp: xmp.is_some(),
+    }
+}
+
+fn write_metadata(doc: &mut lopdf::Document, args: &PdfSetMetadataArgs) -> Result<(), MediaError> {
+    use lopdf::{Dictionary, Object, Stream};
+    // 先驗證，避免寫到一半失敗
+    let creation_date = args
+        .creation_date
+        .as_deref()
+        .filter(|d| !d.is_empty())
+        .map(iso_to_pdf_date)
+        .transpose()?;
+    let mod_date = match args.mod_date.as_deref() {
+        Some("") => None,
+        Some(d) => Some(iso_to_pdf_date(d)?),
+        None if args.touch_mod_date.unwrap_or(true) => Some(iso_to_pdf_date(&now_iso_utc())?),
+        None => None,
+    };
+    if let Some(custom) = &args.custom {
+        for key in custom.keys() {
+            if key.is_empty()
+                || INFO_STANDARD_KEYS.contains(&key.as_bytes())
+                || key
+                    .bytes()
+                    .any(|b| !b.is_ascii_graphic() || b"()<>[]{}/%#".contains(&b))
+            {
+                return Err(MediaError::new(
+                    "invalid_input",
+                    format!("無效的自訂欄位名稱: {}", key),
+                ));
+            }
+        }
+    }
+
+    let mut info = read_info_dict(doc).unwrap_or_default();
+    let mut set_text = |key: &str, value: Option<&String>| match value.map(|v| v.as_str()) {
+        Some("") => {
+            info.remove(key.as_bytes());
+        }
+        Some(v) => info.set(key, encode_pdf_text(v)),
+        None => {}
+    };
+    set_text("Title", args.title.as_ref());
+    set_text("Author", args.author.as_ref());
+    set_text("Subject", args.subject.as_ref());
+    set_text("Keywords", args.keywords.as_ref());
+    set_text("Creator", args.creator.as_ref());
+    set_text("Producer", args.producer.as_ref());
+    if let Some(custom) = &args.custom {
+        for (key, value) in custom {
+            set_text(key, Some(value));
+        }
+    }
+    if args.creation_date.as_deref() == Some("") {
+        info.remove(b"CreationDate");
+    } else if let Some(d) = creation_date {
+        info.set("CreationDate", Object::string_literal(d));
+    }
+    if args.mod_date.as_deref() == Some("") {
+        info.remove(b"ModDate");
+    } else if let Some(d) = mod_date {
+        info.set("ModDate", Object::string_literal(d));
+    }
+
+    // Info 以間接物件寫回（沿用原物件編號）
+    let info_id = match doc.trailer.get(b"Info").and_then(Object::as_reference) {
+        Ok(id) => id,
+        Err(_) => doc.new_object_id(),
+    };
+    doc.objects.insert(info_id, Object::Dictionary(info));
+    doc.trailer.set("Info", Object::Reference(info_id));
+
+    if args.xmp.unwrap_or(true) {
+        let previous = read_xmp(doc);
+        // 只取 Info 的值：Info 已移除的欄位不應由舊 XMP 補回
+        let info_only = {
+            let info = read_info_dict(doc);
+            let text = |key: &[u8]| info.as_ref().and_then(|d| info_text(doc, d, key));
+            PdfDocMetadata {
+                title: text(b"Title"),
+                author: text(b"Author"),
+                subject: text(b"Subject"),
+                keywords: text(b"Keywords"),
+                creator: text(b"Creator"),
+                producer: text(b"Producer"),
+                creation_date: text(b"CreationDate").and_then(|d| pdf_date_to_iso(&d)),
+                mod_date: text(b"ModDate").and_then(|d| pdf_date_to_iso(&d)),
+                custom: BTreeMap::new(),
+                pdf_version: doc.version.clone(),
+                has_xmp: true,
+            }
+        };
+        let packet = build_xmp(&info_only, previous.as_deref());
+        let mut dict = Dictionary::new();
+        dict.set("Type", Object::Name(b"Metadata".to_vec()));
+        dict.set("Subtype", Object::Name(b"XML".to_vec()));
+        // XMP 不壓縮，讓不解析 PDF 的工具也能讀取
+        let stream = Object::Stream(Stream::new(dict, packet.into_bytes()));
+        let existing = doc
+            .catalog()
+            .ok()
+            .and_then(|c| c.get(b"Metadata").ok())
+            .and_then(|o| o.as_reference().ok());
+        let xmp_id = existing.unwrap_or_else(|| doc.new_object_id());
+        doc.objects.insert(xmp_id, stream);
+        doc.catalog_mut()
+            .map_err(|e| MediaError::new("parse_error", format!("讀取文件目錄失敗: {e}")))?
+            .set("Metadata", Object::Reference(xmp_id));
+    }
+    Ok(())
+}
+
+// 將 Pdfium 文件序列化後重新加密；原已加密者須以擁有者密碼開啟才能變更加密
+fn encrypt_pdfium_document(
+    doc: &pdfium_render::prelude::PdfDocument,
+    password: Option<&str>,
+    args: &PdfEncryptArgs,
+) -> Result<(Vec<u8>, String), MediaError> {
+    let bytes = doc
+        .save_to_bytes()
+        .map_err(|e| MediaError::new("io_error", format!("序列化 PDF 失敗: {e}")))?;
+    let mut lo = lopdf::Document::load_mem(&bytes)
+        .map_err(|e| MediaError::new("parse_error", format!("解析 PDF 結構失敗: {e}")))?;
+    if lo.is_encrypted() && !decrypt_document(&mut lo, password.unwrap_or(""))?.is_owner {
+        return Err(MediaError::new(
+            "owner_password_required",
+            "變更加密需要擁有者密碼",
+        ));
+    }
+    let reopen = encrypt_document(&mut lo, args)?;
+    let mut out = Vec::new();
+    lo.save_to(&mut out)
+        .map_err(|e| MediaError::new("io_error", format!("寫入 PDF 失敗: {e}")))?;
+    Ok((out, reopen))
+}
+
+// 解析 '#RGB' / '#RRGGBB' 色碼
+fn parse_hex_color(spec: &str) -> Result<(u8, u8, u8), MediaError> {
+    let hex = spec.trim().trim_start_matches('#');
+    let expanded: String = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect(),
+        6 => hex.to_string(),
+        _ => String::new(),
+    };
+    let value = u32::from_str_radix(&expanded, 16)
+        .ok()
+        .filter(|_| !expanded.is_empty())
+        .ok_or_else(|| MediaError::new("invalid_input", format!("無效的色碼: {}", spec)))?;
+    Ok(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+// 仿射矩陣 [a b c d e f]：先套用 m1 再套用 m2
+fn concat_matrix(m1: [f32; 6], m2: [f32; 6]) -> [f32; 6] {
+    [
+        m1[0] * m2[0] + m1[1] * m2[2],
+        m1[0] * m2[1] + m1[1] * m2[3],
+        m1[2] * m2[0] + m1[3] * m2[2],
+        m1[2] * m2[1] + m1[3] * m2[3],
+        m1[4] * m2[0] + m1[5] * m2[2] + m2[4],
+        m1[4] * m2[1] + m1[5] * m2[3] + m2[5],
+    ]
+}
+
+fn invert_matrix(m: [f32; 6]) -> [f32; 6] {
+    let det = m[0] * m[3] - m[1] * m[2];
+    let (a, b, c, d) = (m[3] / det, -m[1] / det, -m[2] / det, m[0] / det);
+    [a, b, c, d, -(m[4] * a + m[5] * c), -(m[4] * b + m[5] * d)]
+}
+
+// 依 PostScript 名稱（如 'Helvetica-Bold'）取得 PDF 標準 14 字型
+fn builtin_font_by_name(name: &str) -> Option<pdfium_render::prelude::PdfFontBuiltin> {
+    use pdfium_render::prelude::PdfFontBuiltin::*;
+    [
+        TimesRoman,
+        TimesBold,
+        TimesItalic,
+        TimesBoldItalic,
+        Helvetica,
+        HelveticaBold,
+        HelveticaOblique,
+        HelveticaBoldOblique,
+        Courier,
+        CourierBold,
+        CourierOblique,
+        CourierBoldOblique,
+        Symbol,
+        ZapfDingbats,
+    ]
+    .into_iter()
+    .find(|f| f.to_pdf_font_name().eq_ignore_ascii_case(name))
+}
+
+// 載入文字物件用字型：指定 TrueType 字型檔，或標準 14 字型（預設 Helvetica）。
+// 標準字型僅涵蓋 Latin-1，sample 含其他字元時回傳 font_required
+fn load_text_font(
+    doc: &mut pdfium_render::prelude::PdfDocument,
+    font: Option<&str>,
+    font_path: Option<&str>,
+    sample: &str,
+) -> Result<pdfium_render::prelude::PdfFontToken, MediaError> {
+    if let Some(path) = font_path {
+        return doc
+            .fonts_mut()
+            .load_true_type_from_file(path, true)
+            .map_err(|e| MediaError::new("io_error", format!("載入字型失敗: {e}")));
+    }
+    let name = font.unwrap_or("Helvetica");
+    let builtin = builtin_font_by_name(name)
+        .ok_or_else(|| MediaError::new("invalid_input", format!("不支援的字型: {}", name)))?;
+    if sample.chars().any(|c| c as u32 > 0xFF) {
+        return Err(MediaError::new(
+            "font_required",
+            "文字含標準字型不支援的字元，請指定 fontPath",
+        ));
+    }
+    Ok(doc.fonts_mut().new_built_in(builtin))
+}
+
+const ANCHOR_POSITIONS: [&str; 9] = [
+    "center",
+    "top-left",
+    "top",
+    "top-right",
+    "left",
+    "right",
+    "bottom-left",
+    "bottom",
+    "bottom-right",
+];
+
+// 依位置名稱計算物件中心（顯示座標，左下為原點）；half_w/half_h 為旋轉後外框的一半
+fn anchor_center(
+    position: &str,
+    page_w: f32,
+    page_h: f32,
+    half_w: f32,
+    half_h: f32,
+    margin: f32,
+) -> Result<(f32, f32), MediaError> {
+    let (left, mid_x, right) = (margin + half_w, page_w / 2.0, page_w - margin - half_w);
+    let (bottom, mid_y, top) = (margin + half_h, page_h / 2.0, page_h - margin - half_h);
+    Ok(match position {
+        "center" => (mid_x, mid_y),
+        "top-left" => (left, top),
+        "top" => (mid_x, top),
+        "top-right" => (right, top),
+        "left" => (left, mid_y),
+        "right" => (right, mid_y),
+        "bottom-left" => (left, bottom),
+        "bottom" => (mid_x, bottom),
+        "bottom-right" => (right, bottom),
+        other => {
+            return Err(MediaError::new(
+                "invalid_input",
+                format!("不支援的位置: {}", other),
+            ));
+        }
+    })
+}
+
+// 在原點附近建立一組文字浮水印物件，每行一個文字物件
+fn create_watermark_objects<'a>(
+    objects: &mut pdfium_render::prelude::PdfPageObjects<'a>,
+    text: (
+        &[&str],
+        pdfium_render::prelude::PdfFontToken,
+        f32,
+        pdfium_render::prelude::PdfColor,
+    ),
+) -> Result<Vec<pdfium_render::prelude::PdfPageObject<'a>>, MediaError> {
+    use pdfium_render::prelude::*;
+    let map_err = |e: PdfiumError| MediaError::new("io_error", format!("建立浮水印失敗: {e}"));
+    let (lines, font, font_size, fill) = text;
+    lines
+        .iter()
+        .map(|line| {
+            let mut obj = objects
+                .create_text_object(
+                    PdfPoints::new(0.0),
+                    PdfPoints::new(0.0),
+                    *line,
+                    font,
+                    PdfPoints::new(font_size),
+                )
+                .map_err(map_err)?;
+            obj.set_fill_color(fill).map_err(map_err)?;
+            Ok(obj)
+        })
+        .collect()
+}
+
+// 影像浮水印：影像以 Pdfium 放在 1×1 pt 的暫存頁，匯入為單一 form XObject，各放置位置以
+// FPDF_NewFormObjectFromXObject 引用同一串流，整份文件只嵌入一份影像（透明度寫入 SMask）。
+// placements 為（0-based 頁索引, 單位方形 → 使用者空間的矩陣）；頁面一經改動即將 touched 設為 true
+fn add_image_watermark(
+    pdfium: &pdfium_render::prelude::Pdfium,
+    doc: &pdfium_render::prelude::PdfDocument,
+    img: &image::DynamicImage,
+    placements: &[(u32, Vec<[f32; 6]>)],
+    under: bool,
+    touched: &mut bool,
+) -> Result<(), MediaError> {
+    use pdfium_render::prelude::*;
+    let map_err = |e: PdfiumError| MediaError::new("io_error", format!("建立浮水印失敗: {e}"));
+    let mut tmp = pdfium.create_new_pdf().map_err(map_err)?;
+    {
+        let unit = PdfPoints::new(1.0);
+        let mut page = tmp
+            .pages_mut()
+            .create_page_at_end(PdfPagePaperSize::Custom(unit, unit))
+            .map_err(map_err)?;
+        page.objects_mut()
+            .create_image_object(
+                PdfPoints::ZERO,
+                PdfPoints::ZERO,
+                img,
+                Some(unit),
+                Some(unit),
+            )
+            .map_err(map_err)?;
+    }
+    let bindings = doc.bindings();
+    // 匯入時已將影像深拷貝進 doc，暫存文件可隨即關閉
+    let xobject = bindings.FPDF_NewXObjectFromPage(
+        bindings.get_handle_from_document(doc),
+        bindings.get_handle_from_document(&tmp),
+        0,
+    );
+    drop(tmp);
+    if xobject.is_null() {
+        return Err(MediaError::new("io_error", "建立浮水印影像失敗"));
+    }
+    let res = (|| -> Result<(), MediaError> {
+        for (index, matrices) in placements {
+            let mut page = doc
+                .pages()
+                .get(to_page_index(*index)?)
+                .map_err(|_| MediaError::new("not_found", format!("頁索引不存在: {}", index)))?;
+            page.set_content_regeneration_strategy(PdfPageContentRegenerationStrategy::Manual);
+            let page_handle = bindings.get_handle_from_page(&page);
+            let original = page.objects().len();
+            for m in matrices {
+                let object = bindings.FPDF_NewFormObjectFromXObject(xobject);
+                if object.is_null() {
+                    return Err(MediaError::new("io_error", "建立浮水印影像失敗"));
                 }
-                Ok(PdfRequest::ImageToPdf {
-                    src_path,
-                    dest_path,
-                    reply,
-                }) => {
-                    let res = (|| -> Result<String, MediaError> {
-                        let p = Path::new(&src_path);
-                        if !p.exists() {
-                            return Err(MediaError::new(
-                                "not_found",
-                                format!("圖片檔案不存在: {}", src_path),
-                            ));
-                        }
+                let fs_matrix = FS_MATRIX {
+                    a: m[0],
+                    b: m[1],
+                    c: m[2],
+                    d: m[3],
+                    e: m[4],
+                    f: m[5],
+                };
+                if !bindings.is_true(bindings.FPDFPageObj_SetMatrix(object, &fs_matrix)) {
+                    bindings.FPDFPageObj_Destroy(object);
+                    return Err(MediaError::new("io_error", "設定影像矩陣失敗"));
+                }
+                // 加入後由頁面持有
+                bindings.FPDFPage_InsertObject(page_handle, object);
+                *touched = true;
+            }
+            // 置於內容下方：將原有物件依序移到浮水印之後
+            if under {
+                for _ in 0..original {
+                    let obj = page
+                        .objects_mut()
+                        .remove_object_at_index(0)
+                        .map_err(map_err)?;
+                    page.objects_mut().add_object(obj).map_err(map_err)?;
+                }
+            }
+            page.regenerate_content().map_err(map_err)?;
+        }
+        Ok(())
+    })();
+    bindings.FPDF_CloseXObject(xobject);
+    res
+}
+
+// AcroForm 欄位旗標（/Ff）
+const FF_READ_ONLY: i64 = 1;
+const FF_REQUIRED: i64 = 1 << 1;
+const FF_MULTILINE: i64 = 1 << 12;
+const FF_RADIO: i64 = 1 << 15;
+const FF_PUSHBUTTON: i64 = 1 << 16;
+const FF_COMBO: i64 = 1 << 17;
+const FF_EDIT: i64 = 1 << 18;
+const FF_MULTISELECT: i64 = 1 << 21;
+
+// 終端欄位：名稱以 '.' 串接各層 /T；/FT、/Ff、/V 可由父欄位繼承
+struct FormFieldNode {
+    id: lopdf::ObjectId,
+    name: String,
+    kind: &'static str,
+    flags: i64,
+    value: Option<lopdf::Object>,
+    widgets: Vec<lopdf::ObjectId>,
+}
+
+fn form_field_kind(ft: &[u8], flags: i64) -> &'static str {
+    match ft {
+        b"Tx" => "text",
+        b"Btn" if flags & FF_PUSHBUTTON != 0 => "button",
+        b"Btn" if flags & FF_RADIO != 0 => "radio",
+        b"Btn" => "checkbox",
+        b"Ch" if flags & FF_COMBO != 0 => "combo",
+        b"Ch" => "list",
+        b"Sig" => "signature",
+        _ => "unknown",
+    }
+}
+
+fn collect_form_fields_level(
+    lo: &lopdf::Document,
+    id: lopdf::ObjectId,
+    parent: (&str, Option<&[u8]>, Option<i64>, Option<&lopdf::Object>),
+    depth: u32,
+    out: &mut Vec<FormFieldNode>,
+) {
+    let Ok(dict) = lo.get_dictionary(id) else {
+        return;
+    };
+    if depth > 32 {
+        return;
+    }
+    let (parent_name, parent_ft, parent_ff, parent_v) = parent;
+    let name = match dict.get(b"T").ok().and_then(|t| resolve_object(lo, t)) {
+        Some(lopdf::Object::String(bytes, _)) => {
+            let t = decode_pdf_text(&bytes);
+            if parent_name.is_empty() {
+                t
+            } else {
+                format!("{}.{}", parent_name, t)
+            }
+        }
+        _ => parent_name.to_string(),
+    };
+    let ft = match dict.get(b"FT") {
+        Ok(lopdf::Object::Name(n)) => Some(n.as_slice()),
+        _ => parent_ft,
+    };
+    let ff = dict
+        .get(b"Ff")
+        .ok()
+        .and_then(|o| o.as_i64().ok())
+        .or(parent_ff);
+    let v = dict.get(b"V").ok().or(parent_v);
+
+    // 具 /T 的子節點為子欄位，其餘為此欄位的 widget
+    let mut widgets = Vec::new();
+    if let Some(lopdf::Object::Array(kids)) =
+        dict.get(b"Kids").ok().and_then(|k| resolve_object(lo, k))
+    {
+        for kid in kids.iter().filter_map(|k| k.as_reference().ok()) {
+            match lo.get_dictionary(kid) {
+                Ok(kd) if kd.has(b"T") => {
+                    collect_form_fields_level(lo, kid, (&name, ft, ff, v), depth + 1, out)
+                }
+                Ok(_) => widgets.push(kid),
+                Err(_) => {}
+            }
+        }
+    } else if matches!(dict.get(b"Subtype"), Ok(lopdf::Object::Name(n)) if n == b"Widget") {
+        widgets.push(id);
+    }
+    if !widgets.is_empty() {
+        let flags = ff.unwrap_or(0);
+        out.push(FormFieldNode {
+            id,
+            name,
+            kind: form_field_kind(ft.unwrap_or_default(), flags),
+            flags,
+            value: v.and_then(|v| resolve_object(lo, v)),
+            widgets,
+        });
+    }
+}
+
+fn collect_form_fields(lo: &lopdf::Document) -> Vec<FormFieldNode> {
+    let mut out = Vec::new();
+    let fields = lo
+        .catalog()
+        .ok()
+        .and_then(|c| c.get(b"AcroForm").ok())
+        .and_then(|a| resolve_dict(lo, a))
+        .and_then(|a| a.get(b"Fields").ok().and_then(|f| resolve_object(lo, f)));
+    if let Some(lopdf::Object::Array(fields)) = fields {
+        for id in fields.iter().filter_map(|f| f.as_reference().ok()) {
+            collect_form_fields_level(lo, id, ("", None, None, None), 0, &mut out);
+        }
+    }
+    out
+}
+
+// widget 物件 → 0-based 頁索引（依各頁 /Annots）
+fn annotation_page_map(lo: &lopdf::Document) -> HashMap<lopdf::ObjectId, u32> {
+    let mut map = HashMap::new();
+    for (i, page_id) in lo.get_pages().into_values().enumerate() {
+        let annots = lo
+            .get_dictionary(page_id)
+            .ok()
+            .and_then(|p| p.get(b"Annots").ok())
+            .and_then(|a| resolve_object(lo, a));
+        if let Some(lopdf::Object::Array(annots)) = annots {
+            for id in annots.iter().filter_map(|a| a.as_reference().ok()) {
+                map.entry(id).or_insert(i as u32);
+            }
+        }
+    }
+    map
+}
+
+fn pdf_rect(obj: Option<&lopdf::Object>) -> Option<PdfBox> {
+    let lopdf::Object::Array(items) = obj? else {
+        return None;
+    };
+    let v: Vec<f32> = items.iter().filter_map(pdf_number).collect();
+    if v.len() != 4 {
+        return None;
+    }
+    Some(PdfBox {
+        left: v[0].min(v[2]),
+        bottom: v[1].min(v[3]),
+        right: v[0].max(v[2]),
+        top: v[1].max(v[3]),
+    })
+}
+
+// 核取方塊/選項按鈕 widget 的開啟狀態名稱（/AP /N 中 Off 以外的鍵）
+fn widget_on_states(lo: &lopdf::Document, widget: lopdf::ObjectId) -> Vec<String> {
+    lo.get_dictionary(widget)
+        .ok()
+        .and_then(|w| w.get(b"AP").ok())
+        .and_then(|ap| resolve_dict(lo, ap))
+        .and_then(|ap| ap.get(b"N").ok().and_then(|n| resolve_dict(lo, n)))
+        .map(|n| {
+            n.iter()
+                .filter(|(k, _)| k.as_slice() != b"Off")
+                .map(|(k, _)| String::from_utf8_lossy(k).into_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// 選擇欄位 /Opt：字串或 [匯出值, 顯示文字]
+fn choice_options(lo: &lopdf::Document, field: lopdf::ObjectId) -> Vec<PdfFormOption> {
+    let opt = lo
+        .get_dictionary(field)
+        .ok()
+        .and_then(|d| d.get(b"Opt").ok())
+        .and_then(|o| resolve_object(lo, o));
+    let Some(lopdf::Object::Array(items)) = opt else {
+        return Vec::new();
+    };
+    items
+        .iter()
+        .filter_map(|item| match resolve_object(lo, item)? {
+            lopdf::Object::String(s, _) => {
+                let text = decode_pdf_text(&s);
+                Some(PdfFormOption {
+                    value: text.clone(),
+                    label: text,
+                })
+            }
+            lopdf::Object::Array(pair) => {
+                let text = |i: usize| match pair.get(i).and_then(|o| resolve_object(lo, o)) {
+                    Some(lopdf::Object::String(s, _)) => Some(decode_pdf_text(&s)),
+                    _ => None,
+                };
+                let value = text(0)?;
+                Some(PdfFormOption {
+                    label: text(1).unwrap_or_else(|| value.clone()),
+                    value,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn form_value_from_object(obj: &lopdf::Object) -> Option<PdfFormValue> {
+    match obj {
+        lopdf::Object::String(s, _) => Some(PdfFormValue::Text(decode_pdf_text(s))),
+        lopdf::Object::Name(n) => Some(PdfFormValue::Text(String::from_utf8_lossy(n).into_owned())),
+        lopdf::Object::Array(items) => Some(PdfFormValue::List(
+            items
+                .iter()
+                .filter_map(|o| match o {
+                    lopdf::Object::String(s, _) => Some(decode_pdf_text(s)),
+                    _ => None,
+                })
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+fn read_form_fields(lo: &lopdf::Document) -> Vec<PdfFormFieldInfo> {
+    let pages = annotation_page_map(lo);
+    collect_form_fields(lo)
+        .into_iter()
+        .map(|f| {
+            let options = match f.kind {
+                "combo" | "list" => choice_options(lo, f.id),
+                "checkbox" | "radio" => {
+                    let mut states: Vec<String> = Vec::new();
+                    for w in &f.widgets {
+                        for s in widget_on_states(lo, *w) {
+                            if !states.contains(&s) {
+                                states.push(s);
+                            }
+                        }
+                    }
+                    states
+                        .into_iter()
+                        .map(|s| PdfFormOption {
+                            value: s.clone(),
+                            label: s,
+                        })
+                        .collect()
+                }
+                _ => Vec::new(),
+            };
+            let widgets = f
+                .widgets
+                .iter()
+                .map(|w| PdfFormWidgetInfo {
+                    page_index: pages.get(w).copied(),
+                    rect: lo
+                        .get_dictionary(*w)
+                        .ok()
+                        .and_then(|d| pdf_rect(d.get(b"Rect").ok())),
+                })
+                .collect();
+            PdfFormFieldInfo {
+                name: f.name,
+                field_type: f.kind.to_string(),
+                value: f.value.as_ref().and_then(form_value_from_object),
+                options,
+                read_only: f.flags & FF_READ_ONLY != 0,
+                required: f.flags & FF_REQUIRED != 0,
+                multiline: f.kind == "text" && f.flags & FF_MULTILINE != 0,
+                multi_select: f.kind == "list" && f.flags & FF_MULTISELECT != 0,
+                widgets,
+            }
+        })
+        .collect()
+}
+
+// 由 widget 沿 /Parent 向上取得可繼承的欄位屬性（/DA、/Q），最後退回 AcroForm
+fn inherited_field_attr(
+    lo: &lopdf::Document,
+    widget: lopdf::ObjectId,
+    key: &[u8],
+) -> Option<lopdf::Object> {
+    let mut node = lo.get_dictionary(widget).ok();
+    for _ in 0..32 {
+        let n = node?;
+        if let Some(v) = n.get(key).ok().and_then(|v| resolve_object(lo, v)) {
+            return Some(v);
+        }
+        match n.get(b"Parent").ok().and_then(|p| p.as_reference().ok()) {
+            Some(id) => node = lo.get_dictionary(id).ok(),
+            None => break,
+        }
+    }
+    lo.catalog()
+        .ok()
+        .and_then(|c| c.get(b"AcroForm").ok())
+        .and_then(|a| resolve_dict(lo, a))
+        .and_then(|a| a.get(key).ok().and_then(|v| resolve_object(lo, v)))
+}
+
+// 依 /DA 的字型與字級產生文字/選擇欄位的外觀串流（/AP /N），lines 為各行顯示文字。
+// 字型取自 AcroForm /DR：簡單字型以 Latin-1 編碼，Type0 字型僅支援 UCS-2 CMap；無法編碼時回傳 false
+fn write_field_appearance(
+    lo: &mut lopdf::Document,
+    widget: lopdf::ObjectId,
+    lines: &[String],
+    multiline: bool,
+) -> bool {
+    use lopdf::{Dictionary, Object, Stream};
+    let Some(rect) = lo
+        .get_dictionary(widget)
+        .ok()
+        .and_then(|w| pdf_rect(w.get(b"Rect").ok()))
+    else {
+        return false;
+    };
+    let (w, h) = (rect.right - rect.left, rect.top - rect.bottom);
+    let da = match inherited_field_attr(lo, widget, b"DA") {
+        Some(Object::String(s, _)) => String::from_utf8_lossy(&s).into_owned(),
+        _ => String::new(),
+    };
+    // /DA 例如 "/Helv 0 Tf 0 g"：取出字型與字級，其餘（顏色）原樣保留
+    let tokens: Vec<&str> = da.split_whitespace().collect();
+    let (font_name, mut size, color) = match tokens.iter().position(|t| *t == "Tf") {
+        Some(i) if i >= 2 => (
+            tokens[i - 2].trim_start_matches('/').to_string(),
+            tokens[i - 1].parse::<f32>().unwrap_or(0.0),
+            tokens[..i - 2]
+                .iter()
+                .chain(&tokens[i + 1..])
+                .copied()
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        _ => ("Helv".to_string(), 0.0, "0 g".to_string()),
+    };
+    let dr_font = lo
+        .catalog()
+        .ok()
+        .and_then(|c| c.get(b"AcroForm").ok())
+        .and_then(|a| resolve_dict(lo, a))
+        .and_then(|a| a.get(b"DR").ok().and_then(|d| resolve_dict(lo, d)))
+        .and_then(|dr| dr.get(b"Font").ok().and_then(|f| resolve_dict(lo, f)))
+        .and_then(|fonts| fonts.get(font_name.as_bytes()).ok().cloned());
+    let font_obj = dr_font.unwrap_or_else(|| {
+        let mut helv = Dictionary::new();
+        helv.set("Type", Object::Name(b"Font".to_vec()));
+        helv.set("Subtype", Object::Name(b"Type1".to_vec()));
+        helv.set("BaseFont", Object::Name(b"Helvetica".to_vec()));
+        helv.set("Encoding", Object::Name(b"WinAnsiEncoding".to_vec()));
+        Object::Reference(lo.add_object(helv))
+    });
+    let Some(font) = resolve_dict(lo, &font_obj) else {
+        return false;
+    };
+
+    // 編碼為字型的字碼並估算寬度（千分之一字級）
+    let ucs2 = font.get(b"Subtype").ok().and_then(|s| s.as_name().ok()) == Some(b"Type0");
+    if ucs2
+        && !font
+            .get(b"Encoding")
+            .ok()
+            .and_then(|e| e.as_name().ok())
+            .is_some_and(|e| e.ends_with(b"UCS2-H"))
+    {
+        return false;
+    }
+    let first_char = dict_get_i64(lo, &font, b"FirstChar").unwrap_or(0);
+    let widths: Vec<f32> = match font.get(b"Widths").ok().and_then(|o| resolve_object(lo, o)) {
+        Some(Object::Array(items)) => items
+            .iter()
+            .map(|o| pdf_number(o).unwrap_or(500.0))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let mut encoded = Vec::with_capacity(lines.len());
+    for line in lines {
+        let mut bytes = Vec::new();
+        let mut width = 0.0;
+        for c in line.chars() {
+            let code = c as u32;
+            if ucs2 {
+                if code > 0xFFFF {
+                    return false;
+                }
+                bytes.extend((code as u16).to_be_bytes());
+                width += if code < 0x80 { 500.0 } else { 1000.0 };
+            } else {
+                if code > 0xFF {
+                    return false;
+                }
+                bytes.push(code as u8);
+                width += widths
+                    .get((code as i64 - first_char) as usize)
+                    .copied()
+                    .unwrap_or(500.0);
+            }
+        }
+        encoded.push((bytes, width));
+    }
+
+    // 字級 0 為自動：單行依高度並縮小至可容納，多行固定 12
+    let widest = encoded.iter().map(|(_, wd)| *wd).fold(0.0, f32::max);
+    if size <= 0.0 {
+        size = if multiline {
+            12.0
+        } else {
+            ((h - 4.0) / 1.15).clamp(4.0, 12.0)
+        };
+        if !multiline && widest > 0.0 {
+            size = size.min((w - 4.0) * 1000.0 / widest).max(4.0);
+        }
+    }
+    let quadding = match inherited_field_attr(lo, widget, b"Q") {
+        Some(Object::Integer(q)) => q,
+        _ => 0,
+    };
+    let mut ops = format!(
+        "/Tx BMC q 1 1 {} {} re W n BT /{} {} Tf {}\n",
+        w - 2.0,
+        h - 2.0,
+        font_name,
+        size,
+        color
+    );
+    for (i, (bytes, width)) in encoded.iter().enumerate() {
+        let tw = width * size / 1000.0;
+        let x = match quadding {
+            1 => (w - tw) / 2.0,
+            2 => w - 2.0 - tw,
+            _ => 2.0,
+        };
+        let y = if multiline {
+            h - 2.0 - size - i as f32 * size * 1.15
+        } else {
+            (h - size) / 2.0 + size * 0.22
+        };
+        let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        ops.push_str(&format!("1 0 0 1 {} {} Tm <{}> Tj\n", x, y, hex));
+    }
+    ops.push_str("ET Q EMC\n");
+
+    let mut fonts = Dictionary::new();
+    fonts.set(font_name.into_bytes(), font_obj);
+    let mut resources = Dictionary::new();
+    resources.set("Font", Object::Dictionary(fonts));
+    let mut dict = Dictionary::new();
+    dict.set("Type", Object::Name(b"XObject".to_vec()));
+    dict.set("Subtype", Object::Name(b"Form".to_vec()));
+    dict.set(
+        "BBox",
+        Object::Array(vec![0.into(), 0.into(), w.into(), h.into()]),
+    );
+    dict.set("Resources", Object::Dictionary(resources));
+    let stream_id = lo.add_object(Stream::new(dict, ops.into_bytes()));
+    let mut ap = Dictionary::new();
+    ap.set("N", Object::Reference(stream_id));
+    match lo.get_dictionary_mut(widget) {
+        Ok(widget) => {
+            widget.set("AP", Object::Dictionary(ap));
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+// 文字/選擇欄位目前值的顯示文字（各行）；選擇欄位顯示選項標籤
+fn field_display_lines(
+    lo: &lopdf::Document,
+    field: &FormFieldNode,
+    value: Option<&lopdf::Object>,
+) -> Vec<String> {
+    let values = match value.and_then(form_value_from_object) {
+        Some(PdfFormValue::Text(s)) => vec![s],
+        Some(PdfFormValue::List(items)) => items,
+        _ => Vec::new(),
+    };
+    match field.kind {
+        "text" if field.flags & FF_MULTILINE != 0 => values
+            .iter()
+            .flat_map(|v| v.lines().map(str::to_string))
+            .collect(),
+        "text" => values
+            .iter()
+            .map(|v| v.replace(['\r', '\n'], " "))
+            .collect(),
+        _ => {
+            let options = choice_options(lo, field.id);
+            values
+                .into_iter()
+                .map(|v| {
+                    options
+                        .iter()
+                        .find(|o| o.value == v)
+                        .map(|o| o.label.clone())
+                        .unwrap_or(v)
+                })
+                .collect()
+        }
+    }
+}
+
+// 填入欄位值；文字與選擇欄位依 /DA 重新產生外觀，字型無法編碼時移除外觀並設定 NeedAppearances 交由檢視器產生
+fn fill_form_fields(
+    lo: &mut lopdf::Document,
+    values: &HashMap<String, PdfFormValue>,
+) -> Result<(), MediaError> {
+    use lopdf::Object;
+    let fields = collect_form_fields(lo);
+    let mut need_appearances = false;
+    for (name, value) in values {
+        let field = fields
+            .iter()
+            .find(|f| &f.name == name)
+            .ok_or_else(|| MediaError::new("not_found", format!("表單欄位不存在: {}", name)))?;
+        if field.flags & FF_READ_ONLY != 0 {
+            return Err(MediaError::new(
+                "read_only",
+                format!("欄位為唯讀: {}", name),
+            ));
+        }
+        let invalid = || MediaError::new("invalid_input", format!("欄位 {} 的值無效", name));
+        let (v, states): (Object, Option<String>) = match (field.kind, value) {
+            ("text", PdfFormValue::Text(s)) => (encode_pdf_text(s), None),
+            ("checkbox" | "radio", v) => {
+                let on: Vec<String> = field
+                    .widgets
+                    .iter()
+                    .flat_map(|w| widget_on_states(lo, *w))
+                    .collect();
+                let state = match v {
+                    PdfFormValue::Bool(false) => "Off".to_string(),
+                    PdfFormValue::Bool(true) if field.kind == "checkbox" => {
+                        on.first().cloned().unwrap_or_else(|| "Yes".to_string())
+                    }
+                    PdfFormValue::Text(s) if s == "Off" || on.contains(s) => s.clone(),
+                    _ => return Err(invalid()),
+                };
+                (Object::Name(state.clone().into_bytes()), Some(state))
+            }
+            ("combo" | "list", v) => {
+                let options = choice_options(lo, field.id);
+                let selected: Vec<&String> = match v {
+                    PdfFormValue::Text(s) => vec![s],
+                    PdfFormValue::List(items)
+                        if field.kind == "list" && field.flags & FF_MULTISELECT != 0 =>
+                    {
+                        items.iter().collect()
+                    }
+                    _ => return Err(invalid()),
+                };
+                let free_text = field.kind == "combo" && field.flags & FF_EDIT != 0;
+                let mut indices = Vec::new();
+                for s in &selected {
+                    match options.iter().position(|o| &o.value == *s) {
+                        Some(i) => indices.push(Object::Integer(i as i64)),
+                        None if free_text || options.is_empty() => {}
+                        None => return Err(invalid()),
+                    }
+                }
+                let dict = lo.get_dictionary_mut(field.id).map_err(|_| invalid())?;
+                if indices.is_empty() {
+                    dict.remove(b"I");
+                } else {
+                    dict.set("I", Object::Array(indices));
+                }
+                let v = match selected.as_slice() {
+                    [one] => encode_pdf_text(one),
+                    many => Object::Array(many.iter().map(|s| encode_pdf_text(s)).collect()),
+                };
+                (v, None)
+            }
+            ("text", _) => return Err(invalid()),
+            (kind, _) => {
+                return Err(MediaError::new(
+                    "unsupported",
+                    format!("不支援填寫此類欄位（{}）: {}", kind, name),
+                ));
+            }
+        };
+        let lines = states
+            .is_none()
+            .then(|| field_display_lines(lo, field, Some(&v)));
+        let multiline =
+            field.kind == "list" || (field.kind == "text" && field.flags & FF_MULTILINE != 0);
+        lo.get_dictionary_mut(field.id)
+            .map_err(|_| invalid())?
+            .set("V", v);
+        for w in &field.widgets {
+            if let Some(lines) = &lines {
+                if !write_field_appearance(lo, *w, lines, multiline) {
+                    if let Ok(widget) = lo.get_dictionary_mut(*w) {
+                        widget.remove(b"AP");
+                    }
+                    need_appearances = true;
+                }
+                continue;
+            }
+            let has_state = states
+                .as_ref()
+                .map(|s| widget_on_states(lo, *w).contains(s));
+            let Ok(widget) = lo.get_dictionary_mut(*w) else {
+                continue;
+            };
+            match (&states, has_state) {
+                (Some(s), Some(true)) => widget.set("AS", Object::Name(s.clone().into_bytes())),
+                _ => widget.set("AS", Object::Name(b"Off".to_vec())),
+            }
+        }
+    }
+    if !need_appearances {
+        return Ok(());
+    }
+
+    let catalog = lo
+        .catalog()
+        .map_err(|e| MediaError::new("parse_error", format!("讀取文件目錄失敗: {e}")))?;
+    match catalog.get(b"AcroForm") {
+        Ok(Object::Reference(id)) => {
+            let id = *id;
+            if let Ok(form) = lo.get_dictionary_mut(id) {
+                form.set("NeedAppearances", Object::Boolean(true));
+            }
+        }
+        Ok(Object::Dictionary(_)) => {
+            if let Ok(Object::Dictionary(form)) =
+                lo.catalog_mut().and_then(|c| c.get_mut(b"AcroForm"))
+            {
+                form.set("NeedAppearances", Object::Boolean(true));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// 繼承而來的資源先複製到頁面，避免 add_xobject 建立空的 Resources
+fn ensure_page_resources(lo: &mut lopdf::Document, page_id: lopdf::ObjectId) {
+    if !lo
+        .get_dictionary(page_id)
+        .is_ok_and(|p| !p.has(b"Resources"))
+    {
+        return;
+    }
+    let mut node = lo.get_dictionary(page_id).ok();
+    let mut inherited = None;
+    for _ in 0..32 {
+        let Some(n) = node else { break };
+        if let Some(res) = n.get(b"Resources").ok().and_then(|r| resolve_dict(lo, r)) {
+            inherited = Some(res);
+            break;
+        }
+        node = n
+            .get(b"Parent")
+            .ok()
+            .and_then(|p| p.as_reference().ok())
+            .and_then(|id| lo.get_dictionary(id).ok());
+    }
+    let inherited = lopdf::Object::Dictionary(inherited.unwrap_or_default());
+    if let Ok(page) = lo.get_dictionary_mut(page_id) {
+        page.set("Resources", inherited);
+    }
+}
+
+// 以 q/Q 包住原有內容，避免未平衡的繪圖狀態影響新增內容；under 畫在原內容之前，over 畫在之後
+fn wrap_page_content(lo: &mut lopdf::Document, page_id: lopdf::ObjectId, under: &str, over: &str) {
+    use lopdf::{Dictionary, Object, Stream};
+    let mut contents: Vec<Object> = lo
+        .get_page_contents(page_id)
+        .into_iter()
+        .map(Object::Reference)
+        .collect();
+    let head = lo.add_object(Stream::new(
+        Dictionary::new(),
+        format!("{}q\n", under).into_bytes(),
+    ));
+    let tail = lo.add_object(Stream::new(
+        Dictionary::new(),
+        format!("Q\n{}", over).into_bytes(),
+    ));
+    contents.insert(0, Object::Reference(head));
+    contents.push(Object::Reference(tail));
+    if let Ok(page) = lo.get_dictionary_mut(page_id) {
+        page.set("Contents", Object::Array(contents));
+    }
+}
+
+// 將 select 選中的註解外觀串流畫入頁面內容並自 /Annots 移除；無外觀、隱藏者與 Popup 直接移除。回傳畫入的註解數
+fn flatten_page_annotations(lo: &mut lopdf::Document, select: impl Fn(&[u8]) -> bool) -> usize {
+    use lopdf::Object;
+    let mut flattened = 0;
+    for page_id in lo.get_pages().into_values().collect::<Vec<_>>() {
+        let annots = lo
+            .get_dictionary(page_id)
+            .ok()
+            .and_then(|p| p.get(b"Annots").ok())
+            .and_then(|a| resolve_object(lo, a));
+        let Some(Object::Array(annots)) = annots else {
+            continue;
+        };
+        let mut keep = Vec::new();
+        let mut ops = Vec::new();
+        for annot in annots {
+            let Some(widget) = annot
+                .as_reference()
+                .ok()
+                .and_then(|id| lo.get_dictionary(id).ok())
+            else {
+                keep.push(annot);
+                continue;
+            };
+            let subtype = widget
+                .get(b"Subtype")
+                .and_then(|s| s.as_name())
+                .unwrap_or(b"");
+            if !select(subtype) {
+                keep.push(annot);
+                continue;
+            }
+            // /F：Hidden(2)、NoView(32)；Popup 僅為註解的彈出視窗，不畫入內容
+            let hidden = widget
+                .get(b"F")
+                .ok()
+                .and_then(|f| f.as_i64().ok())
+                .unwrap_or(0)
+                & (2 | 32)
+                != 0
+                || subtype == b"Popup";
+            let ap = widget
+                .get(b"AP")
+                .ok()
+                .and_then(|ap| resolve_dict(lo, ap))
+                .and_then(|ap| ap.get(b"N").ok().cloned());
+            let stream_id = match ap {
+                Some(Object::Reference(id))
+                    if matches!(lo.get_object(id), Ok(Object::Stream(_))) =>
+                {
+                    Some(id)
+                }
+                Some(n) => {
+                    let state = widget
+                        .get(b"AS")
+                        .ok()
+                        .and_then(|s| s.as_name().ok())
+                        .unwrap_or(b"Off");
+                    resolve_dict(lo, &n).and_then(|states| {
+                        states.get(state).ok().and_then(|s| s.as_reference().ok())
+                    })
+                }
+                None => None,
+            };
+            let (Some(stream_id), Some(rect), false) =
+                (stream_id, pdf_rect(widget.get(b"Rect").ok()), hidden)
+            else {
+                continue;
+            };
+            let Ok(Object::Stream(stream)) = lo.get_object_mut(stream_id) else {
+                continue;
+            };
+            // 外觀串流以 Matrix 轉換後的 BBox 對齊 Rect（PDF 規範 12.5.5）
+            let bbox = pdf_rect(stream.dict.get(b"BBox").ok()).unwrap_or(PdfBox {
+                left: 0.0,
+                bottom: 0.0,
+                right: 1.0,
+                top: 1.0,
+            });
+            let m: Vec<f32> = match stream.dict.get(b"Matrix") {
+                Ok(Object::Array(items)) => items.iter().filter_map(pdf_number).collect(),
+                _ => Vec::new(),
+            };
+            let m = <[f32; 6]>::try_from(m).unwrap_or([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+            let corners = [
+                (bbox.left, bbox.bottom),
+                (bbox.right, bbox.bottom),
+                (bbox.left, bbox.top),
+                (bbox.right, bbox.top),
+            ]
+            .map(|(x, y)| (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5]));
+            let (x0, x1) = corners
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(a, b), c| (a.min(c.0), b.max(c.0)));
+            let (y0, y1) = corners
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(a, b), c| (a.min(c.1), b.max(c.1)));
+            if x1 - x0 <= 0.0 || y1 - y0 <= 0.0 {
+                continue;
+            }
+            stream.dict.set("Type", Object::Name(b"XObject".to_vec()));
+            stream.dict.set("Subtype", Object::Name(b"Form".to_vec()));
+            let (sx, sy) = (
+                (rect.right - rect.left) / (x1 - x0),
+                (rect.top - rect.bottom) / (y1 - y0),
+            );
+            let name = format!("Flat{}_{}", stream_id.0, stream_id.1);
+            ops.push(format!(
+                "q {} 0 0 {} {} {} cm /{} Do Q\n",
+                sx,
+                sy,
+                rect.left - sx * x0,
+                rect.bottom - sy * y0,
+                name
+            ));
+            ensure_page_resources(lo, page_id);
+            let _ = lo.add_xobject(page_id, name.into_bytes(), stream_id);
+            flattened += 1;
+        }
+        if !ops.is_empty() {
+            wrap_page_content(lo, page_id, "", &ops.concat());
+        }
+        if let Ok(page) = lo.get_dictionary_mut(page_id) {
+            if keep.is_empty() {
+                page.remove(b"Annots");
+            } else {
+                page.set("Annots", Object::Array(keep));
+            }
+        }
+    }
+    flattened
+}
+
+// 將 widget 畫入頁面內容並移除表單。回傳畫入的 widget 數
+fn flatten_form_widgets(lo: &mut lopdf::Document) -> Result<usize, MediaError> {
+    // 有值的文字/選擇欄位須有外觀才能畫入頁面：缺少（或 NeedAppearances 要求重建）時於此產生，
+    // 缺少且無法產生時回報錯誤，避免平面化後值消失
+    let need_appearances = lo
+        .catalog()
+        .ok()
+        .and_then(|c| c.get(b"AcroForm").ok())
+        .and_then(|a| resolve_dict(lo, a))
+        .is_some_and(|a| matches!(a.get(b"NeedAppearances"), Ok(lopdf::Object::Boolean(true))));
+    for field in collect_form_fields(lo) {
+        if !matches!(field.kind, "text" | "combo" | "list") {
+            continue;
+        }
+        let lines = field_display_lines(lo, &field, field.value.as_ref());
+        if lines.iter().all(|l| l.is_empty()) {
+            continue;
+        }
+        let multiline =
+            field.kind == "list" || (field.kind == "text" && field.flags & FF_MULTILINE != 0);
+        for w in &field.widgets {
+            let has_ap = lo
+                .get_dictionary(*w)
+                .ok()
+                .and_then(|d| d.get(b"AP").ok())
+                .and_then(|ap| resolve_dict(lo, ap))
+                .is_some_and(|ap| ap.has(b"N"));
+            if has_ap && !need_appearances {
+                continue;
+            }
+            // 既有外觀在重建失敗時仍可沿用
+            if !write_field_appearance(lo, *w, &lines, multiline) && !has_ap {
+                return Err(MediaError::new(
+                    "font_required",
+                    format!("欄位 {} 的字型無法顯示填入的文字，無法平面化", field.name),
+                ));
+            }
+        }
+    }
+    let flattened = flatten_page_annotations(lo, |subtype| subtype == b"Widget");
+    if let Ok(catalog) = lo.catalog_mut() {
+        catalog.remove(b"AcroForm");
+    }
+    lo.prune_objects();
+    Ok(flattened)
+}
+
+// 以 Pdfium 渲染含選中註解的頁面：渲染時會為缺少外觀串流的註解（如剛填寫的文字欄位、無 /AP 的螢光標記）產生 /AP
+fn generate_missing_appearances(
+    doc: &pdfium_render::prelude::PdfDocument,
+    select: impl Fn(pdfium_render::prelude::PdfPageAnnotationType) -> bool,
+) -> Result<(), MediaError> {
+    use pdfium_render::prelude::*;
+    let cfg = PdfRenderConfig::new()
+        .set_target_width(16)
+        .render_annotations(true)
+        .render_form_data(doc.form().is_some());
+    for page in doc.pages().iter() {
+        if page
+            .annotations()
+            .iter()
+            .any(|a| select(a.annotation_type()))
+        {
+            page.render_with_config(&cfg)
+                .map_err(|e| MediaError::new("parse_error", format!("產生註解外觀失敗: {e}")))?;
+        }
+    }
+    Ok(())
+}
+
+// 平面化表單：先讓 Pdfium 產生缺少的欄位外觀，再畫入頁面內容
+fn flatten_document_forms<'a>(
+    pdfium: &'a pdfium_render::prelude::Pdfium,
+    doc: &pdfium_render::prelude::PdfDocument,
+    password: Option<&str>,
+) -> Result<(pdfium_render::prelude::PdfDocument<'a>, usize), MediaError> {
+    use pdfium_render::prelude::*;
+    if doc.form().is_some() {
+        generate_missing_appearances(doc, |t| t == PdfPageAnnotationType::Widget)?;
+    }
+    edit_with_lopdf(pdfium, doc, password, flatten_form_widgets)
+}
+
+fn page_annotation_list(lo: &lopdf::Document, page_id: lopdf::ObjectId) -> Vec<lopdf::Object> {
+    let annots = lo
+        .get_dictionary(page_id)
+        .ok()
+        .and_then(|p| p.get(b"Annots").ok())
+        .and_then(|a| resolve_object(lo, a));
+    match annots {
+        Some(lopdf::Object::Array(items)) => items,
+        _ => Vec::new(),
+    }
+}
+
+// 註解類型 → 前端類型名稱（同 /Subtype，首字小寫，如 FreeText → freeText）
+fn annotation_kind(kind: pdfium_render::prelude::PdfPageAnnotationType) -> &'static str {
+    use pdfium_render::prelude::PdfPageAnnotationType as T;
+    match kind {
+        T::Text => "text",
+        T::Link => "link",
+        T::FreeText => "freeText",
+        T::Line => "line",
+        T::Square => "square",
+        T::Circle => "circle",
+        T::Polygon => "polygon",
+        T::Polyline => "polyLine",
+        T::Highlight => "highlight",
+        T::Underline => "underline",
+        T::Squiggly => "squiggly",
+        T::Strikeout => "strikeout",
+        T::Stamp => "stamp",
+        T::Caret => "caret",
+        T::Ink => "ink",
+        T::Popup => "popup",
+        T::FileAttachment => "fileAttachment",
+        T::Sound => "sound",
+        T::Movie => "movie",
+        T::Widget | T::XfaWidget => "widget",
+        T::Screen => "screen",
+        T::PrinterMark => "printerMark",
+        T::TrapNet => "trapNet",
+        T::Watermark => "watermark",
+        T::ThreeD => "3D",
+        T::RichMedia => "richMedia",
+        T::Redacted => "redact",
+        T::Unknown => "unknown",
+    }
+}
+
+// fpdf_annot.h 的 FPDFANNOT_COLORTYPE 與 fpdf_edit.h 的物件類型
+const FPDFANNOT_COLORTYPE_COLOR: u32 = 0;
+const FPDFANNOT_COLORTYPE_INTERIOR_COLOR: u32 = 1;
+const FPDF_PAGEOBJ_TEXT: i32 = 1;
+const FPDF_PAGEOBJ_PATH: i32 = 2;
+
+// 註解的線條色與填色（'#RRGGBB'）。有外觀串流時 Pdfium 不回傳 /C、/IC，
+// 改由外觀中的物件取色：文字色（自由文字）、筆畫色與填色
+fn annotation_colors(
+    bindings: &dyn pdfium_render::prelude::PdfiumLibraryBindings,
+    handle: pdfium_render::prelude::FPDF_ANNOTATION,
+    kind: &str,
+) -> (Option<String>, Option<String>) {
+    let hex = |(r, g, b): (u32, u32, u32)| format!("#{:02X}{:02X}{:02X}", r, g, b);
+    let annot_color = |color_type| {
+        let (mut r, mut g, mut b, mut a) = (0, 0, 0, 0);
+        bindings
+            .is_true(
+                bindings.FPDFAnnot_GetColor(handle, color_type, &mut r, &mut g, &mut b, &mut a),
+            )
+            .then_some((r, g, b))
+    };
+    let fill_kind = matches!(kind, "square" | "circle" | "polygon" | "freeText");
+    if let Some(color) = annot_color(FPDFANNOT_COLORTYPE_COLOR) {
+        let fill = annot_color(FPDFANNOT_COLORTYPE_INTERIOR_COLOR).filter(|_| fill_kind);
+        return (Some(hex(color)), fill.map(hex));
+    }
+
+    let (mut text, mut stroke, mut fill) = (None, None, None);
+    for i in 0..bindings.FPDFAnnot_GetObjectCount(handle).max(0) {
+        let obj = bindings.FPDFAnnot_GetObject(handle, i);
+        if obj.is_null() {
+            continue;
+        }
+        let object_color = |stroked: bool| {
+            let (mut r, mut g, mut b, mut a) = (0, 0, 0, 0);
+            let ok = if stroked {
+                bindings.FPDFPageObj_GetStrokeColor(obj, &mut r, &mut g, &mut b, &mut a)
+            } else {
+                bindings.FPDFPageObj_GetFillColor(obj, &mut r, &mut g, &mut b, &mut a)
+            };
+            bindings.is_true(ok).then_some((r, g, b))
+        };
+        match bindings.FPDFPageObj_GetType(obj) {
+            FPDF_PAGEOBJ_TEXT => text = text.or_else(|| object_color(false)),
+            FPDF_PAGEOBJ_PATH => {
+                let (mut fill_mode, mut stroked) = (0, 0);
+                if !bindings.is_true(bindings.FPDFPath_GetDrawMode(
+                    obj,
+                    &mut fill_mode,
+                    &mut stroked,
+                )) {
+                    continue;
+                }
+                if bindings.is_true(stroked) {
+                    stroke = stroke.or_else(|| object_color(true));
+                }
+                if fill_mode != 0 {
+                    fill = fill.or_else(|| object_color(false));
+                }
+            }
+            _ => {}
+        }
+    }
+    // 自由文字的線條色即文字色；螢光標記等只有填色的外觀以填色為線條色
+    let color = match kind {
+        "freeText" => text.or(stroke),
+        _ if fill_kind => stroke,
+        _ => stroke.or(fill),
+    };
+    (color.map(hex), fill.filter(|_| fill_kind).map(hex))
+}
+
+// 以 Pdfium 列出已開啟文件的註解（Popup 除外）；index 為該頁註解的位置，供刪除使用
+fn list_annotations(
+    doc: &pdfium_render::prelude::PdfDocument,
+    page_index: Option<u32>,
+) -> Result<Vec<PdfAnnotationInfo>, MediaError> {
+    use pdfium_render::prelude::*;
+    let bindings = doc.bindings();
+    let pages: Vec<u32> = match page_index {
+        Some(p) => vec![p],
+        None => (0..total_pages(doc)).collect(),
+    };
+    let mut out = Vec::new();
+    for page_index in pages {
+        let page = doc
+            .pages()
+            .get(to_page_index(page_index)?)
+            .map_err(|_| MediaError::new("not_found", format!("頁索引不存在: {}", page_index)))?;
+        let page_handle = bindings.get_handle_from_page(&page);
+        for (index, annot) in page.annotations().iter().enumerate() {
+            let annotation_type = annot.annotation_type();
+            if annotation_type == PdfPageAnnotationType::Popup {
+                continue;
+            }
+            let kind = annotation_kind(annotation_type);
+            let handle = bindings.FPDFPage_GetAnnot(page_handle, index as i32);
+            let (mut color, mut fill_color, mut opacity, mut quads) =
+                (None, None, None, Vec::new());
+            if !handle.is_null() {
+                (color, fill_color) = annotation_colors(bindings, handle, kind);
+                let mut ca = 0.0;
+                if bindings.is_true(bindings.FPDFAnnot_GetNumberValue(handle, "CA", &mut ca)) {
+                    opacity = Some(ca);
+                }
+                for q in 0..bindings.FPDFAnnot_CountAttachmentPoints(handle) {
+                    let mut p = FS_QUADPOINTSF {
+                        x1: 0.0,
+                        y1: 0.0,
+                        x2: 0.0,
+                        y2: 0.0,
+                        x3: 0.0,
+                        y3: 0.0,
+                        x4: 0.0,
+                        y4: 0.0,
+                    };
+                    if bindings.is_true(bindings.FPDFAnnot_GetAttachmentPoints(handle, q, &mut p)) {
+                        quads.push([p.x1, p.y1, p.x2, p.y2, p.x3, p.y3, p.x4, p.y4]);
+                    }
+                }
+                bindings.FPDFPage_CloseAnnot(handle);
+            }
+            out.push(PdfAnnotationInfo {
+                page_index,
+                index: index as u32,
+                kind: kind.to_string(),
+                rect: annot.bounds().ok().map(|r| pdf_box_from_rect(&r)),
+                contents: annot.contents(),
+                author: annot.creator(),
+                color,
+                fill_color,
+                opacity,
+                modified: annot.modification_date().and_then(|m| pdf_date_to_iso(&m)),
+                quads,
+            });
+        }
+    }
+    Ok(out)
+}
+
+// 字寬估計（em），用於自由文字換行；非 Latin-1 字元視為全形
+fn approx_char_width(c: char) -> f32 {
+    match c {
+        ' ' | 'i' | 'j' | 'l' | '.' | ',' | ':' | ';' | '\'' | '!' | '|' => 0.28,
+        'm' | 'w' | 'M' | 'W' => 0.85,
+        c if c.is_ascii_uppercase() || c.is_ascii_digit() => 0.64,
+        c if (c as u32) < 0x100 => 0.52,
+        _ => 1.0,
+    }
+}
+
+// 依寬度（em）換行；拉丁文字優先於空白處斷行
+fn wrap_text_lines(text: &str, max_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    for para in text.split('\n') {
+        let mut line = String::new();
+        let mut width = 0.0;
+        for c in para.trim_end_matches('\r').chars() {
+            let w = approx_char_width(c);
+            if width + w > max_width && !line.is_empty() {
+                match line.rfind(' ').filter(|_| c != ' ' && (c as u32) < 0x100) {
+                    Some(pos) => {
+                        let rest = line.split_off(pos + 1);
+                        lines.push(line.trim_end().to_string());
+                        line = rest;
+                    }
+                    None => lines.push(std::mem::take(&mut line)),
+                }
+                width = line.chars().map(approx_char_width).sum();
+                if c == ' ' && line.is_empty() {
+                    continue;
+                }
+            }
+            line.push(c);
+            width += w;
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// 座標串列 [x1, y1, x2, y2, ...] 的外框
+fn points_bbox(points: &[f32]) -> Option<PdfBox> {
+    let mut pairs = points.chunks_exact(2);
+    let first = pairs.next()?;
+    let init = PdfBox {
+        left: first[0],
+        bottom: first[1],
+        right: first[0],
+        top: first[1],
+    };
+    Some(pairs.fold(init, |b, p| PdfBox {
+        left: b.left.min(p[0]),
+        bottom: b.bottom.min(p[1]),
+        right: b.right.max(p[0]),
+        top: b.top.max(p[1]),
+    }))
+}
+
+// Pdfium 未提供建立橢圓註解的包裝（fpdf_annot.h 的 FPDF_ANNOT_CIRCLE）
+const FPDF_ANNOT_CIRCLE: i32 = 6;
+const FPDF_ANNOT_APPEARANCEMODE_NORMAL: i32 = 0;
+
+// 註解的線條色、填色、框線寬度與不透明度（含各類型預設值）
+struct AnnotationStyle {
+    color: (u8, u8, u8),
+    fill: Option<(u8, u8, u8)>,
+    border_width: f32,
+    opacity: f32,
+}
+
+fn annotation_style(args: &PdfAddAnnotationArgs) -> Result<AnnotationStyle, MediaError> {
+    let kind = args.kind.as_str();
+    let default_color = match kind {
+        "highlight" => "#FFFF00",
+        "freeText" => "#000000",
+        _ => "#FF0000",
+    };
+    let color = parse_hex_color(args.color.as_deref().unwrap_or(default_color))?;
+    let fill = args
+        .fill_color
+        .as_deref()
+        .map(parse_hex_color)
+        .transpose()?;
+    let bw = args
+        .border_width
+        .unwrap_or(match kind {
+            "ink" => 2.0,
+            "freeText" => 0.0,
+            _ => 1.0,
+        })
+        .max(0.0);
+    Ok(AnnotationStyle {
+        color,
+        fill,
+        border_width: bw,
+        opacity: args.opacity.unwrap_or(1.0).clamp(0.0, 1.0),
+    })
+}
+
+// 移除建立到一半的註解
+fn discard_annotation(page: &mut pdfium_render::prelude::PdfPage, index: usize) {
+    if let Ok(annot) = page.annotations().get(index) {
+        let _ = page.annotations_mut().delete_annotation(annot);
+    }
+}
+
+// 以 Pdfium 在開啟中的文件建立註解，回傳其在頁面 /Annots 中的位置。
+// 外觀由 Pdfium 依 /C、/IC、/QuadPoints、/InkList 與框線寬度產生；自由文字先建立為 Stamp，
+// 以頁面物件排版（字型由 Pdfium 載入並內嵌），再由 stamp_to_free_text 改為 FreeText
+fn add_annotation(
+    doc: &mut pdfium_render::prelude::PdfDocument,
+    args: &PdfAddAnnotationArgs,
+) -> Result<u32, MediaError> {
+    use pdfium_render::prelude::*;
+    let invalid = |msg: &str| MediaError::new("invalid_input", msg.to_string());
+    let kind = args.kind.as_str();
+    if !matches!(
+        kind,
+        "highlight" | "underline" | "strikeout" | "ink" | "square" | "circle" | "freeText"
+    ) {
+        return Err(invalid(&format!("不支援的註解類型: {}", kind)));
+    }
+    if args.page_index >= total_pages(doc) {
+        return Err(MediaError::new(
+            "not_found",
+            format!("頁索引不存在: {}", args.page_index),
+        ));
+    }
+    let AnnotationStyle {
+        color,
+        fill,
+        border_width: bw,
+        opacity,
+    } = annotation_style(args)?;
+    let user_rect = || -> Result<PdfBox, MediaError> {
+        let r = args.rect.ok_or_else(|| invalid("此註解類型需要 rect"))?;
+        let r = PdfBox {
+            left: r.left.min(r.right),
+            bottom: r.bottom.min(r.top),
+            right: r.left.max(r.right),
+            top: r.bottom.max(r.top),
+        };
+        if r.right - r.left <= bw || r.top - r.bottom <= bw {
+            return Err(invalid("rect 的寬高需大於框線寬度"));
+        }
+        Ok(r)
+    };
+
+    // 先驗證參數並算出註解範圍，避免建立後才失敗
+    let quads = args.quads.as_deref().unwrap_or_default();
+    let strokes = args.ink_list.as_deref().unwrap_or_default();
+    let text = args.contents.as_deref().unwrap_or_default();
+    let size = args.font_size.unwrap_or(12.0);
+    let rect = match kind {
+        "highlight" | "underline" | "strikeout" => {
+            if quads.is_empty() || quads.iter().any(|q| q.len() != 8) {
+                return Err(invalid("quads 需為每組 8 個數值的座標陣列"));
+            }
+            points_bbox(&quads.concat()).ok_or_else(|| invalid("quads 不可為空"))?
+        }
+        "ink" => {
+            if strokes.is_empty() || strokes.iter().any(|s| s.len() < 4 || s.len() % 2 != 0) {
+                return Err(invalid(
+                    "inkList 每筆需為至少兩點的座標陣列 [x1, y1, x2, y2, ...]",
+                ));
+            }
+            let r = points_bbox(&strokes.concat()).ok_or_else(|| invalid("inkList 不可為空"))?;
+            let pad = bw / 2.0 + 1.0;
+            PdfBox {
+                left: r.left - pad,
+                bottom: r.bottom - pad,
+                right: r.right + pad,
+                top: r.top + pad,
+            }
+        }
+        "square" | "circle" => {
+            if fill.is_none() && bw <= 0.0 {
+                return Err(invalid("需設定框線寬度或填色"));
+            }
+            user_rect()?
+        }
+        _ => {
+            if text.trim().is_empty() {
+                return Err(invalid("自由文字需要 contents"));
+            }
+            if !size.is_finite() || size <= 0.0 {
+                return Err(invalid("fontSize 需大於 0"));
+            }
+            user_rect()?
+        }
+    };
+    let font = match kind {
+        "freeText" => Some(load_text_font(
+            doc,
+            args.font.as_deref(),
+            args.font_path.as_deref(),
+            text,
+        )?),
+        _ => None,
+    };
+
+    let map_err = |e: PdfiumError| MediaError::new("io_error", format!("建立註解失敗: {e}"));
+    let mut page = doc
+        .pages()
+        .get(to_page_index(args.page_index)?)
+        .map_err(map_err)?;
+    let bindings = doc.bindings();
+    let page_handle = bindings.get_handle_from_page(&page);
+    let index = page.annotations().len();
+    let annotations = page.annotations_mut();
+    match kind {
+        "highlight" => annotations.create_highlight_annotation().map(drop),
+        "underline" => annotations.create_underline_annotation().map(drop),
+        "strikeout" => annotations.create_strikeout_annotation().map(drop),
+        "ink" => annotations.create_ink_annotation().map(drop),
+        "square" => annotations.create_square_annotation().map(drop),
+        "freeText" => annotations.create_stamp_annotation().map(drop),
+        _ => {
+            let handle = bindings.FPDFPage_CreateAnnot(page_handle, FPDF_ANNOT_CIRCLE);
+            if handle.is_null() {
+                Err(PdfiumError::PdfiumLibraryInternalError(
+                    PdfiumInternalError::Unknown,
+                ))
+            } else {
+                bindings.FPDFPage_CloseAnnot(handle);
+                Ok(())
+            }
+        }
+    }
+    .map_err(map_err)?;
+
+    let alpha = (opacity * 255.0).round() as u8;
+    let pdf_color = |c: (u8, u8, u8)| PdfColor::new(c.0, c.1, c.2, alpha);
+    let res = (|| -> Result<(), PdfiumError> {
+        let mut annot = page.annotations().get(index)?;
+        annot.set_bounds(PdfRect::new_from_values(
+            rect.bottom,
+            rect.left,
+            rect.top,
+            rect.right,
+        ))?;
+        annot.set_creation_date(chrono::Utc::now())?;
+        annot.set_modification_date(chrono::Utc::now())?;
+        annot.set_is_printed(true)?;
+        if let Some(contents) = args.contents.as_deref() {
+            annot.set_contents(contents)?;
+        }
+        if let Some(author) = args.author.as_deref() {
+            annot.set_creator(author)?;
+        }
+        if kind != "freeText" {
+            // 色彩的 alpha 即為註解的 /CA
+            annot.set_stroke_color(pdf_color(color))?;
+            if let Some(fill) = fill.filter(|_| matches!(kind, "square" | "circle")) {
+                annot.set_fill_color(pdf_color(fill))?;
+            }
+        }
+        let attachment_points = match kind {
+            "highlight" => annot
+                .as_highlight_annotation_mut()
+                .map(|a| a.attachment_points_mut()),
+            "underline" => annot
+                .as_underline_annotation_mut()
+                .map(|a| a.attachment_points_mut()),
+            "strikeout" => annot
+                .as_strikeout_annotation_mut()
+                .map(|a| a.attachment_points_mut()),
+            _ => None,
+        };
+        if let Some(attachment_points) = attachment_points {
+            for q in quads {
+                let p = |i: usize| PdfPoints::new(q[i]);
+                attachment_points.create_attachment_point_at_end(PdfQuadPoints::new(
+                    p(0),
+                    p(1),
+                    p(2),
+                    p(3),
+                    p(4),
+                    p(5),
+                    p(6),
+                    p(7),
+                ))?;
+            }
+        }
+        if let (Some(font), Some(stamp)) = (font, annot.as_stamp_annotation_mut()) {
+            let objects = stamp.objects_mut();
+            let bounds = PdfRect::new_from_values(rect.bottom, rect.left, rect.top, rect.right);
+            if let Some(fill) = fill {
+                objects.add_path_object(PdfPagePathObject::new_rect(
+                    doc,
+                    bounds,
+                    None,
+                    None,
+                    Some(pdf_color(fill)),
+                )?)?;
+            }
+            if bw > 0.0 {
+                let h = bw / 2.0;
+                let inner = PdfRect::new_from_values(
+                    rect.bottom + h,
+                    rect.left + h,
+                    rect.top - h,
+                    rect.right - h,
+                );
+                let border = PdfPagePathObject::new_rect(
+                    doc,
+                    inner,
+                    Some(pdf_color(color)),
+                    Some(PdfPoints::new(bw)),
+                    None,
+                )?;
+                objects.add_path_object(border)?;
+            }
+            let pad = bw + 2.0;
+            for (i, line) in wrap_text_lines(text, (rect.right - rect.left - pad * 2.0) / size)
+                .iter()
+                .enumerate()
+            {
+                let baseline = rect.top - pad - size * 0.8 - i as f32 * size * 1.2;
+                // 超出 rect 的行不顯示
+                if baseline - size * 0.2 < rect.bottom {
+                    break;
+                }
+                // Pdfium 不接受空字串的文字物件
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let mut obj = PdfPageTextObject::new(doc, line, font, PdfPoints::new(size))?;
+                obj.set_fill_color(pdf_color(color))?;
+                obj.translate(PdfPoints::new(rect.left + pad), PdfPoints::new(baseline))?;
+                objects.add_text_object(obj)?;
+            }
+        }
+
+        // 框線寬度與筆畫沒有 pdfium-render 包裝，以原生 API 設定
+        if matches!(kind, "ink" | "square" | "circle") {
+            let handle = bindings.FPDFPage_GetAnnot(page_handle, index as i32);
+            if handle.is_null() {
+                return Err(PdfiumError::PdfiumLibraryInternalError(
+                    PdfiumInternalError::Unknown,
+                ));
+            }
+            let ok = bindings.is_true(bindings.FPDFAnnot_SetBorder(handle, 0.0, 0.0, bw))
+                && strokes.iter().all(|stroke| {
+                    let pts: Vec<FS_POINTF> = stroke
+                        .chunks_exact(2)
+                        .map(|p| FS_POINTF { x: p[0], y: p[1] })
+                        .collect();
+                    bindings.FPDFAnnot_AddInkStroke(handle, pts.as_ptr(), pts.len()) >= 0
+                });
+            bindings.FPDFPage_CloseAnnot(handle);
+            if !ok {
+                return Err(PdfiumError::PdfiumLibraryInternalError(
+                    PdfiumInternalError::Unknown,
+                ));
+            }
+        }
+        Ok(())
+    })();
+    if let Err(e) = res {
+        discard_annotation(&mut page, index);
+        return Err(map_err(e));
+    }
+    if kind != "freeText" {
+        // 渲染一次讓 Pdfium 依上述屬性產生並寫入 /AP（螢光標記以色彩相乘），存檔後其他檢視器也能顯示
+        let cfg = PdfRenderConfig::new()
+            .set_target_width(16)
+            .render_annotations(true);
+        page.render_with_config(&cfg)
+            .map_err(|e| MediaError::new("parse_error", format!("產生註解外觀失敗: {e}")))?;
+    }
+    Ok(index as u32)
+}
+
+// Pdfium 無法改變註解類型：將 add_annotation 以 Stamp 排版的自由文字改為 FreeText，
+// 外觀沿用，補上 /DA、/DR（字型與文字色）、底色 /C、框線寬度與不透明度
+fn stamp_to_free_text(
+    lo: &mut lopdf::Document,
+    args: &PdfAddAnnotationArgs,
+    index: u32,
+) -> Result<(), MediaError> {
+    use lopdf::{Dictionary, Object, dictionary};
+    let AnnotationStyle {
+        color,
+        fill,
+        border_width: bw,
+        opacity,
+    } = annotation_style(args)?;
+    let not_found = || {
+        MediaError::new(
+            "not_found",
+            format!("註解不存在: 第 {} 頁第 {} 個", args.page_index, index),
+        )
+    };
+    let page_id = *lo
+        .get_pages()
+        .get(&(args.page_index + 1))
+        .ok_or_else(not_found)?;
+    let annot_id = page_annotation_list(lo, page_id)
+        .get(index as usize)
+        .and_then(|a| a.as_reference().ok())
+        .ok_or_else(not_found)?;
+    let rgb = |c: (u8, u8, u8)| [c.0, c.1, c.2].map(|v| v as f32 / 255.0);
+    let [r, g, b] = rgb(color);
+    let size = args.font_size.unwrap_or(12.0);
+    // /DA 引用外觀中實際使用的字型（含 fontPath 內嵌字型），並登記於 /DR；找不到時才用 Helvetica
+    let ap_font = lo
+        .get_dictionary(annot_id)
+        .ok()
+        .and_then(|a| a.get(b"AP").ok())
+        .and_then(|ap| resolve_dict(lo, ap))
+        .and_then(|ap| ap.get(b"N").ok().and_then(|n| resolve_dict(lo, n)))
+        .and_then(|n| n.get(b"Resources").ok().and_then(|r| resolve_dict(lo, r)))
+        .and_then(|res| res.get(b"Font").ok().and_then(|f| resolve_dict(lo, f)))
+        .and_then(|fonts| {
+            fonts
+                .iter()
+                .next()
+                .map(|(name, font)| (name.clone(), font.clone()))
+        });
+    let (font_name, font) = match ap_font {
+        Some(found) => found,
+        None => (
+            b"Helv".to_vec(),
+            Object::Dictionary(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type1",
+                "BaseFont" => "Helvetica",
+                "Encoding" => "WinAnsiEncoding",
+            }),
+        ),
+    };
+    let da = format!(
+        "/{} {} Tf {:.3} {:.3} {:.3} rg",
+        String::from_utf8_lossy(&font_name),
+        size,
+        r,
+        g,
+        b
+    );
+    let mut fonts = Dictionary::new();
+    fonts.set(font_name, font);
+    let annot = lo.get_dictionary_mut(annot_id).map_err(|_| not_found())?;
+    annot.set("Subtype", Object::Name(b"FreeText".to_vec()));
+    annot.set("DA", Object::string_literal(da));
+    annot.set("DR", dictionary! { "Font" => fonts });
+    annot.remove(b"IC");
+    match fill {
+        Some(fill) => annot.set("C", Object::Array(rgb(fill).map(Object::Real).to_vec())),
+        None => annot.remove(b"C").map(drop).unwrap_or(()),
+    }
+    let mut bs = Dictionary::new();
+    bs.set("W", Object::Real(bw));
+    annot.set("BS", Object::Dictionary(bs));
+    if opacity < 1.0 {
+        annot.set("CA", Object::Real(opacity));
+    }
+    Ok(())
+}
+
+// 以 Pdfium 刪除註解（連同其 Popup）；表單欄位需以表單功能處理。回傳刪除數
+fn delete_annotations(
+    doc: &pdfium_render::prelude::PdfDocument,
+    targets: &[PdfAnnotationRef],
+) -> Result<usize, MediaError> {
+    use pdfium_render::prelude::*;
+    let map_err = |e: PdfiumError| MediaError::new("io_error", format!("刪除註解失敗: {e}"));
+    let mut by_page: BTreeMap<u32, BTreeSet<usize>> = BTreeMap::new();
+    for t in targets {
+        by_page
+            .entry(t.page_index)
+            .or_default()
+            .insert(t.index as usize);
+    }
+    let bindings = doc.bindings();
+    // 先檢查全部目標並找出 Popup，避免刪到一半才失敗
+    let mut pages = Vec::new();
+    let mut removed = 0;
+    for (page_index, indices) in by_page {
+        let page = doc
+            .pages()
+            .get(to_page_index(page_index)?)
+            .map_err(|_| MediaError::new("not_found", format!("頁索引不存在: {}", page_index)))?;
+        let page_handle = bindings.get_handle_from_page(&page);
+        let mut remove = indices.clone();
+        for &i in &indices {
+            let annot = page.annotations().get(i).map_err(|_| {
+                MediaError::new(
+                    "not_found",
+                    format!("註解不存在: 第 {} 頁第 {} 個", page_index, i),
+                )
+            })?;
+            if annot.annotation_type() == PdfPageAnnotationType::Widget {
+                return Err(MediaError::new("unsupported", "表單欄位無法以註解方式刪除"));
+            }
+            let handle = bindings.FPDFPage_GetAnnot(page_handle, i as i32);
+            if handle.is_null() {
+                continue;
+            }
+            let popup = bindings.FPDFAnnot_GetLinkedAnnot(handle, "Popup");
+            if !popup.is_null() {
+                let popup_index = bindings.FPDFPage_GetAnnotIndex(page_handle, popup);
+                if popup_index >= 0 {
+                    remove.insert(popup_index as usize);
+                }
+                bindings.FPDFPage_CloseAnnot(popup);
+            }
+            bindings.FPDFPage_CloseAnnot(handle);
+        }
+        removed += indices.len();
+        pages.push((page, remove));
+    }
+    for (mut page, remove) in pages {
+        // 由後往前刪，前面的位置不受影響
+        for i in remove.into_iter().rev() {
+            let annot = page.annotations().get(i).map_err(map_err)?;
+            page.annotations_mut()
+                .delete_annotation(annot)
+                .map_err(map_err)?;
+        }
+    }
+    Ok(removed)
+}
+
+// Pdfium 可依註解字典重建外觀的類型；其餘帶 /AP 的註解（圖章、表單欄位等）無法隨頁面縮放
+fn annotation_appearance_regenerable(kind: pdfium_render::prelude::PdfPageAnnotationType) -> bool {
+    use pdfium_render::prelude::PdfPageAnnotationType as T;
+    matches!(
+        kind,
+        T::Highlight
+            | T::Underline
+            | T::Squiggly
+            | T::Strikeout
+            | T::Ink
+            | T::Square
+            | T::Circle
+            | T::Popup
+            | T::Text
+    )
+}
+
+// 縮放前先檢查，避免處理到一半才因註解而失敗
+fn check_annotations_scalable(
+    doc: &pdfium_render::prelude::PdfDocument,
+    page: &pdfium_render::prelude::PdfPage,
+    page_index: u32,
+) -> Result<(), MediaError> {
+    let bindings = doc.bindings();
+    let page_handle = bindings.get_handle_from_page(page);
+    for (i, annot) in page.annotations().iter().enumerate() {
+        if annotation_appearance_regenerable(annot.annotation_type()) {
+            continue;
+        }
+        let handle = bindings.FPDFPage_GetAnnot(page_handle, i as i32);
+        if handle.is_null() {
+            continue;
+        }
+        let has_ap = bindings.is_true(bindings.FPDFAnnot_HasKey(handle, "AP"));
+        bindings.FPDFPage_CloseAnnot(handle);
+        if has_ap {
+            return Err(MediaError::new(
+                "unsupported",
+                format!(
+                    "第 {} 頁含有無法隨內容縮放的註解（圖章、表單欄位等）",
+                    page_index + 1
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// transform_with_clip 只轉換內容串流：註解的 /Rect、/QuadPoints 與 /InkList 以同一矩陣另行轉換，
+// 舊外觀移除後重新渲染一次，讓 Pdfium 依新座標產生並寫入 /AP
+fn transform_page_annotations(
+    doc: &pdfium_render::prelude::PdfDocument,
+    page: &pdfium_render::prelude::PdfPage,
+    scale: f32,
+    e: f32,
+    f: f32,
+) -> Result<(), MediaError> {
+    use pdfium_render::prelude::*;
+    let map_err = |i: usize| MediaError::new("io_error", format!("轉換註解座標失敗: 第 {} 個", i));
+    let map = |x: f32, y: f32| (x * scale + e, y * scale + f);
+    let bindings = doc.bindings();
+    let page_handle = bindings.get_handle_from_page(page);
+    let kinds: Vec<_> = page
+        .annotations()
+        .iter()
+        .map(|a| a.annotation_type())
+        .collect();
+    let mut regenerate = false;
+    for (i, kind) in kinds.into_iter().enumerate() {
+        let handle = bindings.FPDFPage_GetAnnot(page_handle, i as i32);
+        if handle.is_null() {
+            return Err(map_err(i));
+        }
+        let ok = (|| {
+            if bindings.is_true(bindings.FPDFAnnot_HasKey(handle, "AP")) {
+                if !bindings.is_true(bindings.FPDFAnnot_SetAP(
+                    handle,
+                    FPDF_ANNOT_APPEARANCEMODE_NORMAL,
+                    std::ptr::null(),
+                )) {
+                    return false;
+                }
+                regenerate = true;
+            }
+            let mut rect = FS_RECTF {
+                left: 0.0,
+                top: 0.0,
+                right: 0.0,
+                bottom: 0.0,
+            };
+            if bindings.is_true(bindings.FPDFAnnot_GetRect(handle, &mut rect)) {
+                let (left, bottom) = map(rect.left, rect.bottom);
+                let (right, top) = map(rect.right, rect.top);
+                let rect = FS_RECTF {
+                    left,
+                    top,
+                    right,
+                    bottom,
+                };
+                if !bindings.is_true(bindings.FPDFAnnot_SetRect(handle, &rect)) {
+                    return false;
+                }
+            }
+            for q in 0..bindings.FPDFAnnot_CountAttachmentPoints(handle) {
+                let mut quad = FS_QUADPOINTSF {
+                    x1: 0.0,
+                    y1: 0.0,
+                    x2: 0.0,
+                    y2: 0.0,
+                    x3: 0.0,
+                    y3: 0.0,
+                    x4: 0.0,
+                    y4: 0.0,
+                };
+                if !bindings.is_true(bindings.FPDFAnnot_GetAttachmentPoints(handle, q, &mut quad)) {
+                    return false;
+                }
+                let ((x1, y1), (x2, y2)) = (map(quad.x1, quad.y1), map(quad.x2, quad.y2));
+                let ((x3, y3), (x4, y4)) = (map(quad.x3, quad.y3), map(quad.x4, quad.y4));
+                let quad = FS_QUADPOINTSF {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    x3,
+                    y3,
+                    x4,
+                    y4,
+                };
+                if !bindings.is_true(bindings.FPDFAnnot_SetAttachmentPoints(handle, q, &quad)) {
+                    return false;
+                }
+            }
+            if kind == PdfPageAnnotationType::Ink {
+                // 沒有逐點修改的 API：讀出全部筆畫、清除 /InkList 後重新加入
+                let mut strokes = Vec::new();
+                for path in 0..bindings.FPDFAnnot_GetInkListCount(handle) {
+                    let len =
+                        bindings.FPDFAnnot_GetInkListPath(handle, path, std::ptr::null_mut(), 0);
+                    let mut pts = vec![FS_POINTF { x: 0.0, y: 0.0 }; len as usize];
+                    bindings.FPDFAnnot_GetInkListPath(handle, path, pts.as_mut_ptr(), len);
+                    for p in &mut pts {
+                        (p.x, p.y) = map(p.x, p.y);
+                    }
+                    strokes.push(pts);
+                }
+                if !strokes.is_empty()
+                    && !bindings.is_true(bindings.FPDFAnnot_RemoveInkList(handle))
+                {
+                    return false;
+                }
+                if !strokes.iter().all(|pts| {
+                    bindings.FPDFAnnot_AddInkStroke(handle, pts.as_ptr(), pts.len()) >= 0
+                }) {
+                    return false;
+                }
+            }
+            true
+        })();
+        bindings.FPDFPage_CloseAnnot(handle);
+        if !ok {
+            return Err(map_err(i));
+        }
+    }
+    if regenerate {
+        let cfg = PdfRenderConfig::new()
+            .set_target_width(16)
+            .render_annotations(true);
+        page.render_with_config(&cfg)
+            .map_err(|e| MediaError::new("parse_error", format!("產生註解外觀失敗: {e}")))?;
+    }
+    Ok(())
+}
+
+// 平面化註解：widget 與連結保留，其餘先讓 Pdfium 補齊外觀後畫入頁面內容
+fn flatten_document_annotations<'a>(
+    pdfium: &'a pdfium_render::prelude::Pdfium,
+    doc: &pdfium_render::prelude::PdfDocument,
+    password: Option<&str>,
+) -> Result<(pdfium_render::prelude::PdfDocument<'a>, usize), MediaError> {
+    use pdfium_render::prelude::*;
+    generate_missing_appearances(doc, |t| {
+        !matches!(
+            t,
+            PdfPageAnnotationType::Widget
+                | PdfPageAnnotationType::Link
+                | PdfPageAnnotationType::Popup
+        )
+    })?;
+    edit_with_lopdf(pdfium, doc, password, |lo| {
+        let flattened =
+            flatten_page_annotations(lo, |subtype| !matches!(subtype, b"Widget" | b"Link"));
+        lo.prune_objects();
+        Ok(flattened)
+    })
+}
+
+// 文件修改狀態：revision 每次成功修改遞增，saved_revision 為最後一次儲存/開啟時的值，
+// autosaved_revision 為最後一次寫入復原目錄時的值
+#[derive(Clone, Copy, Default)]
+struct DocState {
+    revision: u64,
+    saved_revision: u64,
+    autosaved_revision: u64,
+}
+
+impl DocState {
+    fn dirty(&self) -> bool {
+        self.revision != self.saved_revision
+    }
+}
+
+fn doc_state_snapshot(
+    doc_id: u64,
+    states: &HashMap<u64, DocState>,
+    paths: &HashMap<u64, String>,
+) -> PdfDocState {
+    let state = states.get(&doc_id).copied().unwrap_or_default();
+    PdfDocState {
+        doc_id,
+        path: paths.get(&doc_id).cloned(),
+        dirty: state.dirty(),
+        revision: state.revision,
+    }
+}
+
+fn emit_doc_state(app: &tauri::AppHandle, state: &PdfDocState) {
+    if let Err(err) = app.emit("pdf-doc-state", state) {
+        warn!("Failed to emit pdf-doc-state event: {err}");
+    }
+}
+
+// 成功修改文件後呼叫：遞增 revision 並通知前端
+fn mark_modified(
+    app: &tauri::AppHandle,
+    states: &mut HashMap<u64, DocState>,
+    paths: &HashMap<u64, String>,
+    doc_id: u64,
+) {
+    states.entry(doc_id).or_default().revision += 1;
+    emit_doc_state(app, &doc_state_snapshot(doc_id, states, paths));
+}
+
+// 安全儲存：先寫入同目錄暫存檔並 fsync，再以 rename 原子替換目的檔。
+// 目的檔可能正被 Pdfium 讀取，因此不可直接覆寫；backup=true 時保留原檔為 .bak。
+// 回傳備份檔路徑（若有）。
+fn save_document_atomic(
+    doc: &pdfium_render::prelude::PdfDocument,
+    dest: &Path,
+    backup: bool,
+) -> Result<Option<String>, MediaError> {
+    write_file_atomic(dest, backup, |file| {
+        doc.save_to_writer(file)
+            .map_err(|e| MediaError::new("io_error", format!("寫入檔案失敗: {e}")))
+    })
+}
+
+fn write_file_atomic(
+    dest: &Path,
+    backup: bool,
+    write: impl FnOnce(&mut fs::File) -> Result<(), MediaError>,
+) -> Result<Option<String>, MediaError> {
+    let dir = match dest.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = file_name(dest);
+    let tmp_path = dir.join(format!(".{}.{}.tmp", name, std::process::id()));
+
+    let write_tmp = || -> Result<(), MediaError> {
+        let mut file = fs::File::create(&tmp_path)
+            .map_err(|e| MediaError::new("io_error", format!("建立暫存檔失敗: {e}")))?;
+        write(&mut file)?;
+        file.sync_all()
+            .map_err(|e| MediaError::new("io_error", format!("同步暫存檔失敗: {e}")))?;
+        Ok(())
+    };
+    if let Err(e) = write_tmp() {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+
+    // 以複製保留備份，確保替換前目的檔始終完整存在
+    let mut backup_path: Option<String> = None;
+    if backup && dest.exists() {
+        let bak = dir.join(format!("{}.bak", name));
+        if let Err(e) = fs::copy(dest, &bak) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(MediaError::new("io_error", format!("建立備份檔失敗: {e}")));
+        }
+        backup_path = Some(bak.to_string_lossy().into_owned());
+    }
+
+    if let Err(e) = fs::rename(&tmp_path, dest) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(MediaError::new("io_error", format!("替換目的檔失敗: {e}")));
+    }
+
+    // 同步目錄項，確保 rename 在斷電後仍然有效
+    #[cfg(unix)]
+    if let Ok(d) = fs::File::open(&dir) {
+        let _ = d.sync_all();
+    }
+
+    Ok(backup_path)
+}
+
+// =====================
+// Autosave / crash recovery
+// =====================
+
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+// 本次執行的識別字，用於區分復原檔屬於目前或先前（已崩潰）的執行
+static SESSION_ID: Lazy<String> = Lazy::new(|| {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("{}-{}", secs, std::process::id())
+});
+
+// 復原檔旁的 sidecar（<id>.json）
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecoveryMeta {
+    original_path: Option<String>,
+    saved_at: u64,
+    pages: usize,
+    revision: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryEntry {
+    pub id: String,
+    pub original_path: Option<String>,
+    pub saved_at: u64,
+    pub pages: usize,
+    pub size: Option<u64>,
+}
+
+fn recovery_dir(app: &tauri::AppHandle) -> Option<PathBuf> {
+    app.path().app_data_dir().ok().map(|d| d.join("recovery"))
+}
+
+fn recovery_id(doc_id: u64) -> String {
+    format!("{}-{}", SESSION_ID.as_str(), doc_id)
+}
+
+// id 來自前端，僅允許英數與 '-'，避免路徑穿越
+fn validate_recovery_id(id: &str) -> Result<(), MediaError> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(MediaError::new(
+            "invalid_input",
+            format!("無效的復原 id: {}", id),
+        ));
+    }
+    Ok(())
+}
+
+fn remove_recovery_files(dir: &Path, id: &str) {
+    let _ = fs::remove_file(dir.join(format!("{}.pdf", id)));
+    let _ = fs::remove_file(dir.join(format!("{}.json", id)));
+}
+
+// 將有變更且尚未自動儲存的文件寫入復原目錄
+fn autosave_dirty_documents(
+    dir: &Path,
+    docs: &HashMap<u64, pdfium_render::prelude::PdfDocument>,
+    states: &mut HashMap<u64, DocState>,
+    paths: &HashMap<u64, String>,
+) {
+    for (doc_id, doc) in docs {
+        let Some(state) = states.get_mut(doc_id) else {
+            continue;
+        };
+        if !state.dirty() || state.autosaved_revision == state.revision {
+            continue;
+        }
+        if let Err(err) = fs::create_dir_all(dir) {
+            warn!("Autosave: failed to create recovery dir: {err}");
+            return;
+        }
+        let id = recovery_id(*doc_id);
+        if let Err(e) = save_document_atomic(doc, &dir.join(format!("{}.pdf", id)), false) {
+            warn!("Autosave failed for doc {}: {}", doc_id, e.message);
+            continue;
+        }
+        let meta = RecoveryMeta {
+            original_path: paths.get(doc_id).cloned(),
+            saved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            pages: total_pages(doc) as usize,
+            revision: state.revision,
+        };
+        let written = serde_json::to_vec(&meta)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| {
+                fs::write(dir.join(format!("{}.json", id)), bytes).map_err(|e| e.to_string())
+            });
+        match written {
+            Ok(()) => state.autosaved_revision = state.revision,
+            Err(err) => warn!("Autosave metadata failed for doc {}: {}", doc_id, err),
+        }
+    }
+}
+
+pub fn init_pdf_worker(app: tauri::AppHandle) {
+    let (tx, rx) = mpsc::channel::<PdfRequest>();
+    *WORKER_TX.lock().unwrap() = Some(tx);
+    std::thread::spawn(move || {
+        use pdfium_render::prelude::*;
+        let pdfium = match get_pdfium() {
+            Ok(p) => p,
+            Err(e) => {
+                // 無法載入 PDFium，工作執行緒退場
+                eprintln!("PDF worker init failed: {}", e.message);
+                return;
+            }
+        };
+        let mut docs: HashMap<u64, PdfDocument> = HashMap::new();
+        let mut paths: HashMap<u64, String> = HashMap::new();
+        let mut states: HashMap<u64, DocState> = HashMap::new();
+        // 以密碼開啟的文件：docId → 密碼（僅存於記憶體）
+        let mut passwords: HashMap<u64, String> = HashMap::new();
+        // 加密文件：docId → 開啟時讀取的加密參數
+        let mut crypts: HashMap<u64, PdfCrypt> = HashMap::new();
+        // 最小允許世代：小於此值的渲染將被立刻忽略（最佳努力取消）
+        let mut min_gen: HashMap<(u64, u32), u64> = HashMap::new();
+        // 尚未寫入文件的書籤：docId → 書籤樹
+        let mut outlines: HashMap<u64, Vec<PdfOutlineItem>> = HashMap::new();
+        let recovery = recovery_dir(&app);
+        let mut last_autosave = Instant::now();
+        loop {
+            // 自動儲存在 worker 內執行，PdfDocument 不需跨執行緒
+            if last_autosave.elapsed() >= AUTOSAVE_INTERVAL {
+                if let Some(dir) = recovery.as_deref() {
+                    flush_pending_outlines(&pdfium, &mut docs, &passwords, &mut outlines);
+                    autosave_dirty_documents(dir, &docs, &mut states, &paths);
+                }
+                last_autosave = Instant::now();
+            }
+            let wait = AUTOSAVE_INTERVAL.saturating_sub(last_autosave.elapsed());
+            let request = rx.recv_timeout(wait);
+            // 其他請求可能改變頁面結構或序列化文件，先寫入暫存的書籤
+            if let Ok(req) = &request
+                && !req.keeps_outline_pending()
+            {
+                flush_pending_outlines(&pdfium, &mut docs, &passwords, &mut outlines);
+            }
+            match request {
+                Ok(PdfRequest::Shutdown { reply }) => {
+                    // 前端關閉前已讓使用者儲存或捨棄（捨棄時強制關閉並移除復原檔）；
+                    // 仍有變更的文件寫入最新內容並保留復原檔，供下次啟動復原
+                    if let Some(dir) = recovery.as_deref() {
+                        autosave_dirty_documents(dir, &docs, &mut states, &paths);
+                        for (doc_id, state) in &states {
+                            if !state.dirty() {
+                                remove_recovery_files(dir, &recovery_id(*doc_id));
+                            }
+                        }
+                    }
+                    let _ = reply.send(());
+                    break;
+                }
+                Ok(PdfRequest::RemoveEncryption {
+                    doc_id,
+                    owner_password,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<PdfSecurityInfo, MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let password = owner_password
+                            .or_else(|| passwords.get(&doc_id).cloned())
+                            .unwrap_or_default();
+                        let bytes = doc.save_to_bytes().map_err(|e| {
+                            MediaError::new("io_error", format!("序列化 PDF 失敗: {e}"))
+                        })?;
+                        let mut lo = lopdf::Document::load_mem(&bytes).map_err(|e| {
+                            MediaError::new("parse_error", format!("解析 PDF 結構失敗: {e}"))
+                        })?;
+                        if !decrypt_document(&mut lo, &password)?.is_owner {
+                            return Err(MediaError::new(
+                                "owner_password_required",
+                                "移除加密需要擁有者密碼",
+                            ));
+                        }
+                        let mut out = Vec::new();
+                        lo.save_to(&mut out).map_err(|e| {
+                            MediaError::new("io_error", format!("寫入 PDF 失敗: {e}"))
+                        })?;
+                        let new_doc = pdfium.load_pdf_from_byte_vec(out, None).map_err(|e| {
+                            MediaError::new("parse_error", format!("重新載入 PDF 失敗: {e}"))
+                        })?;
+                        let info = read_security_info(&new_doc, None);
+                        docs.insert(doc_id, new_doc);
+                        passwords.remove(&doc_id);
+                        crypts.remove(&doc_id);
+                        Ok(info)
+                    })();
+                    if res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Security { doc_id, reply }) => {
+                    let res = docs
+                        .get(&doc_id)
+                        .map(|doc| read_security_info(doc, crypts.get(&doc_id)))
+                        .ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        });
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Open {
+                    path,
+                    password,
+                    reply,
+                }) => {
+                    let res = (|| {
+                        let password = password.filter(|p| !p.is_empty());
+                        let document =
+                            load_pdf_file(&pdfium, &path, password.as_deref(), "開啟 PDF 失敗")?;
+                        let pages = total_pages(&document) as usize;
+                        let crypt = read_document_crypt(
+                            &document,
+                            || fs::read(&path).ok(),
+                            password.as_deref(),
+                        );
+                        let security = read_security_info(&document, crypt.as_ref());
+                        let id = NEXT_DOC_ID.fetch_add(1, Ordering::SeqCst);
+                        docs.insert(id, document);
+                        if let Some(crypt) = crypt {
+                            crypts.insert(id, crypt);
+                        }
+                        paths.insert(id, path);
+                        states.insert(id, DocState::default());
+                        // 另存後重新載入仍需密碼
+                        if let Some(pw) = password {
+                            passwords.insert(id, pw);
+                        }
+                        Ok(PdfOpenResult {
+                            doc_id: id,
+                            pages,
+                            security: Some(security),
+                        })
+                    })();
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Close {
+                    doc_id,
+                    force,
+                    reply,
+                }) => {
+                    let dirty = states.get(&doc_id).map(|s| s.dirty()).unwrap_or(false);
+                    if dirty && !force {
+                        let _ = reply.send(Err(MediaError::new(
+                            "dirty",
+                            format!("文件尚有未儲存的變更: {}", doc_id),
+                        )));
+                        continue;
+                    }
+                    let _ = docs.remove(&doc_id);
+                    let _ = paths.remove(&doc_id);
+                    let _ = passwords.remove(&doc_id);
+                    let _ = crypts.remove(&doc_id);
+                    let _ = states.remove(&doc_id);
+                    let _ = outlines.remove(&doc_id);
+                    let _ = SEARCH_MIN_GEN.lock().unwrap().remove(&doc_id);
+                    if let Some(dir) = recovery.as_deref() {
+                        remove_recovery_files(dir, &recovery_id(doc_id));
+                    }
+                    let _ = reply.send(Ok(()));
+                }
+                Ok(PdfRequest::RecoveryRestore {
+                    id,
+                    password,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<PdfOpenResult, MediaError> {
+                        validate_recovery_id(&id)?;
+                        let dir = recovery
+                            .as_deref()
+                            .ok_or_else(|| MediaError::new("not_found", "無法取得復原目錄"))?;
+                        let pdf_path = dir.join(format!("{}.pdf", id));
+                        let meta: RecoveryMeta = fs::read(dir.join(format!("{}.json", id)))
+                            .ok()
+                            .and_then(|b| serde_json::from_slice(&b).ok())
+                            .ok_or_else(|| {
+                                MediaError::new("not_found", format!("找不到復原資料: {}", id))
+                            })?;
+                        // 以記憶體載入，之後即可刪除復原檔
+                        let bytes = fs::read(&pdf_path).map_err(|e| {
+                            MediaError::new("io_error", format!("讀取復原檔失敗: {e}"))
+                        })?;
+                        // 加密文件的復原檔仍為加密狀態（不在磁碟保存密碼），需再次輸入密碼
+                        let password = password.filter(|p| !p.is_empty());
+                        let document =
+                            load_pdf_bytes(&pdfium, bytes, password.as_deref(), "開啟復原檔失敗")?;
+                        let crypt = read_document_crypt(
+                            &document,
+                            || fs::read(&pdf_path).ok(),
+                            password.as_deref(),
+                        );
+                        let pages = total_pages(&document) as usize;
+                        let doc_id = NEXT_DOC_ID.fetch_add(1, Ordering::SeqCst);
+                        docs.insert(doc_id, document);
+                        if let Some(orig) = meta.original_path {
+                            paths.insert(doc_id, orig);
+                        }
+                        // 復原內容尚未存回原檔，視為有變更
+                        states.insert(
+                            doc_id,
+                            DocState {
+                                revision: 1,
+                                ..DocState::default()
+                            },
+                        );
+                        if let Some(pw) = password {
+                            passwords.insert(doc_id, pw);
+                        }
+                        if let Some(crypt) = crypt {
+                            crypts.insert(doc_id, crypt);
+                        }
+                        remove_recovery_files(dir, &id);
+                        emit_doc_state(&app, &doc_state_snapshot(doc_id, &states, &paths));
+                        Ok(PdfOpenResult {
+                            doc_id,
+                            pages,
+                            security: None,
+                        })
+                    })();
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::DocState { doc_id, reply }) => {
+                    let res = match doc_id {
+                        Some(id) if docs.contains_key(&id) => {
+                            Ok(vec![doc_state_snapshot(id, &states, &paths)])
+                        }
+                        Some(id) => Err(MediaError::new(
+                            "not_found",
+                            format!("未知的 docId: {}", id),
+                        )),
+                        None => {
+                            let mut ids: Vec<u64> = docs.keys().copied().collect();
+                            ids.sort_unstable();
+                            Ok(ids
+                                .into_iter()
+                                .map(|id| doc_state_snapshot(id, &states, &paths))
+                                .collect())
+                        }
+                    };
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Render { args, reply }) => {
+                    let res = (|| -> Result<PageRender, MediaError> {
+                        let doc = docs.get(&args.doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", args.doc_id))
+                        })?;
+                        // best-effort 取消：若 gen 比 min_gen 小則直接丟棄
+                        let g_req = args.r#gen.unwrap_or(0);
+                        let key = (args.doc_id, args.page_index);
+                        if let Some(g_min) = min_gen.get(&key) {
+                            if g_req < *g_min {
+                                return Err(MediaError::new("canceled", "render canceled"));
+                            }
+                        }
+                        render_page_for_document(doc, &args)
+                    })();
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Size {
+                    doc_id,
+                    page_index,
+                    reply,
+                }) => {
+                    let res = docs
+                        .get(&doc_id)
+                        .ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })
+                        .and_then(|doc| page_size_info(doc, page_index));
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Cancel {
+                    doc_id,
+                    page_index,
+                    min_gen: g,
+                    reply,
+                }) => {
+                    let key = (doc_id, page_index);
+                    let entry = min_gen.entry(key).or_insert(0);
+                    if g > *entry {
+                        *entry = g;
+                    }
+                    let _ = reply.send(Ok(()));
+                }
+                Ok(PdfRequest::ExportImage {
+                    doc_id,
+                    page_index,
+                    dest_path,
+                    format,
+                    target_width,
+                    dpi,
+                    quality,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<(String, u32, u32, String), MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let fmt = match format.to_lowercase().as_str() {
+                            "jpeg" | "jpg" => "jpeg",
+                            _ => "png",
+                        };
+                        let args = PdfRenderArgs {
+                            doc_id,
+                            page_index,
+                            scale: None,
+                            dpi,
+                            format: Some(fmt.to_string()),
+                            target_width,
+                            quality,
+                            r#gen: None,
+                        };
+                        let page = render_page_for_document(doc, &args)?;
+                        std::fs::write(&dest_path, &page.image_bytes).map_err(|e| {
+                            MediaError::new("io_error", format!("寫入影像失敗: {e}"))
+                        })?;
+                        Ok((dest_path, page.width_px, page.height_px, page.format))
+                    })();
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::ExportPdf {
+                    doc_id,
+                    page_index,
+                    dest_path,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<String, MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        // 建立新 PDF 並以向量方式複製該頁，不變更原文件
+                        let new_doc = rebuild_document(&pdfium, doc, &[page_index])?;
+                        new_doc.save_to_file(&dest_path).map_err(|e| {
+                            MediaError::new("io_error", format!("寫入 PDF 失敗: {e}"))
+                        })?;
+                        Ok(dest_path)
+                    })();
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::InsertBlank {
+                    doc_id,
+                    index,
+                    width_pt,
+                    height_pt,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<usize, MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        Ok(insert_blank_page(doc, index, width_pt, height_pt)? as usize)
+                    })();
+                    if res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::DeletePages {
+                    doc_id,
+                    indices,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<usize, MediaError> {
+                        let old = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let (new_doc, outline) = delete_document_pages(
+                            &pdfium,
+                            old,
+                            crypts.get(&doc_id),
+                            passwords.get(&doc_id).map(String::as_str),
+                            indices,
+                        )?;
+                        let pages_after = total_pages(&new_doc) as usize;
+                        // 重建成功後才替換文件，失敗時原文件保持不變
+                        docs.insert(doc_id, new_doc);
+                        set_pending_outline(&mut outlines, doc_id, outline);
+                        Ok(pages_after)
+                    })();
+                    if res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::RotatePage {
+                    doc_id,
+                    index,
+                    rotate_deg,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<(), MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        if !matches!(rotate_deg, 0 | 90 | 180 | 270) {
+                            return Err(MediaError::new(
+                                "invalid_input",
+                                "旋轉角度只接受 0|90|180|270",
+                            ));
+                        }
+                        set_page_rotation(doc, index, rotate_deg)
+                    })();
+                    if res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::RotatePages { args, reply }) => {
+                    let doc_id = args.doc_id;
+                    let res = (|| -> Result<Vec<u16>, MediaError> {
+                        let doc = docs.get_mut(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let page_count = total_pages(doc);
+                        let mut targets = match (&args.indices, &args.range) {
+                            (Some(list), _) => {
+                                if let Some(bad) = list.iter().find(|i| **i >= page_count) {
+                                    return Err(MediaError::new(
+                                        "not_found",
+                                        format!("頁索引不存在: {}", bad),
+                                    ));
+                                }
+                                list.clone()
+                            }
+                            (None, range) => {
+                                parse_page_range(range.as_deref().unwrap_or(""), page_count)?
+                            }
+                        };
+                        // 重複頁只處理一次，避免相對旋轉被套用多次
+                        let mut seen = HashSet::new();
+                        targets.retain(|i| seen.insert(*i));
+                        if args.angle % 90 != 0 {
+                            return Err(MediaError::new("invalid_input", "旋轉角度需為 90 的倍數"));
+                        }
+                        let relative = args.relative.unwrap_or(false);
+                        for idx in targets {
+                            let base = if relative {
+                                page_rotation(doc, idx)? as i32
+                            } else {
+                                0
+                            };
+                            let next = (base + args.angle as i32).rem_euclid(360) as u16;
+                            set_page_rotation(doc, idx, next)?;
+                        }
+                        Ok((0..page_count)
+                            .map(|i| page_rotation(doc, i).unwrap_or(0))
+                            .collect())
+                    })();
+                    if res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Metadata { doc_id, set, reply }) => {
+                    let editing = set.is_some();
+                    let res = (|| -> Result<PdfDocMetadata, MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let password = passwords.get(&doc_id).map(String::as_str);
+                        let Some(args) = set else {
+                            return Ok(read_metadata(&load_lopdf(doc, password)?));
+                        };
+                        let (new_doc, meta) = edit_with_lopdf(&pdfium, doc, password, |lo| {
+                            write_metadata(lo, &args)?;
+                            Ok(read_metadata(lo))
+                        })?;
+                        docs.insert(doc_id, new_doc);
+                        Ok(meta)
+                    })();
+                    if editing && res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Annotations {
+                    doc_id,
+                    page_index,
+                    edit,
+                    reply,
+                }) => {
+                    let editing = edit.is_some();
+                    let res = (|| -> Result<Vec<PdfAnnotationInfo>, MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let password = passwords.get(&doc_id).map(String::as_str);
+                        if let Some(index) = page_index
+                            && index >= total_pages(doc)
+                        {
+                            return Err(MediaError::new(
+                                "not_found",
+                                format!("頁索引不存在: {}", index),
+                            ));
+                        }
+                        let Some(edit) = edit else {
+                            if doc.pages().iter().all(|p| p.annotations().is_empty()) {
+                                return Ok(Vec::new());
+                            }
+                            return list_annotations(doc, page_index);
+                        };
+                        // 新增回傳建立的註解，刪除與平面化回傳剩餘的註解
+                        match edit {
+                            AnnotationEdit::Add(args) => {
+                                let doc = docs.get_mut(&doc_id).ok_or_else(|| {
+                                    MediaError::new(
+                                        "not_found",
+                                        format!("未知的 docId: {}", doc_id),
+                                    )
+                                })?;
+                                let index = add_annotation(doc, &args)?;
+                                if args.kind == "freeText" {
+                                    match edit_with_lopdf(&pdfium, doc, password, |lo| {
+                                        stamp_to_free_text(lo, &args, index)
+                                    }) {
+                                        Ok((new_doc, ())) => {
+                                            docs.insert(doc_id, new_doc);
+                                        }
+                                        Err(e) => {
+                                            if let Ok(mut page) =
+                                                doc.pages().get(to_page_index(args.page_index)?)
+                                            {
+                                                discard_annotation(&mut page, index as usize);
+                                            }
+                                            return Err(e);
+                                        }
+                                    }
+                                }
+                                let doc = docs.get(&doc_id).ok_or_else(|| {
+                                    MediaError::new(
+                                        "not_found",
+                                        format!("未知的 docId: {}", doc_id),
+                                    )
+                                })?;
+                                Ok(list_annotations(doc, Some(args.page_index))?
+                                    .into_iter()
+                                    .filter(|a| a.index == index)
+                                    .collect())
+                            }
+                            AnnotationEdit::Delete(targets) => {
+                                delete_annotations(doc, &targets)?;
+                                list_annotations(doc, None)
+                            }
+                            AnnotationEdit::Flatten => {
+                                let new_doc =
+                                    flatten_document_annotations(&pdfium, doc, password)?.0;
+                                let annots = list_annotations(&new_doc, None)?;
+                                docs.insert(doc_id, new_doc);
+                                Ok(annots)
+                            }
+                        }
+                    })();
+                    if editing && res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Form {
+                    doc_id,
+                    edit,
+                    reply,
+                }) => {
+                    let editing = edit.is_some();
+                    let res = (|| -> Result<Vec<PdfFormFieldInfo>, MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let password = passwords.get(&doc_id).map(String::as_str);
+                        let new_doc = match edit {
+                            None => {
+                                if doc.form().is_none() {
+                                    return Ok(Vec::new());
+                                }
+                                return Ok(read_form_fields(&load_lopdf(doc, password)?));
+                            }
+                            Some(FormEdit::Fill(values)) => {
+                                edit_with_lopdf(&pdfium, doc, password, |lo| {
+                                    fill_form_fields(lo, &values)
+                                })?
+                                .0
+                            }
+                            Some(FormEdit::Flatten) => {
+                                flatten_document_forms(&pdfium, doc, password)?.0
+                            }
+                        };
+                        let fields = read_form_fields(&load_lopdf(&new_doc, password)?);
+                        docs.insert(doc_id, new_doc);
+                        Ok(fields)
+                    })();
+                    if editing && res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Outline {
+                    doc_id,
+                    edit,
+                    reply,
+                }) => {
+                    let editing = edit.is_some();
+                    let res = (|| -> Result<Vec<PdfOutlineItem>, MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let password = passwords.get(&doc_id).map(String::as_str);
+                        let mut items = match outlines.get(&doc_id) {
+                            Some(items) => items.clone(),
+                            None => read_document_outline(doc, password)?,
+                        };
+                        let Some(edit) = edit else {
+                            return Ok(items);
+                        };
+                        apply_outline_edit(&mut items, edit, total_pages(doc))?;
+                        // 暫存於 worker，於下一個會變更或序列化文件的請求前才寫入，連續編輯不需反覆重載
+                        outlines.insert(doc_id, items.clone());
+                        Ok(items)
+                    })();
+                    if editing && res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::TextLayer { args, reply }) => {
+                    let res = (|| -> Result<PdfTextLayer, MediaError> {
+                        let doc = docs.get(&args.doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", args.doc_id))
+                        })?;
+                        let page =
+                            doc.pages()
+                                .get(to_page_index(args.page_index)?)
+                                .map_err(|_| {
+                                    MediaError::new(
+                                        "not_found",
+                                        format!("頁索引不存在: {}", args.page_index),
+                                    )
+                                })?;
+                        let visible = page
+                            .boundaries()
+                            .crop()
+                            .or_else(|_| page.boundaries().media())
+                            .map(|b| pdf_box_from_rect(&b.bounds))
+                            .map_err(|e| {
+                                MediaError::new("parse_error", format!("讀取頁面框失敗: {e}"))
+                            })?;
+                        let rotation = page.rotation().map(rotation_to_degrees).unwrap_or(0);
+                        // 與 render_page_for_document 相同：以寬度決定縮放，高度依比例
+                        let page_w = page.width().value.max(0.01);
+                        let width_px = args.width_px.unwrap_or(page_w.round() as u32).max(1);
+                        let s = width_px as f32 / page_w;
+                        let height_px = (page.height().value * s).round() as u32;
+
+                        let text = page.text().map_err(|e| {
+                            MediaError::new("parse_error", format!("讀取頁面文字失敗: {e}"))
+                        })?;
+                        let mut runs = Vec::new();
+                        for seg in text.segments().iter() {
+                            let content = seg.text();
+                            if content.trim().is_empty() {
+                                continue;
+                            }
+                            let b = pdf_box_from_rect(&seg.bounds());
+                            let (x1, y1) =
+                                page_point_to_display(&visible, rotation, s, b.left, b.bottom);
+                            let (x2, y2) =
+                                page_point_to_display(&visible, rotation, s, b.right, b.top);
+                            // 字級與角度取自第一個非空白字元
+                            let (mut font_size, mut char_angle) = (0.0f32, 0.0f32);
+                            if let Ok(chars) = seg.chars()
+                                && let Some(ch) = chars
+                                    .iter()
+                                    .find(|c| c.unicode_char().is_some_and(|c| !c.is_whitespace()))
+                            {
+                                font_size = ch.scaled_font_size().value;
+                                char_angle = ch.angle_degrees().unwrap_or(0.0);
+                            }
+                            runs.push(PdfTextRun {
+                                text: content,
+                                x: x1.min(x2),
+                                y: y1.min(y2),
+                                width: (x2 - x1).abs(),
+                                height: (y2 - y1).abs(),
+                                font_size: font_size * s,
+                                // 字元角度為使用者空間逆時針；轉為顯示空間順時針（同 CSS rotate）
+                                angle: (rotation as f32 - char_angle).rem_euclid(360.0),
+                            });
+                        }
+                        Ok(PdfTextLayer {
+                            page_index: args.page_index,
+                            width_px,
+                            height_px,
+                            runs,
+                        })
+                    })();
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Search { args, reply }) => {
+                    let res = (|| -> Result<PdfSearchSummary, MediaError> {
+                        let doc = docs.get(&args.doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", args.doc_id))
+                        })?;
+                        if args.query.is_empty() {
+                            return Err(MediaError::new("invalid_input", "搜尋字串不可為空"));
+                        }
+                        let mut pattern = if args.regex.unwrap_or(false) {
+                            args.query.clone()
+                        } else {
+                            regex::escape(&args.query)
+                        };
+                        if args.whole_word.unwrap_or(false) {
+                            pattern = format!(r"\b(?:{})\b", pattern);
+                        }
+                        let re = regex::RegexBuilder::new(&pattern)
+                            .case_insensitive(!args.match_case.unwrap_or(false))
+                            .build()
+                            .map_err(|e| {
+                                MediaError::new("invalid_input", format!("無效的正規表示式: {e}"))
+                            })?;
+                        let g = args.r#gen.unwrap_or(0);
+                        let max_hits = args.max_hits.unwrap_or(10_000);
+                        let mut summary = PdfSearchSummary {
+                            doc_id: args.doc_id,
+                            r#gen: g,
+                            total: 0,
+                            pages_searched: 0,
+                            canceled: false,
+                        };
+                        for idx in 0..total_pages(doc) {
+                            // 每頁檢查一次是否已被取消或被新的搜尋取代
+                            let canceled = SEARCH_MIN_GEN
+                                .lock()
+                                .unwrap()
+                                .get(&args.doc_id)
+                                .is_some_and(|min| g < *min);
+                            if canceled {
+                                summary.canceled = true;
+                                break;
+                            }
+                            let page = doc.pages().get(to_page_index(idx)?).map_err(|_| {
+                                MediaError::new("not_found", format!("頁索引不存在: {}", idx))
+                            })?;
+                            let mut hits = search_page_text(&page, &re)?;
+                            summary.pages_searched += 1;
+                            hits.truncate(max_hits.saturating_sub(summary.total));
+                            if !hits.is_empty() {
+                                summary.total += hits.len();
+                                let payload = PdfSearchPageHits {
+                                    doc_id: args.doc_id,
+                                    r#gen: g,
+                                    page_index: idx,
+                                    hits,
+                                };
+                                if let Err(err) = app.emit("pdf-search-hit", &payload) {
+                                    warn!("Failed to emit pdf-search-hit event: {err}");
+                                }
+                            }
+                            if summary.total >= max_hits {
+                                break;
+                            }
+                        }
+                        if let Err(err) = app.emit("pdf-search-done", &summary) {
+                            warn!("Failed to emit pdf-search-done event: {err}");
+                        }
+                        Ok(summary)
+                    })();
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::ExtractText { args, reply }) => {
+                    let res = (|| -> Result<Vec<PdfPageTextResult>, MediaError> {
+                        let doc = docs.get(&args.doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", args.doc_id))
+                        })?;
+                        let targets = parse_page_range(
+                            args.range.as_deref().unwrap_or(""),
+                            total_pages(doc),
+                        )?;
+                        let granularity = args.granularity.as_deref().unwrap_or("page");
+                        let mut out = Vec::with_capacity(targets.len());
+                        for idx in targets {
+                            let page = doc.pages().get(to_page_index(idx)?).map_err(|_| {
+                                MediaError::new("not_found", format!("頁索引不存在: {}", idx))
+                            })?;
+                            out.push(extract_page_text(&page, idx, granularity)?);
+                        }
+                        Ok(out)
+                    })();
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::DetectBlankPages { args, reply }) => {
+                    let doc_id = args.doc_id;
+                    let res = (|| -> Result<PdfBlankPagesResult, MediaError> {
+                        use pdfium_render::prelude::*;
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let page_count = total_pages(doc);
+                        let mut targets =
+                            parse_page_range(args.range.as_deref().unwrap_or(""), page_count)?;
+                        let mut seen = HashSet::new();
+                        targets.retain(|i| seen.insert(*i));
+                        let dpi = args.dpi.unwrap_or(40).clamp(10, 150) as f32;
+                        let ink_threshold = args.ink_threshold.unwrap_or(0.001).max(0.0);
+                        let noise = args.noise_threshold.unwrap_or(0.25).clamp(0.0, 1.0);
+                        let tolerance = args.tolerance.unwrap_or(64);
+                        let edge_margin = args.edge_margin.unwrap_or(0.03).clamp(0.0, 0.4);
+                        let mut out = Vec::with_capacity(targets.len());
+                        for idx in targets {
+                            let page = doc.pages().get(to_page_index(idx)?).map_err(|_| {
+                                MediaError::new("not_found", format!("頁索引不存在: {}", idx))
+                            })?;
+                            let width_px = ((page.width().value * dpi / 72.0).ceil() as i32).max(1);
+                            let bitmap = page
+                                .render_with_config(
+                                    &PdfRenderConfig::new().set_target_width(width_px),
+                                )
+                                .map_err(|e| {
+                                    MediaError::new("parse_error", format!("渲染失敗: {e}"))
+                                })?;
+                            let coverage = blank_page_coverage(
+                                &bitmap.as_image().to_luma8(),
+                                tolerance,
+                                noise,
+                                edge_margin,
+                            );
+                            out.push(PdfBlankPageScore {
+                                index: idx,
+                                coverage,
+                                blank: coverage <= ink_threshold,
+                            });
+                        }
+                        let blank: Vec<u32> =
+                            out.iter().filter(|p| p.blank).map(|p| p.index).collect();
+                        if !args.delete.unwrap_or(false) || blank.is_empty() {
+                            return Ok(PdfBlankPagesResult {
+                                pages: out,
+                                deleted: Vec::new(),
+                                pages_after: None,
+                            });
+                        }
+                        if blank.len() == page_count as usize {
+                            return Err(MediaError::new(
+                                "invalid_input",
+                                "所有頁面皆為空白，無法全部刪除",
+                            ));
+                        }
+                        // 偵測與刪除在同一請求內完成，避免期間文件被其他請求變更
+                        let (new_doc, outline) = delete_document_pages(
+                            &pdfium,
+                            doc,
+                            crypts.get(&doc_id),
+                            passwords.get(&doc_id).map(String::as_str),
+                            blank.clone(),
+                        )?;
+                        let pages_after = total_pages(&new_doc) as usize;
+                        docs.insert(doc_id, new_doc);
+                        set_pending_outline(&mut outlines, doc_id, outline);
+                        Ok(PdfBlankPagesResult {
+                            pages: out,
+                            deleted: blank,
+                            pages_after: Some(pages_after),
+                        })
+                    })();
+                    if matches!(&res, Ok(r) if !r.deleted.is_empty()) {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Deskew { args, reply }) => {
+                    let doc_id = args.doc_id;
+                    let mut touched = false;
+                    let res = (|| -> Result<Vec<PdfDeskewResult>, MediaError> {
+                        let doc = docs.get_mut(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let page_count = total_pages(doc);
+                        let mut targets =
+                            parse_page_range(args.range.as_deref().unwrap_or(""), page_count)?;
+                        let mut seen = HashSet::new();
+                        targets.retain(|i| seen.insert(*i));
+                        let max_angle = args.max_angle.unwrap_or(5.0).clamp(0.1, 45.0);
+                        let min_angle = args.min_angle.unwrap_or(0.1).max(0.0);
+                        let apply = args.apply.unwrap_or(false);
+                        let mut out = Vec::with_capacity(targets.len());
+                        for idx in targets {
+                            let mut page =
+                                doc.pages_mut().get(to_page_index(idx)?).map_err(|_| {
+                                    MediaError::new("not_found", format!("頁索引不存在: {}", idx))
+                                })?;
+                            let apply = apply.then_some(&mut touched);
+                            out.push(deskew_page(
+                                doc, &mut page, idx, max_angle, min_angle, None, apply,
+                            )?);
+                        }
+                        Ok(out)
+                    })();
+                    if touched {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::DeskewPreview { args, reply }) => {
+                    let res = (|| -> Result<PageRender, MediaError> {
+                        let doc = docs.get(&args.doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", args.doc_id))
+                        })?;
+                        if args.page_index >= total_pages(doc) {
+                            return Err(MediaError::new(
+                                "not_found",
+                                format!("頁索引不存在: {}", args.page_index),
+                            ));
+                        }
+                        // 於暫存副本套用後渲染，不變更原文件
+                        let mut tmp = rebuild_document(&pdfium, doc, &[args.page_index])?;
+                        let mut page = tmp.pages_mut().get(0).map_err(|e| {
+                            MediaError::new("io_error", format!("讀取暫存頁面失敗: {e}"))
+                        })?;
+                        let max_angle = args.max_angle.unwrap_or(5.0).clamp(0.1, 45.0);
+                        deskew_page(
+                            &tmp,
+                            &mut page,
+                            args.page_index,
+                            max_angle,
+                            0.0,
+                            args.angle,
+                            Some(&mut true),
+                        )?;
+                        drop(page);
+                        let render_args = PdfRenderArgs {
+                            doc_id: args.doc_id,
+                            page_index: 0,
+                            scale: None,
+                            dpi: None,
+                            format: args.format.clone(),
+                            target_width: Some(args.target_width.unwrap_or(800)),
+                            quality: args.quality,
+                            r#gen: None,
+                        };
+                        let mut rendered = render_page_for_document(&tmp, &render_args)?;
+                        rendered.page_index = args.page_index;
+                        Ok(rendered)
+                    })();
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::AutoOrient { args, reply }) => {
+                    let doc_id = args.doc_id;
+                    let mut touched = false;
+                    let res = (|| -> Result<Vec<PdfOrientationGuess>, MediaError> {
+                        use pdfium_render::prelude::*;
+                        let doc = docs.get_mut(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let page_count = total_pages(doc);
+                        let mut targets =
+                            parse_page_range(args.range.as_deref().unwrap_or(""), page_count)?;
+                        let mut seen = HashSet::new();
+                        targets.retain(|i| seen.insert(*i));
+                        let apply = args.apply.unwrap_or(false);
+                        let min_conf = args.min_confidence.unwrap_or(0.6);
+                        let dpi = args.dpi.unwrap_or(72).clamp(36, 200) as f32;
+
+                        let mut out = Vec::with_capacity(targets.len());
+                        for idx in targets {
+                            let mut page =
+                                doc.pages_mut().get(to_page_index(idx)?).map_err(|_| {
+                                    MediaError::new("not_found", format!("頁索引不存在: {}", idx))
+                                })?;
+                            let rotation = page.rotation().map(rotation_to_degrees).unwrap_or(0);
+                            let (guess, method) = match orientation_from_text(&page) {
+                                Some(g) => (Some(g), "text"),
+                                None => {
+                                    let width_px =
+                                        ((page.width().value * dpi / 72.0).ceil() as i32).max(1);
+                                    let bitmap = page
+                                        .render_with_config(
+                                            &PdfRenderConfig::new().set_target_width(width_px),
+                                        )
+                                        .map_err(|e| {
+                                            MediaError::new("parse_error", format!("渲染失敗: {e}"))
+                                        })?;
+                                    let luma = bitmap.as_image().to_luma8();
+                                    (orientation_from_image(&luma), "image")
+                                }
+                            };
+                            let (correction, confidence, method) = match guess {
+                                Some((c, conf)) => (c, conf, method),
+                                None => (0, 0.0, "none"),
+                            };
+                            let suggested = (rotation + correction) % 360;
+                            let applied = apply && correction != 0 && confidence >= min_conf;
+                            if applied && let Some(rot) = degrees_to_rotation(suggested) {
+                                page.set_rotation(rot);
+                                // 之後的頁面渲染失敗時，已旋轉的頁面仍需標記文件
+                                touched = true;
+                            }
+                            out.push(PdfOrientationGuess {
+                                index: idx,
+                                rotation,
+                                correction,
+                                suggested,
+                                confidence,
+                                method: method.to_string(),
+                                applied,
+                            });
+                        }
+                        Ok(out)
+                    })();
+                    if touched {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::PageRotations { doc_id, reply }) => {
+                    let res = docs
+                        .get(&doc_id)
+                        .ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })
+                        .map(|doc| {
+                            (0..total_pages(doc))
+                                .map(|i| page_rotation(doc, i).unwrap_or(0))
+                                .collect()
+                        });
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::RotatePageRelative {
+                    doc_id,
+                    index,
+                    delta_deg,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<u16, MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let cur_deg = page_rotation(doc, index)? as i16;
+                        // 規範到 0/90/180/270（非 90 倍數的差值視為 0）
+                        let add: i16 = ((delta_deg % 360) + 360) % 360;
+                        let next = match ((cur_deg + add) % 360 + 360) % 360 {
+                            n @ (90 | 180 | 270) => n as u16,
+                            _ => 0,
+                        };
+                        set_page_rotation(doc, index, next)?;
+                        Ok(next)
+                    })();
+                    if res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::CopyPage {
+                    src_doc_id,
+                    src_index,
+                    dest_doc_id,
+                    dest_index,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<usize, MediaError> {
+                        if src_doc_id == dest_doc_id {
+                            // 同文件複製：先取出文件所有權，避免 HashMap 借用衝突
+                            let doc = docs.get(&src_doc_id).ok_or_else(|| {
+                                MediaError::new(
+                                    "not_found",
+                                    format!("未知的 docId: {}", src_doc_id),
+                                )
+                            })?;
+                            // 用暫存文件承接來源頁，Pdfium 不支援來源與目標為同一文件的匯入
+                            let tmp = rebuild_document(&pdfium, doc, &[src_index])?;
+                            import_pages(doc, &tmp, &[0], dest_index)?;
+                            Ok(total_pages(doc) as usize)
+                        } else {
+                            // 跨文件：兩份文件皆以共享借用取得
+                            let dest = docs.get(&dest_doc_id).ok_or_else(|| {
+                                MediaError::new(
+                                    "not_found",
+                                    format!("未知的 docId: {}", dest_doc_id),
+                                )
+                            })?;
+                            let src = docs.get(&src_doc_id).ok_or_else(|| {
+                                MediaError::new(
+                                    "not_found",
+                                    format!("未知的 docId: {}", src_doc_id),
+                                )
+                            })?;
+                            import_pages(dest, src, &[src_index], dest_index)?;
+                            Ok(total_pages(dest) as usize)
+                        }
+                    })();
+                    if res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, dest_doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Save {
+                    doc_id,
+                    dest_path,
+                    overwrite,
+                    backup,
+                    encryption,
+                    flatten_forms,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<PdfSaveResult, MediaError> {
+                        let dest = match (dest_path, overwrite.unwrap_or(false)) {
+                            (Some(p), ow) => {
+                                if !ow && Path::new(&p).exists() {
+                                    return Err(MediaError::new(
+                                        "io_error",
+                                        format!("目的檔已存在：{}（overwrite=false）", p),
+                                    ));
+                                }
+                                p
+                            }
+                            (None, true) => paths.get(&doc_id).cloned().ok_or_else(|| {
+                                MediaError::new(
+                                    "invalid_input",
+                                    "未提供 destPath，且無可覆蓋之原始路徑",
+                                )
+                            })?,
+                            _ => {
+                                return Err(MediaError::new(
+                                    "invalid_input",
+                                    "請提供 destPath 或設定 overwrite=true 以覆蓋原檔",
+                                ));
+                            }
+                        };
+                        // 先前寫入失敗的書籤於此重試並回報
+                        flush_outline(&pdfium, &mut docs, &passwords, &mut outlines, doc_id)?;
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        // 平面化到副本，寫入成功後才取代開啟中的文件；失敗時原文件保持不變
+                        let flat = match flatten_forms {
+                            true => Some(
+                                flatten_document_forms(
+                                    &pdfium,
+                                    doc,
+                                    passwords.get(&doc_id).map(String::as_str),
+                                )?
+                                .0,
+                            ),
+                            false => None,
+                        };
+                        let doc = flat.as_ref().unwrap_or(doc);
+                        let (backup_path, password) = match &encryption {
+                            Some(enc) => {
+                                let (bytes, reopen) = encrypt_pdfium_document(
+                                    doc,
+                                    passwords.get(&doc_id).map(|p| p.as_str()),
+                                    enc,
+                                )?;
+                                let backup_path =
+                                    write_file_atomic(Path::new(&dest), backup, |file| {
+                                        std::io::Write::write_all(file, &bytes).map_err(|e| {
+                                            MediaError::new(
+                                                "io_error",
+                                                format!("寫入檔案失敗: {e}"),
+                                            )
+                                        })
+                                    })?;
+                                (backup_path, Some(reopen).filter(|p| !p.is_empty()))
+                            }
+                            None => (
+                                save_document_atomic(doc, Path::new(&dest), backup)?,
+                                passwords.get(&doc_id).cloned(),
+                            ),
+                        };
+                        // 寫入已完成；由新檔重新載入失敗時沿用記憶體中的文件，另以 reloadError 回報
+                        let reload_error = match load_pdf_file(
+                            &pdfium,
+                            &dest,
+                            password.as_deref(),
+                            "重新載入 PDF 失敗",
+                        ) {
+                            Ok(reloaded) => {
+                                // 以新的加密參數另存時更新
+                                if encryption.is_some() {
+                                    match read_document_crypt(
+                                        &reloaded,
+                                        || fs::read(&dest).ok(),
+                                        password.as_deref(),
+                                    ) {
+                                        Some(crypt) => crypts.insert(doc_id, crypt),
+                                        None => crypts.remove(&doc_id),
+                                    };
+                                }
+                                match password {
+                                    Some(pw) => passwords.insert(doc_id, pw),
+                                    None => passwords.remove(&doc_id),
+                                };
+                                docs.insert(doc_id, reloaded);
+                                None
+                            }
+                            Err(e) => {
+                                warn!("儲存後重新載入失敗 {}: {}", dest, e.message);
+                                if let Some(flat) = flat {
+                                    docs.insert(doc_id, flat);
+                                }
+                                Some(e.message)
+                            }
+                        };
+                        let pages = docs
+                            .get(&doc_id)
+                            .map(|d| total_pages(d) as usize)
+                            .unwrap_or(0);
+                        paths.insert(doc_id, dest.clone());
+                        Ok(PdfSaveResult {
+                            path: dest,
+                            pages,
+                            backup_path,
+                            reload_error,
+                        })
+                    })();
+                    if res.is_ok() {
+                        let state = states.entry(doc_id).or_default();
+                        state.saved_revision = state.revision;
+                        state.autosaved_revision = state.revision;
+                        if let Some(dir) = recovery.as_deref() {
+                            remove_recovery_files(dir, &recovery_id(doc_id));
+                        }
+                        emit_doc_state(&app, &doc_state_snapshot(doc_id, &states, &paths));
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::ImageToPdf {
+                    src_path,
+                    dest_path,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<String, MediaError> {
+                        let mut doc = pdfium.create_new_pdf().map_err(|e| {
+                            MediaError::new("io_error", format!("建立 PDF 失敗: {e}"))
+                        })?;
+                        append_image_page(&mut doc, Path::new(&src_path))?;
+
+                        doc.save_to_file(&dest_path).map_err(|e| {
+                            MediaError::new("io_error", format!("寫入 PDF 失敗: {e}"))
+                        })?;
+                        Ok(dest_path)
+                    })();
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::DuplicatePages {
+                    doc_id,
+                    range,
+                    dest_index,
+                    copies,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<usize, MediaError> {
+                        let doc = docs.get_mut(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let page_count = total_pages(doc);
+                        let selected = parse_page_range(&range, page_count)?;
+                        if copies == 0 {
+                            return Err(MediaError::new("invalid_input", "copies 必須大於 0"));
+                        }
+                        // 預設插入於所選頁面中最後一頁之後
+                        let insert_at = match dest_index {
+                            Some(i) if i > page_count => {
+                                return Err(MediaError::new(
+                                    "invalid_input",
+                                    format!("頁索引超出範圍: {} > {}", i, page_count),
+                                ));
+                            }
+                            Some(i) => i,
+                            None => selected.iter().max().map(|m| m + 1).unwrap_or(page_count),
+                        };
+                        let order: Vec<u32> =
+                            (0..copies).flat_map(|_| selected.iter().copied()).collect();
+                        // 先複製到暫存文件，Pdfium 不支援來源與目標為同一文件的匯入
+                        let tmp = rebuild_document(&pdfium, doc, &order)?;
+                        import_pages(
+                            doc,
+                            &tmp,
+                            &(0..total_pages(&tmp)).collect::<Vec<_>>(),
+                            insert_at,
+                        )?;
+                        Ok(total_pages(doc) as usize)
+                    })();
+                    if res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::ReversePages {
+                    doc_id,
+                    range,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<usize, MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let page_count = total_pages(doc);
+                        let mut selected =
+                            parse_page_range(range.as_deref().unwrap_or(""), page_count)?;
+                        selected.sort_unstable();
+                        selected.dedup();
+                        // 所選頁面的位置不變，以反向順序填回
+                        let mut order: Vec<u32> = (0..page_count).collect();
+                        for (slot, src) in selected.iter().zip(selected.iter().rev()) {
+                            order[*slot as usize] = *src;
+                        }
+                        let password = passwords.get(&doc_id).map(String::as_str);
+                        let new_doc = restore_encryption(
+                            &pdfium,
+                            crypts.get(&doc_id),
+                            password,
+                            rebuild_document(&pdfium, doc, &order)?,
+                        )?;
+                        let outline = carry_outline(&[(doc, password, 0)], &order)?;
+                        let pages_after = total_pages(&new_doc) as usize;
+                        docs.insert(doc_id, new_doc);
+                        set_pending_outline(&mut outlines, doc_id, outline);
+                        Ok(pages_after)
+                    })();
+                    if res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::InterleavePages {
+                    doc_id,
+                    other_doc_id,
+                    reverse_second,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<usize, MediaError> {
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        // 兩份文件時先合併為暫存文件，使頁面都來自同一來源
+                        let combined;
+                        let other_doc;
+                        let (source, first_len, total) = match other_doc_id {
+                            Some(other_id) if other_id != doc_id => {
+                                let other = docs.get(&other_id).ok_or_else(|| {
+                                    MediaError::new(
+                                        "not_found",
+                                        format!("未知的 docId: {}", other_id),
+                                    )
+                                })?;
+                                let first_len = total_pages(doc);
+                                let tmp = rebuild_document(
+                                    &pdfium,
+                                    doc,
+                                    &(0..first_len).collect::<Vec<_>>(),
+                                )?;
+                                import_pages(
+                                    &tmp,
+                                    other,
+                                    &(0..total_pages(other)).collect::<Vec<_>>(),
+                                    first_len,
+                                )?;
+                                other_doc = Some((other_id, other));
+                                combined = tmp;
+                                let total = total_pages(&combined);
+                                (&combined, first_len, total)
+                            }
+                            Some(_) => {
+                                return Err(MediaError::new(
+                                    "invalid_input",
+                                    "otherDocId 不可與 docId 相同",
+                                ));
+                            }
+                            None => {
+                                // 同一文件前後兩半：前半為正面，後半為背面
+                                other_doc = None;
+                                let total = total_pages(doc);
+                                (doc, total.div_ceil(2), total)
+                            }
+                        };
+                        if total < 2 {
+                            return Err(MediaError::new("invalid_input", "頁數不足，無法交錯"));
+                        }
+                        let first: Vec<u32> = (0..first_len).collect();
+                        let mut second: Vec<u32> = (first_len..total).collect();
+                        if reverse_second {
+                            second.reverse();
+                        }
+                        let mut order: Vec<u32> = Vec::with_capacity(total as usize);
+                        for i in 0..first.len().max(second.len()) {
+                            if let Some(p) = first.get(i) {
+                                order.push(*p);
+                            }
+                            if let Some(p) = second.get(i) {
+                                order.push(*p);
+                            }
+                        }
+                        // 沿用目標文件的加密
+                        let password = passwords.get(&doc_id).map(String::as_str);
+                        let new_doc = restore_encryption(
+                            &pdfium,
+                            crypts.get(&doc_id),
+                            password,
+                            rebuild_document(&pdfium, source, &order)?,
+                        )?;
+                        // 書籤取自原始文件（暫存合併文件不含書籤），第二份文件的頁接在第一份之後
+                        let mut sources = vec![(doc, password, 0)];
+                        sources.extend(other_doc.map(|(other_id, other)| {
+                            (
+                                other,
+                                passwords.get(&other_id).map(String::as_str),
+                                first_len,
+                            )
+                        }));
+                        let outline = carry_outline(&sources, &order)?;
+                        let pages_after = total_pages(&new_doc) as usize;
+                        docs.insert(doc_id, new_doc);
+                        set_pending_outline(&mut outlines, doc_id, outline);
+                        Ok(pages_after)
+                    })();
+                    if res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::InsertFromFile {
+                    doc_id,
+                    src_paths,
+                    src_password,
+                    range,
+                    index,
+                    reply,
+                }) => {
+                    let res = (|| -> Result<usize, MediaError> {
+                        if src_paths.is_empty() {
+                            return Err(MediaError::new("invalid_input", "缺少來源檔案"));
+                        }
+                        // 來源頁面先收集到暫存文件，再一次插入目標；來源 PDF 不註冊到 docs
+                        let mut tmp = pdfium.create_new_pdf().map_err(|e| {
+                            MediaError::new("io_error", format!("建立暫存 PDF 失敗: {e}"))
+                        })?;
+                        // 來源書籤（已對應到插入後的頁索引），插入完成後附加到目標書籤之後
+                        let mut src_outline: Vec<PdfOutlineItem> = Vec::new();
+                        for src_path in &src_paths {
+                            let p = Path::new(src_path);
+                            match infer_media_type(p) {
+                                MediaType::Pdf => {
+                                    let src = load_pdf_file(
+                                        &pdfium,
+                                        src_path,
+                                        src_password.as_deref(),
+                                        "開啟 PDF 失敗",
+                                    )?;
+                                    let selected = parse_page_range(
+                                        range.as_deref().unwrap_or(""),
+                                        total_pages(&src),
+                                    )?;
+                                    let at = total_pages(&tmp);
+                                    import_pages(&tmp, &src, &selected, at)?;
+                                    let mut map = HashMap::new();
+                                    for (pos, old) in selected.iter().enumerate() {
+                                        map.entry(*old).or_insert(index + at + pos as u32);
+                                    }
+                                    let items =
+                                        read_document_outline(&src, src_password.as_deref())?;
+                                    src_outline.extend(remap_outline(items, &map));
+                                }
+                                MediaType::Image => append_image_page(&mut tmp, p)?,
+                                MediaType::Unknown => {
+                                    return Err(MediaError::new(
+                                        "invalid_input",
+                                        format!("不支援的檔案類型: {}", src_path),
+                                    ));
+                                }
+                            }
+                        }
+                        let doc = docs.get(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        import_pages(
+                            doc,
+                            &tmp,
+                            &(0..total_pages(&tmp)).collect::<Vec<_>>(),
+                            index,
+                        )?;
+                        let pages_after = total_pages(doc) as usize;
+                        if !src_outline.is_empty() {
+                            // 目標原有書籤指向頁面物件，插入後仍對應正確頁面
+                            let mut items = read_document_outline(
+                                doc,
+                                passwords.get(&doc_id).map(String::as_str),
+                            )?;
+                            items.append(&mut src_outline);
+                            set_pending_outline(&mut outlines, doc_id, Some(items));
+                        }
+                        Ok(pages_after)
+                    })();
+                    if res.is_ok() {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Watermark { args, reply }) => {
+                    let doc_id = args.doc_id;
+                    // 逐頁修改中途失敗時，已處理的頁面仍需標記為已修改
+                    let mut touched = false;
+                    let res = (|| -> Result<usize, MediaError> {
+                        let text = args.text.as_deref().filter(|t| !t.trim().is_empty());
+                        if text.is_some() == args.image_path.is_some() {
+                            return Err(MediaError::new(
+                                "invalid_input",
+                                "需指定 text 或 imagePath 其中之一",
+                            ));
+                        }
+                        let layer = args.layer.as_deref().unwrap_or("over");
+                        if !matches!(layer, "over" | "under") {
+                            return Err(MediaError::new(
+                                "invalid_input",
+                                format!("不支援的圖層: {}", layer),
+                            ));
+                        }
+                        let tile = args.tile.unwrap_or(false);
+                        let position = args.position.as_deref().unwrap_or("center");
+                        if !tile && !ANCHOR_POSITIONS.contains(&position) {
+                            return Err(MediaError::new(
+                                "invalid_input",
+                                format!("不支援的位置: {}", position),
+                            ));
+                        }
+                        let opacity = args.opacity.unwrap_or(0.3).clamp(0.0, 1.0);
+                        let font_size = args.font_size.unwrap_or(48.0);
+                        if font_size <= 0.0 {
+                            return Err(MediaError::new("invalid_input", "字型大小需大於 0"));
+                        }
+                        let (r, g, b) =
+                            parse_hex_color(args.color.as_deref().unwrap_or("#808080"))?;
+                        let fill = PdfColor::new(r, g, b, (opacity * 255.0).round() as u8);
+                        let angle = args.rotation.unwrap_or(0.0).to_radians();
+                        let (sin, cos) = angle.sin_cos();
+                        let margin = args.margin_pt.unwrap_or(36.0).max(0.0);
+                        let gap = args.tile_gap_pt.unwrap_or(72.0).max(0.0);
+
+                        // 影像物件不套用填色透明度，改將不透明度乘入 alpha 通道
+                        let image = match &args.image_path {
+                            Some(p) => {
+                                let img = image::open(p).map_err(|e| {
+                                    MediaError::new("decode_error", format!("解碼圖片失敗: {e}"))
+                                })?;
+                                Some(if opacity < 1.0 {
+                                    let mut rgba = img.to_rgba8();
+                                    for px in rgba.pixels_mut() {
+                                        px[3] = (px[3] as f32 * opacity).round() as u8;
+                                    }
+                                    image::DynamicImage::ImageRgba8(rgba)
+                                } else {
+                                    img
+                                })
+                            }
+                            None => None,
+                        };
+
+                        let doc = docs.get_mut(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let font = match text {
+                            Some(t) => Some(load_text_font(
+                                doc,
+                                args.font.as_deref(),
+                                args.font_path.as_deref(),
+                                t,
+                            )?),
+                            None => None,
+                        };
+                        let lines: Vec<&str> =
+                            text.map(|t| t.lines().collect()).unwrap_or_default();
+                        let text_spec = font.map(|f| (lines.as_slice(), f, font_size, fill));
+
+                        let page_count = total_pages(doc);
+                        let mut selected =
+                            parse_page_range(args.range.as_deref().unwrap_or(""), page_count)?;
+                        selected.sort_unstable();
+                        selected.dedup();
+                        let page_indices = selected
+                            .iter()
+                            .map(|idx| to_page_index(*idx).map(|i| (*idx, i)))
+                            .collect::<Result<Vec<_>, _>>()?;
+
+                        // 影像於所有頁面排版完成後一次加入，共用同一個 XObject
+                        let mut image_placements: Vec<(u32, Vec<[f32; 6]>)> = Vec::new();
+                        for (idx, idx_u16) in page_indices {
+                            let mut page = doc.pages_mut().get(idx_u16).map_err(|_| {
+                                MediaError::new("not_found", format!("頁索引不存在: {}", idx))
+                            })?;
+                            // 物件全部加入後才重建內容串流一次
+                            page.set_content_regeneration_strategy(
+                                PdfPageContentRegenerationStrategy::Manual,
+                            );
+                            let visible = page
+                                .boundaries()
+                                .crop()
+                                .or_else(|_| page.boundaries().media())
+                                .map(|b| pdf_box_from_rect(&b.bounds))
+                                .map_err(|e| {
+                                    MediaError::new("parse_error", format!("讀取頁面框失敗: {e}"))
+                                })?;
+                            let rotation = page.rotation().map(rotation_to_degrees).unwrap_or(0);
+                            // 以顯示方向（左下為原點）排版，再換回未旋轉的使用者空間
+                            let to_user = invert_matrix(page_placement_matrix(
+                                &visible, rotation, 1.0, 0.0, 0.0,
+                            ));
+                            let (w0, h0) =
+                                (visible.right - visible.left, visible.top - visible.bottom);
+                            let (page_w, page_h) = if matches!(rotation, 90 | 270) {
+                                (h0, w0)
+                            } else {
+                                (w0, h0)
+                            };
+                            let original = page.objects().len();
+
+                            let map_err = |e: PdfiumError| {
+                                MediaError::new("io_error", format!("建立浮水印失敗: {e}"))
+                            };
+
+                            // 影像只需尺寸；文字先建立第一組物件量測，多行逐行水平置中、行距為字級 1.2 倍
+                            let mut first = Vec::new();
+                            let (block_w, block_h, offsets) = match (&image, text_spec) {
+                                (Some(img), _) => {
+                                    let w = args.image_width_pt.unwrap_or(page_w * 0.4).max(1.0);
+                                    (
+                                        w,
+                                        w * img.height() as f32 / img.width().max(1) as f32,
+                                        Vec::new(),
+                                    )
+                                }
+                                (None, Some(spec)) => {
+                                    first = create_watermark_objects(page.objects_mut(), spec)?;
+                                    touched = true;
+                                    let pitch = font_size * 1.2;
+                                    let n = first.len();
+                                    let mut bounds = Vec::with_capacity(n);
+                                    for obj in &first {
+                                        let q = obj.bounds().map_err(map_err)?;
+                                        bounds.push((
+                                            q.left().value,
+                                            q.bottom().value,
+                                            q.right().value,
+                                            q.top().value,
+                                        ));
+                                    }
+                                    let block_w =
+                                        bounds.iter().map(|(l, _, r, _)| r - l).fold(0.0, f32::max);
+                                    let max_h =
+                                        bounds.iter().map(|(_, b, _, t)| t - b).fold(0.0, f32::max);
+                                    let block_h = (n.saturating_sub(1)) as f32 * pitch + max_h;
+                                    let offsets: Vec<(f32, f32)> = bounds
+                                        .iter()
+                                        .enumerate()
+                                        .map(|(i, (l, b, r, _))| {
+                                            (
+                                                (block_w - (r - l)) / 2.0 - l,
+                                                (n - 1 - i) as f32 * pitch - b,
+                                            )
+                                        })
+                                        .collect();
+                                    (block_w, block_h, offsets)
+                                }
+                                (None, None) => {
+                                    return Err(MediaError::new("invalid_input", "缺少浮水印內容"));
+                                }
+                            };
+                            let half_w = (block_w * cos.abs() + block_h * sin.abs()) / 2.0;
+                            let half_h = (block_w * sin.abs() + block_h * cos.abs()) / 2.0;
+
+                            let centers: Vec<(f32, f32)> = if tile {
+                                // 以頁面中心為基準交錯鋪排，只保留與頁面重疊者
+                                let (step_x, step_y) = (half_w * 2.0 + gap, half_h * 2.0 + gap);
+                                let nx = (page_w / 2.0 / step_x).ceil() as i32 + 1;
+                                let ny = (page_h / 2.0 / step_y).ceil() as i32;
+                                let mut out = Vec::new();
+                                for j in -ny..=ny {
+                                    let shift = if j % 2 != 0 { step_x / 2.0 } else { 0.0 };
+                                    for i in -nx..=nx {
+                                        let (x, y) = (
+                                            page_w / 2.0 + i as f32 * step_x + shift,
+                                            page_h / 2.0 + j as f32 * step_y,
+                                        );
+                                        if x + half_w > 0.0
+                                            && x - half_w < page_w
+                                            && y + half_h > 0.0
+                                            && y - half_h < page_h
+                                        {
+                                            out.push((x, y));
+                                        }
+                                    }
+                                }
+                                out
+                            } else {
+                                let (x, y) = anchor_center(
+                                    position, page_w, page_h, half_w, half_h, margin,
+                                )?;
+                                vec![(
+                                    x + args.offset_x.unwrap_or(0.0),
+                                    y + args.offset_y.unwrap_or(0.0),
+                                )]
+                            };
+                            let places = centers.iter().map(|(cx, cy)| {
+                                concat_matrix(
+                                    concat_matrix(
+                                        [cos, sin, -sin, cos, 0.0, 0.0],
+                                        [1.0, 0.0, 0.0, 1.0, *cx, *cy],
+                                    ),
+                                    to_user,
+                                )
+                            });
+
+                            let Some(spec) = text_spec.filter(|_| image.is_none()) else {
+                                // 影像的單位方形先縮放為區塊大小並置中
+                                let unit =
+                                    [block_w, 0.0, 0.0, block_h, -block_w / 2.0, -block_h / 2.0];
+                                image_placements.push((
+                                    idx,
+                                    places.map(|place| concat_matrix(unit, place)).collect(),
+                                ));
+                                continue;
+                            };
+                            for (k, place) in places.enumerate() {
+                                let mut objs = if k == 0 {
+                                    std::mem::take(&mut first)
+                                } else {
+                                    create_watermark_objects(page.objects_mut(), spec)?
+                                };
+                                for (obj, (dx, dy)) in objs.iter_mut().zip(&offsets) {
+                                    let m = concat_matrix(
+                                        [
+                                            1.0,
+                                            0.0,
+                                            0.0,
+                                            1.0,
+                                            dx - block_w / 2.0,
+                                            dy - block_h / 2.0,
+                                        ],
+                                        place,
+                                    );
+                                    obj.transform(m[0], m[1], m[2], m[3], m[4], m[5])
+                                        .map_err(map_err)?;
+                                }
+                            }
+
+                            // 置於內容下方：將原有物件依序移到浮水印之後
+                            if layer == "under" {
+                                for _ in 0..original {
+                                    let obj = page
+                                        .objects_mut()
+                                        .remove_object_at_index(0)
+                                        .map_err(map_err)?;
+                                    page.objects_mut().add_object(obj).map_err(map_err)?;
+                                }
+                            }
+                            page.regenerate_content().map_err(map_err)?;
+                        }
+
+                        if let Some(img) = &image {
+                            add_image_watermark(
+                                &pdfium,
+                                doc,
+                                img,
+                                &image_placements,
+                                layer == "under",
+                                &mut touched,
+                            )?;
+                        }
+                        Ok(selected.len())
+                    })();
+                    if touched {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::HeaderFooter { args, reply }) => {
+                    let doc_id = args.doc_id;
+                    // 逐頁修改中途失敗時，已處理的頁面仍需標記為已修改
+                    let mut touched = false;
+                    let res = (|| -> Result<PdfHeaderFooterResult, MediaError> {
+                        let slots: Vec<(&str, &str)> = [
+                            ("header-left", &args.header_left),
+                            ("header-center", &args.header_center),
+                            ("header-right", &args.header_right),
+                            ("footer-left", &args.footer_left),
+                            ("footer-center", &args.footer_center),
+                            ("footer-right", &args.footer_right),
+                        ]
+                        .into_iter()
+                        .filter_map(|(slot, t)| {
+                            t.as_deref()
+                                .filter(|t| !t.trim().is_empty())
+                                .map(|t| (slot, t))
+                        })
+                        .collect();
+                        if slots.is_empty() {
+                            return Err(MediaError::new(
+                                "invalid_input",
+                                "未指定任何頁首/頁尾文字",
+                            ));
+                        }
+                        let font_size = args.font_size.unwrap_or(10.0);
+                        if font_size <= 0.0 {
+                            return Err(MediaError::new("invalid_input", "字型大小需大於 0"));
+                        }
+                        let (r, g, b) =
+                            parse_hex_color(args.color.as_deref().unwrap_or("#000000"))?;
+                        let fill = PdfColor::new(r, g, b, 255);
+                        let margin_x = args.margin_x_pt.unwrap_or(36.0).max(0.0);
+                        let margin_y = args.margin_y_pt.unwrap_or(24.0).max(0.0);
+                        let start = args.start_number.unwrap_or(1);
+                        let bates_start = args.bates_start.unwrap_or(1);
+                        let bates_digits = args.bates_digits.unwrap_or(6) as usize;
+                        let bates_prefix = args.bates_prefix.as_deref().unwrap_or("");
+                        let bates_suffix = args.bates_suffix.as_deref().unwrap_or("");
+                        let filename = paths
+                            .get(&doc_id)
+                            .and_then(|p| Path::new(p).file_name())
+                            .map(|n| n.to_string_lossy().into_owned())
+                            .unwrap_or_default();
+                        let date = args
+                            .date
+                            .clone()
+                            .unwrap_or_else(|| chrono::Local::now().format("%Y-%m-%d").to_string());
+
+                        let doc = docs.get_mut(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        // 字型需涵蓋範本與實際代入的內容
+                        let uses = |token: &str| slots.iter().any(|(_, t)| t.contains(token));
+                        let mut sample: String = slots.iter().map(|(_, t)| *t).collect();
+                        if uses("{bates}") {
+                            sample += bates_prefix;
+                            sample += bates_suffix;
+                        }
+                        if uses("{filename}") {
+                            sample += &filename;
+                        }
+                        if uses("{date}") {
+                            sample += &date;
+                        }
+                        let font = load_text_font(
+                            doc,
+                            args.font.as_deref(),
+                            args.font_path.as_deref(),
+                            &sample,
+                        )?;
+
+                        let page_count = total_pages(doc);
+                        let mut selected =
+                            parse_page_range(args.range.as_deref().unwrap_or(""), page_count)?;
+                        selected.sort_unstable();
+                        selected.dedup();
+                        let total = (start as u64 + selected.len() as u64)
+                            .saturating_sub(1)
+                            .to_string();
+                        let next_bates = bates_start
+                            .checked_add(selected.len() as u64)
+                            .ok_or_else(|| {
+                                MediaError::new("invalid_input", "Bates 編號超出範圍")
+                            })?;
+                        let page_indices = selected
+                            .iter()
+                            .map(|idx| to_page_index(*idx).map(|i| (*idx, i)))
+                            .collect::<Result<Vec<_>, _>>()?;
+
+                        let map_err = |e: PdfiumError| {
+                            MediaError::new("io_error", format!("加入頁首/頁尾失敗: {e}"))
+                        };
+                        for (ordinal, (idx, idx_u16)) in page_indices.into_iter().enumerate() {
+                            let number = (start as u64 + ordinal as u64).to_string();
+                            let bates_number =
+                                bates_start.checked_add(ordinal as u64).ok_or_else(|| {
+                                    MediaError::new("invalid_input", "Bates 編號超出範圍")
+                                })?;
+                            let bates = format!(
+                                "{}{:0width$}{}",
+                                bates_prefix,
+                                bates_number,
+                                bates_suffix,
+                                width = bates_digits
+                            );
+                            let mut page = doc.pages_mut().get(idx_u16).map_err(|_| {
+                                MediaError::new("not_found", format!("頁索引不存在: {}", idx))
+                            })?;
+                            page.set_content_regeneration_strategy(
+                                PdfPageContentRegenerationStrategy::Manual,
+                            );
+                            let visible = page
+                                .boundaries()
+                                .crop()
+                                .or_else(|_| page.boundaries().media())
+                                .map(|b| pdf_box_from_rect(&b.bounds))
+                                .map_err(|e| {
+                                    MediaError::new("parse_error", format!("讀取頁面框失敗: {e}"))
+                                })?;
+                            let rotation = page.rotation().map(rotation_to_degrees).unwrap_or(0);
+                            // 以顯示方向排版，頁首/頁尾不隨 /Rotate 轉向
+                            let to_user = invert_matrix(page_placement_matrix(
+                                &visible, rotation, 1.0, 0.0, 0.0,
+                            ));
+                            let (w0, h0) =
+                                (visible.right - visible.left, visible.top - visible.bottom);
+                            let (page_w, page_h) = if matches!(rotation, 90 | 270) {
+                                (h0, w0)
+                            } else {
+                                (w0, h0)
+                            };
+
+                            for (slot, template) in &slots {
+                                let text = template
+                                    .replace("{page}", &number)
+                                    .replace("{total}", &total)
+                                    .replace("{bates}", &bates)
+                                    .replace("{filename}", &filename)
+                                    .replace("{date}", &date);
+                                let mut obj = page
+                                    .objects_mut()
+                                    .create_text_object(
+                                        PdfPoints::new(0.0),
+                                        PdfPoints::new(0.0),
+                                        text,
+                                        font,
+                                        PdfPoints::new(font_size),
+                                    )
+                                    .map_err(map_err)?;
+                                touched = true;
+                                obj.set_fill_color(fill).map_err(map_err)?;
+                                let q = obj.bounds().map_err(map_err)?;
+                                let (left, width) =
+                                    (q.left().value, q.right().value - q.left().value);
+                                let (vertical, align) =
+                                    slot.split_once('-').unwrap_or((slot, "center"));
+                                let x = match align {
+                                    "left" => margin_x,
+                                    "right" => page_w - margin_x - width,
+                                    _ => (page_w - width) / 2.0,
+                                } - left;
+                                // 頁首以字級 0.8 倍近似上緣，各頁基線一致
+                                let baseline = if vertical == "header" {
+                                    page_h - margin_y - font_size * 0.8
+                                } else {
+                                    margin_y
+                                };
+                                let m = concat_matrix([1.0, 0.0, 0.0, 1.0, x, baseline], to_user);
+                                obj.transform(m[0], m[1], m[2], m[3], m[4], m[5])
+                                    .map_err(map_err)?;
+                            }
+                            page.regenerate_content().map_err(map_err)?;
+                        }
+                        Ok(PdfHeaderFooterResult {
+                            pages: selected.len(),
+                            next_bates,
+                        })
+                    })();
+                    if touched {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::SetPageBoxes { args, reply }) => {
+                    let doc_id = args.doc_id;
+                    let mut touched = false;
+                    let res = (|| -> Result<usize, MediaError> {
+                        let doc = docs.get_mut(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let page_count = total_pages(doc);
+                        let mut selected =
+                            parse_page_range(args.range.as_deref().unwrap_or(""), page_count)?;
+                        selected.sort_unstable();
+                        selected.dedup();
+                        let crop_to_content = args.crop_to_content.unwrap_or(false);
+                        if crop_to_content && args.crop_box.is_some() {
+                            return Err(MediaError::new(
+                                "invalid_input",
+                                "cropBox 與 cropToContent 不可同時指定",
+                            ));
+                        }
+                        // 先驗證所有框，避免部分頁面已修改後才失敗
+                        let media = args.media_box.as_ref().map(rect_from_pdf_box).transpose()?;
+                        let crop = args.crop_box.as_ref().map(rect_from_pdf_box).transpose()?;
+                        let trim = args.trim_box.as_ref().map(rect_from_pdf_box).transpose()?;
+                        let bleed = args.bleed_box.as_ref().map(rect_from_pdf_box).transpose()?;
+                        let art = args.art_box.as_ref().map(rect_from_pdf_box).transpose()?;
+                        let margin = args.margin_pt.unwrap_or(0.0).max(0.0);
+                        let dpi = args.dpi.unwrap_or(50.0).clamp(10.0, 300.0);
+                        let tolerance = args.tolerance.unwrap_or(16);
+
+                        let mut changed = 0usize;
+                        for idx in selected {
+                            let idx_u16 = to_page_index(idx)?;
+                            let mut page = doc.pages_mut().get(idx_u16).map_err(|_| {
+                                MediaError::new("not_found", format!("頁索引不存在: {}", idx))
+                            })?;
+                            let map_err = |e: PdfiumError| {
+                                MediaError::new("io_error", format!("設定頁面框失敗: {e}"))
+                            };
+                            // 任一頁面框已寫入即算修改，後續失敗仍標記文件
+                            let mut page_changed = false;
+                            for (kind, rect) in [
+                                ("media", media),
+                                ("crop", crop),
+                                ("trim", trim),
+                                ("bleed", bleed),
+                                ("art", art),
+                            ] {
+                                let Some(rect) = rect else { continue };
+                                let boundaries = page.boundaries_mut();
+                                match kind {
+                                    "media" => boundaries.set_media(rect),
+                                    "crop" => boundaries.set_crop(rect),
+                                    "trim" => boundaries.set_trim(rect),
+                                    "bleed" => boundaries.set_bleed(rect),
+                                    _ => boundaries.set_art(rect),
+                                }
+                                .map_err(map_err)?;
+                                touched = true;
+                                page_changed = true;
+                            }
+                            // 空白頁不裁切
+                            let content = match crop_to_content {
+                                true => detect_content_box(&page, dpi, tolerance)?,
+                                false => None,
+                            };
+                            if let Some(content) = content {
+                                let bounds = page
+                                    .boundaries()
+                                    .media()
+                                    .map(|b| pdf_box_from_rect(&b.bounds))
+                                    .unwrap_or_else(|_| pdf_box_from_rect(&page.page_size()));
+                                let padded = PdfBox {
+                                    left: (content.left - margin).max(bounds.left),
+                                    bottom: (content.bottom - margin).max(bounds.bottom),
+                                    right: (content.right + margin).min(bounds.right),
+                                    top: (content.top + margin).min(bounds.top),
+                                };
+                                page.boundaries_mut()
+                                    .set_crop(rect_from_pdf_box(&padded)?)
+                                    .map_err(map_err)?;
+                                touched = true;
+                                page_changed = true;
+                            }
+                            if page_changed {
+                                changed += 1;
+                            }
+                        }
+                        Ok(changed)
+                    })();
+                    if touched {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::NormalizePageSize { args, reply }) => {
+                    let doc_id = args.doc_id;
+                    let mut touched = false;
+                    let res = (|| -> Result<usize, MediaError> {
+                        let (paper_w, paper_h) = match args.paper.as_str() {
+                            "Custom" | "custom" => match (args.width_pt, args.height_pt) {
+                                (Some(w), Some(h)) if w > 0.0 && h > 0.0 => (w, h),
+                                _ => {
+                                    return Err(MediaError::new(
+                                        "invalid_input",
+                                        "Custom 紙張需提供 widthPt 與 heightPt",
+                                    ));
+                                }
+                            },
+                            other => paper_size_pt(other).ok_or_else(|| {
+                                MediaError::new("invalid_input", format!("不支援的紙張: {}", other))
+                            })?,
+                        };
+                        let orientation = args.orientation.as_deref().unwrap_or("auto");
+                        if !matches!(orientation, "auto" | "portrait" | "landscape") {
+                            return Err(MediaError::new(
+                                "invalid_input",
+                                format!("不支援的方向: {}", orientation),
+                            ));
+                        }
+                        let mode = args.mode.as_deref().unwrap_or("fit");
+                        if !matches!(mode, "fit" | "fill" | "none") {
+                            return Err(MediaError::new(
+                                "invalid_input",
+                                format!("不支援的縮放模式: {}", mode),
+                            ));
+                        }
+                        let margin = args.margin_pt.unwrap_or(0.0).max(0.0);
+                        let (short, long) = (paper_w.min(paper_h), paper_w.max(paper_h));
+                        if margin * 2.0 >= short {
+                            return Err(MediaError::new("invalid_input", "邊界過大"));
+                        }
+
+                        let doc = docs.get_mut(&doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", doc_id))
+                        })?;
+                        let page_count = total_pages(doc);
+                        let mut selected =
+                            parse_page_range(args.range.as_deref().unwrap_or(""), page_count)?;
+                        selected.sort_unstable();
+                        selected.dedup();
+                        let get_page = |idx: u32| {
+                            doc.pages().get(to_page_index(idx)?).map_err(|_| {
+                                MediaError::new("not_found", format!("頁索引不存在: {}", idx))
+                            })
+                        };
+                        for idx in &selected {
+                            check_annotations_scalable(doc, &get_page(*idx)?, *idx)?;
+                        }
+
+                        for idx in &selected {
+                            let mut page = get_page(*idx)?;
+                            let visible = page
+                                .boundaries()
+                                .crop()
+                                .or_else(|_| page.boundaries().media())
+                                .map(|b| pdf_box_from_rect(&b.bounds))
+                                .map_err(|e| {
+                                    MediaError::new("parse_error", format!("讀取頁面框失敗: {e}"))
+                                })?;
+                            let rotated = matches!(
+                                page.rotation().map(rotation_to_degrees).unwrap_or(0),
+                                90 | 270
+                            );
+                            // 以顯示方向決定紙張方向，再換回未旋轉的使用者空間
+                            let (src_w, src_h) =
+                                (visible.right - visible.left, visible.top - visible.bottom);
+                            let src_landscape = if rotated {
+                                src_h > src_w
+                            } else {
+                                src_w > src_h
+                            };
+                            let landscape = match orientation {
+                                "portrait" => false,
+                                "landscape" => true,
+                                _ => src_landscape,
+                            };
+                            let (disp_w, disp_h) = if landscape {
+                                (long, short)
+                            } else {
+                                (short, long)
+                            };
+                            let (tw, th) = if rotated {
+                                (disp_h, disp_w)
+                            } else {
+                                (disp_w, disp_h)
+                            };
+
+                            let (avail_w, avail_h) = (tw - margin * 2.0, th - margin * 2.0);
+                            let scale = match mode {
+                                "fill" => (avail_w / src_w).max(avail_h / src_h),
+                                "none" => 1.0,
+                                _ => (avail_w / src_w).min(avail_h / src_h),
+                            };
+                            let e = margin + (avail_w - src_w * scale) / 2.0 - visible.left * scale;
+                            let f =
+                                margin + (avail_h - src_h * scale) / 2.0 - visible.bottom * scale;
+                            // 裁剪路徑位於轉換後座標；fill 模式不讓內容進入邊界
+                            let clip = if mode == "fill" {
+                                PdfRect::new_from_values(margin, margin, th - margin, tw - margin)
+                            } else {
+                                PdfRect::new_from_values(0.0, 0.0, th, tw)
+                            };
+                            page.transform_with_clip(scale, 0.0, 0.0, scale, e, f, clip)
+                                .map_err(|e| {
+                                    MediaError::new("io_error", format!("縮放頁面內容失敗: {e}"))
+                                })?;
+                            touched = true;
+                            transform_page_annotations(doc, &page, scale, e, f)?;
+
+                            // 既有的 trim/bleed/art 框一併改為新頁面大小
+                            let new_rect = PdfRect::new_from_values(0.0, 0.0, th, tw);
+                            let map_err = |e: PdfiumError| {
+                                MediaError::new("io_error", format!("設定頁面框失敗: {e}"))
+                            };
+                            let had_trim = page.boundaries().trim().is_ok();
+                            let had_bleed = page.boundaries().bleed().is_ok();
+                            let had_art = page.boundaries().art().is_ok();
+                            let boundaries = page.boundaries_mut();
+                            boundaries.set_media(new_rect).map_err(map_err)?;
+                            boundaries.set_crop(new_rect).map_err(map_err)?;
+                            if had_trim {
+                                boundaries.set_trim(new_rect).map_err(map_err)?;
+                            }
+                            if had_bleed {
+                                boundaries.set_bleed(new_rect).map_err(map_err)?;
+                            }
+                            if had_art {
+                                boundaries.set_art(new_rect).map_err(map_err)?;
+                            }
+                        }
+                        Ok(selected.len())
+                    })();
+                    if touched {
+                        mark_modified(&app, &mut states, &paths, doc_id);
+                    }
+                    let _ = reply.send(res);
+                }
+                Ok(PdfRequest::Impose { args, reply }) => {
+                    let res = (|| -> Result<PdfOpenResult, MediaError> {
+                        let (cols, rows, booklet) = match args.layout.as_str() {
+                            "2up" => (2u32, 1u32, false),
+                            "4up" => (2, 2, false),
+                            "grid" => match (args.cols, args.rows) {
+                                (Some(c), Some(r))
+                                    if c > 0
+                                        && r > 0
+                                        && c.checked_mul(r).is_some_and(|n| n <= 64) =>
+                                {
+                                    (c, r, false)
+                                }
+                                _ => {
+                                    return Err(MediaError::new(
+                                        "invalid_input",
+                                        "grid 版面需提供 rows 與 cols（乘積不超過 64）",
+                                    ));
+                                }
+                            },
+                            "booklet" => (2, 1, true),
+                            other => {
+                                return Err(MediaError::new(
+                                    "invalid_input",
+                                    format!("不支援的版面: {}", other),
+                                ));
+                            }
+                        };
+                        let (paper_w, paper_h) = match args.paper.as_deref().unwrap_or("A4") {
+                            "Custom" | "custom" => match (args.width_pt, args.height_pt) {
+                                (Some(w), Some(h)) if w > 0.0 && h > 0.0 => (w, h),
+                                _ => {
+                                    return Err(MediaError::new(
+                                        "invalid_input",
+                                        "Custom 紙張需提供 widthPt 與 heightPt",
+                                    ));
+                                }
+                            },
+                            other => paper_size_pt(other).ok_or_else(|| {
+                                MediaError::new("invalid_input", format!("不支援的紙張: {}", other))
+                            })?,
+                        };
+                        // 預設：左右並排的版面用橫式，其餘直式
+                        let landscape = match args.orientation.as_deref() {
+                            Some("landscape") => true,
+                            Some("portrait") => false,
+                            _ => cols > rows,
+                        };
+                        let (short, long) = (paper_w.min(paper_h), paper_w.max(paper_h));
+                        let (sheet_w, sheet_h) = if landscape {
+                            (long, short)
+                        } else {
+                            (short, long)
+                        };
+                        let margin = args.margin_pt.unwrap_or(18.0).max(0.0);
+                        let gutter = args.gutter_pt.unwrap_or(12.0).max(0.0);
+                        let cell_w =
+                            (sheet_w - margin * 2.0 - gutter * (cols - 1) as f32) / cols as f32;
+                        let cell_h =
+                            (sheet_h - margin * 2.0 - gutter * (rows - 1) as f32) / rows as f32;
+                        if cell_w <= 0.0 || cell_h <= 0.0 {
+                            return Err(MediaError::new("invalid_input", "邊界或間距過大"));
+                        }
+                        let border_width = if args.border.unwrap_or(false) {
+                            Some(args.border_width_pt.unwrap_or(0.5).max(0.1))
+                        } else {
+                            None
+                        };
+
+                        let src = docs.get(&args.doc_id).ok_or_else(|| {
+                            MediaError::new("not_found", format!("未知的 docId: {}", args.doc_id))
+                        })?;
+                        let selected = parse_page_range(
+                            args.range.as_deref().unwrap_or(""),
+                            total_pages(src),
+                        )?;
+                        let slots: Vec<Option<u32>> = if booklet {
+                            booklet_order(&selected)
+                        } else {
+                            selected.into_iter().map(Some).collect()
+                        };
+                        let per_sheet = (cols * rows) as usize;
+
+                        let mut out = pdfium.create_new_pdf().map_err(|e| {
+                            MediaError::new("io_error", format!("建立新 PDF 失敗: {e}"))
+                        })?;
+                        for sheet_slots in slots.chunks(per_sheet) {
+                            // 先建立所有 XObject，再建立頁面放置，避免同時借用輸出文件
+                            let mut placed = Vec::new();
+                            for (slot, idx) in sheet_slots.iter().enumerate() {
+                                let Some(idx) = idx else { continue };
+                                let idx_u16 = to_page_index(*idx)?;
+                                let page = src.pages().get(idx_u16).map_err(|_| {
+                                    MediaError::new("not_found", format!("頁索引不存在: {}", idx))
+                                })?;
+                                let visible = page
+                                    .boundaries()
+                                    .crop()
+                                    .or_else(|_| page.boundaries().media())
+                                    .map(|b| pdf_box_from_rect(&b.bounds))
+                                    .map_err(|e| {
+                                        MediaError::new(
+                                            "parse_error",
+                                            format!("讀取頁面框失敗: {e}"),
+                                        )
+                                    })?;
+                                let rotation =
+                                    page.rotation().map(rotation_to_degrees).unwrap_or(0);
+                                let xobj = page
+                                    .objects()
+                                    .copy_into_x_object_form_object(&mut out)
+                                    .map_err(|e| {
+                                        MediaError::new(
+                                            "io_error",
+                                            format!("建立頁面 XObject 失敗: {e}"),
+                                        )
+                                    })?;
+                                placed.push((slot as u32, visible, rotation, xobj));
+                            }
+
+                            let size = PdfPagePaperSize::Custom(
+                                PdfPoints::new(sheet_w),
+                                PdfPoints::new(sheet_h),
+                            );
+                            let mut sheet =
+                                out.pages_mut().create_page_at_end(size).map_err(|e| {
+                                    MediaError::new("io_error", format!("建立頁面失敗: {e}"))
+                                })?;
+                            for (slot, visible, rotation, xobj) in placed {
+                                let (col, row) = (slot % cols, slot / cols);
+                                let (w0, h0) =
+                                    (visible.right - visible.left, visible.top - visible.bottom);
+                                let (disp_w, disp_h) = if matches!(rotation, 90 | 270) {
+                                    (h0, w0)
+                                } else {
+                                    (w0, h0)
+                                };
+                                let scale = (cell_w / disp_w).min(cell_h / disp_h);
+                                let (pw, ph) = (disp_w * scale, disp_h * scale);
+                                // 儲存格由左上角開始逐列排列，頁面置中於儲存格
+                                let cell_x = margin + col as f32 * (cell_w + gutter);
+                                let cell_top = sheet_h - margin - row as f32 * (cell_h + gutter);
+                                let x = cell_x + (cell_w - pw) / 2.0;
+                                let y = cell_top - cell_h + (cell_h - ph) / 2.0;
+                                let m = page_placement_matrix(&visible, rotation, scale, x, y);
+                                let mut obj =
+                                    sheet.objects_mut().add_object(xobj).map_err(|e| {
+                                        MediaError::new("io_error", format!("放置頁面失敗: {e}"))
+                                    })?;
+                                obj.transform(m[0], m[1], m[2], m[3], m[4], m[5])
+                                    .map_err(|e| {
+                                        MediaError::new("io_error", format!("放置頁面失敗: {e}"))
+                                    })?;
+                                if let Some(bw) = border_width {
+                                    sheet
+                                        .objects_mut()
+                                        .create_path_object_rect(
+                                            PdfRect::new_from_values(y, x, y + ph, x + pw),
+                                            Some(PdfColor::BLACK),
+                                            Some(PdfPoints::new(bw)),
+                                            None,
+                                        )
+                                        .map_err(|e| {
+                                            MediaError::new(
+                                                "io_error",
+                                                format!("繪製邊框失敗: {e}"),
+                                            )
+                                        })?;
+                                }
+                            }
+                        }
+
+                        let pages = total_pages(&out) as usize;
+                        let id = NEXT_DOC_ID.fetch_add(1, Ordering::SeqCst);
+                        docs.insert(id, out);
+                        // 新文件尚未存檔，視為有變更
+                        states.insert(
+                            id,
+                            DocState {
+                                revision: 1,
+                                ..DocState::default()
+                            },
+                        );
+                        emit_doc_state(&app, &doc_state_snapshot(id, &states, &paths));
+                        Ok(PdfOpenResult {
+                            doc_id: id,
+                            pages,
+                            security: None,
+                        })
+                    })();
+                    let _ = reply.send(res);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn analyze_media(path: String) -> Result<MediaDescriptor, MediaError> {
+    tokio::task::spawn_blocking(move || analyze_media_blocking(path))
+        .await
+        .map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))?
+}
+
+// 批次分析的單筆結果：成功時 descriptor 有值，失敗時 error 有值
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaBatchItem {
+    pub path: String,
+    pub descriptor: Option<MediaDescriptor>,
+    pub error: Option<MediaError>,
+}
+
+impl MediaBatchItem {
+    fn new(path: &str, res: Result<MediaDescriptor, MediaError>) -> Self {
+        let (descriptor, error) = match res {
+            Ok(d) => (Some(d), None),
+            Err(e) => (None, Some(e)),
+        };
+        MediaBatchItem {
+            path: path.to_string(),
+            descriptor,
+            error,
+        }
+    }
+}
+
+// 批次分析（檔案清單一次加入多個檔案時使用），結果順序與 paths 相同；
+// 影像檔頭於多執行緒平行解析，PDF 則由 worker 依序讀取
+#[tauri::command]
+pub async fn analyze_media_batch(paths: Vec<String>) -> Result<Vec<MediaBatchItem>, MediaError> {
+    tokio::task::spawn_blocking(move || {
+        let threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4)
+            .min(8);
+        let chunk = paths.len().div_ceil(threads).max(1);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = paths
+                .chunks(chunk)
+                .map(|part| {
+                    let handle = scope.spawn(move || {
+                        part.iter()
+                            .map(|p| MediaBatchItem::new(p, analyze_media_blocking(p.clone())))
+                            .collect::<Vec<_>>()
+                    });
+                    (part, handle)
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|(part, h)| {
+                    // 執行緒異常結束時，該段的每個檔案各回報一筆錯誤，維持與 paths 對齊
+                    h.join().unwrap_or_else(|_| {
+                        part.iter()
+                            .map(|p| {
+                                MediaBatchItem::new(
+                                    p,
+                                    Err(MediaError::new("async_error", "分析執行緒異常結束")),
+                                )
+                            })
+                            .collect()
+                    })
+                })
+                .collect()
+        })
+    })
+    .await
+    .map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))
+}
+
+fn analyze_media_blocking(path: String) -> Result<MediaDescriptor, MediaError> {
+    let p = Path::new(&path);
+    if !p.exists() {
+        return Err(MediaError::new(
+            "not_found",
+            format!("檔案不存在: {}", path),
+        ));
+    }
+    let kind = infer_media_type(p);
+    let name = file_name(p);
+    let size = try_stat_size(p);
+
+    let mut desc = MediaDescriptor {
+        path: path.clone(),
+        kind,
+        name,
+        size,
+        pages: None,
+        width: None,
+        height: None,
+        orientation: None,
+        color_type: None,
+        encrypted: None,
+        title: None,
+        author: None,
+        pdf_version: None,
+    };
+    match desc.kind {
+        MediaType::Pdf => {
+            desc.pdf_version = read_pdf_header_version(p);
+            // 無法讀取時（如檔案損毀）僅回傳基本資訊，開啟時再回報錯誤
+            match inspect_pdf(&path) {
+                Ok(info) => {
+                    desc.pages = info.pages;
+                    desc.width = info.width_pt.map(|w| w.round() as u32);
+                    desc.height = info.height_pt.map(|h| h.round() as u32);
+                    desc.encrypted = Some(info.encrypted);
+                    desc.title = info.title;
+                    desc.author = info.author;
+                }
+                Err(err) => warn!("Failed to inspect PDF {}: {}", desc.path, err.message),
+            }
+        }
+        MediaType::Image => {
+            // 僅解析檔頭，不解碼像素
+            let decoder = image::ImageReader::open(p)
+                .and_then(|r| r.with_guessed_format())
+                .map_err(|e| MediaError::new("io_error", format!("讀取影像失敗: {e}")))
+                .and_then(|r| {
+                    r.into_decoder().map_err(|e| {
+                        MediaError::new("decode_error", format!("解析影像檔頭失敗: {e}"))
+                    })
+                });
+            match decoder {
+                Ok(mut decoder) => {
+                    use image::ImageDecoder;
+                    let (w, h) = decoder.dimensions();
+                    desc.width = Some(w);
+                    desc.height = Some(h);
+                    desc.color_type = Some(format!("{:?}", decoder.color_type()).to_lowercase());
+                    desc.orientation = decoder.orientation().ok().map(|o| o.to_exif());
+                }
+                Err(err) => warn!("Failed to inspect image {}: {}", desc.path, err.message),
+            }
+        }
+        MediaType::Unknown => {}
+    }
+    Ok(desc)
+}
+
+// PDF 檔案清單資訊：不經 worker，以 lopdf 讀取 xref、頁面樹、/Encrypt 與 /Info，可與其他檔案平行處理
+struct PdfInspectInfo {
+    pages: Option<usize>,
+    width_pt: Option<f32>,
+    height_pt: Option<f32>,
+    encrypted: bool,
+    title: Option<String>,
+    author: Option<String>,
+}
+
+// 解析時即捨棄內容與影像串流，只保留字典；物件串流保留到展開內含物件為止
+fn inspect_filter(
+    id: lopdf::ObjectId,
+    obj: &mut lopdf::Object,
+) -> Option<(lopdf::ObjectId, lopdf::Object)> {
+    match obj {
+        lopdf::Object::Stream(stream) if stream.dict.type_is(b"ObjStm") => {
+            Some((id, lopdf::Object::Null))
+        }
+        lopdf::Object::Stream(_) => None,
+        _ => Some((id, obj.clone())),
+    }
+}
+
+// 頁面顯示尺寸（pt）：CropBox 優先、沿 /Parent 繼承，/Rotate 為 90、270 時寬高互換
+fn lopdf_page_size(lo: &lopdf::Document, page_id: lopdf::ObjectId) -> Option<(f32, f32)> {
+    let inherited = |key: &[u8]| {
+        let mut node = lo.get_dictionary(page_id).ok();
+        for _ in 0..32 {
+            let n = node?;
+            if let Some(v) = n.get(key).ok().and_then(|v| resolve_object(lo, v)) {
+                return Some(v);
+            }
+            node = n
+                .get(b"Parent")
+                .ok()
+                .and_then(|p| p.as_reference().ok())
+                .and_then(|id| lo.get_dictionary(id).ok());
+        }
+        None
+    };
+    let size = |key: &[u8]| {
+        let values: Vec<f32> = inherited(key)?
+            .as_array()
+            .ok()?
+            .iter()
+            .filter_map(|v| resolve_object(lo, v)?.as_float().ok())
+            .collect();
+        match values[..] {
+            [l, b, r, t] => Some(((r - l).abs(), (t - b).abs())),
+            _ => None,
+        }
+    };
+    let (w, h) = size(b"CropBox").or_else(|| size(b"MediaBox"))?;
+    let rotate = inherited(b"Rotate")
+        .and_then(|r| r.as_i64().ok())
+        .unwrap_or(0);
+    Some(if rotate.rem_euclid(180) == 90 {
+        (h, w)
+    } else {
+        (w, h)
+    })
+}
+
+fn inspect_pdf(path: &str) -> Result<PdfInspectInfo, MediaError> {
+    let mut lo = lopdf::Document::load_filtered(path, inspect_filter)
+        .map_err(|e| MediaError::new("parse_error", format!("開啟 PDF 失敗: {e}")))?;
+    // 空使用者密碼可開啟者照常讀取 /Info；需要密碼時只回報加密與未加密的頁面樹
+    let encrypted = lo.is_encrypted();
+    let readable = !encrypted || decrypt_document(&mut lo, "").is_ok();
+    let pages = lo.get_pages();
+    let first = pages
+        .values()
+        .next()
+        .and_then(|id| lopdf_page_size(&lo, *id));
+    let info = match readable {
+        true => lo
+            .trailer
+            .get(b"Info")
+            .ok()
+            .and_then(|i| resolve_dict(&lo, i)),
+        false => None,
+    };
+    let tag = |key: &[u8]| {
+        let value = resolve_object(&lo, info.as_ref()?.get(key).ok()?)?;
+        Some(decode_pdf_text(value.as_str().ok()?)).filter(|v| !v.is_empty())
+    };
+    Ok(PdfInspectInfo {
+        pages: (!pages.is_empty()).then_some(pages.len()),
+        width_pt: first.map(|(w, _)| w),
+        height_pt: first.map(|(_, h)| h),
+        encrypted,
+        title: tag(b"Title"),
+        author: tag(b"Author"),
+    })
+}
+
+// 移除 pdf_info：請改用 pdf_open 的回傳或 pdf_page_size。
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfOpenResult {
+    pub doc_id: u64,
+    pub pages: usize,
+    // 僅 pdf_open 回傳
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub security: Option<PdfSecurityInfo>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfPermissionFlags {
+    pub print: bool,
+    pub print_high_quality: bool,
+    pub copy: bool,
+    pub modify: bool,
+    pub annotate: bool,
+    pub fill_forms: bool,
+    pub assemble: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfSecurityInfo {
+    pub encrypted: bool,
+    pub method: Option<String>, // 'RC4-40' | 'RC4-128' | 'AES-128' | 'AES-256'
+    pub revision: Option<u32>,
+    pub permissions: PdfPermissionFlags,
+}
+
+// 文件修改狀態（亦作為 pdf-doc-state 事件的 payload）
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfDocState {
+    pub doc_id: u64,
+    pub path: Option<String>,
+    pub dirty: bool,
+    pub revision: u64,
+}
+
+// 頁面框（pt，PDF 使用者空間，左下為原點）
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfBox {
+    pub left: f32,
+    pub bottom: f32,
+    pub right: f32,
+    pub top: f32,
+}
+
+// widthPt/heightPt 為顯示尺寸（已套用裁切框與旋轉）
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfPageSize {
+    pub width_pt: f32,
+    pub height_pt: f32,
+    pub rotation: u16,
+    pub media_box: PdfBox,
+    pub crop_box: Option<PdfBox>,
+    pub trim_box: Option<PdfBox>,
+    pub bleed_box: Option<PdfBox>,
+    pub art_box: Option<PdfBox>,
+}
+
+#[tauri::command]
+// 加密文件未提供密碼時回傳 code=password_required，密碼錯誤時回傳 code=wrong_password
+pub fn pdf_open(path: String, password: Option<String>) -> Result<PdfOpenResult, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Open {
+            path,
+            password,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[tauri::command]
+pub fn pdf_security_info(doc_id: u64) -> Result<PdfSecurityInfo, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Security { doc_id, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+// 有未儲存變更時回傳 code=dirty；force=true 則直接捨棄變更
+#[tauri::command]
+pub fn pdf_close(doc_id: u64, force: Option<bool>) -> Result<(), MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Close {
+            doc_id,
+            force: force.unwrap_or(false),
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+// 未指定 docId 時回傳所有已開啟文件的狀態（供離開程式前檢查）
+#[tauri::command]
+pub fn pdf_doc_state(doc_id: Option<u64>) -> Result<Vec<PdfDocState>, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::DocState { doc_id, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+// 程式正常結束時呼叫；等待 worker 清除復原檔（逾時則放棄）
+pub fn shutdown_pdf_worker() {
+    let (rtx, rrx) = mpsc::channel();
+    let sent = WORKER_TX
+        .lock()
+        .unwrap()
+        .take()
+        .map(|tx| tx.send(PdfRequest::Shutdown { reply: rtx }).is_ok())
+        .unwrap_or(false);
+    if sent {
+        let _ = rrx.recv_timeout(Duration::from_secs(5));
+    }
+}
+
+// 列出先前執行（崩潰或關閉時仍有未儲存變更）留下的自動儲存檔，不含本次執行中的文件
+#[tauri::command]
+pub fn recovery_list(app: tauri::AppHandle) -> Result<Vec<RecoveryEntry>, MediaError> {
+    let Some(dir) = recovery_dir(&app) else {
+        return Ok(Vec::new());
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let own_prefix = format!("{}-", SESSION_ID.as_str());
+    let mut out: Vec<RecoveryEntry> = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(id) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_string())
+        else {
+            continue;
+        };
+        if id.starts_with(&own_prefix) {
+            continue;
+        }
+        let meta: RecoveryMeta = match fs::read(&path)
+            .ok()
+            .and_then(|b| serde_json::from_slice(&b).ok())
+        {
+            Some(m) => m,
+            None => continue,
+        };
+        let pdf_path = dir.join(format!("{}.pdf", id));
+        if !pdf_path.exists() {
+            continue;
+        }
+        out.push(RecoveryEntry {
+            id,
+            original_path: meta.original_path,
+            saved_at: meta.saved_at,
+            pages: meta.pages,
+            size: try_stat_size(&pdf_path),
+        });
+    }
+    out.sort_by_key(|e| std::cmp::Reverse(e.saved_at));
+    Ok(out)
+}
+
+// 以復原檔開啟新文件（路徑指回原檔、標記為有變更），成功後移除復原檔
+#[tauri::command]
+pub fn recovery_restore(id: String, password: Option<String>) -> Result<PdfOpenResult, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::RecoveryRestore {
+            id,
+            password,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[tauri::command]
+pub fn recovery_discard(app: tauri::AppHandle, id: String) -> Result<(), MediaError> {
+    validate_recovery_id(&id)?;
+    if let Some(dir) = recovery_dir(&app) {
+        remove_recovery_files(&dir, &id);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn pdf_page_size(doc_id: u64, page_index: u32) -> Result<PdfPageSize, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Size {
+            doc_id,
+            page_index,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfRenderArgs {
+    pub doc_id: u64,
+    #[serde(alias = "page_index")]
+    pub page_index: u32,
+    pub scale: Option<f32>,
+    pub dpi: Option<f32>,
+    pub format: Option<String>,
+    pub target_width: Option<u32>,
+    pub quality: Option<u8>,
+    #[serde(alias = "gen")]
+    pub r#gen: Option<u64>,
+}
+
+#[tauri::command]
+pub fn pdf_render_page(args: PdfRenderArgs) -> Result<PageRender, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Render { args, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+// ⚡ 新增：異步版本（真正並行渲染）
+#[tauri::command]
+pub async fn pdf_render_page_async(args: PdfRenderArgs) -> Result<PageRender, MediaError> {
+    // 使用 Tokio 阻塞執行緒池，避免阻塞主執行緒
+    tokio::task::spawn_blocking(move || -> Result<PageRender, MediaError> {
+        // 複用現有的同步邏輯
+        let (rtx, rrx) = mpsc::channel();
+        WORKER_TX
+            .lock()
+            .unwrap()
+            .as_ref()
+            .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+            .send(PdfRequest::Render { args, reply: rtx })
+            .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+
+        rrx.recv()
+            .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))? // 加上 ? 展開
+    })
+    .await
+    .map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))?
+}
+
+#[tauri::command]
+pub fn pdf_render_cancel(doc_id: u64, page_index: u32, min_gen: u64) -> Result<(), MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Cancel {
+            doc_id,
+            page_index,
+            min_gen,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+fn render_page_for_document(
+    document: &pdfium_render::prelude::PdfDocument,
+    args: &PdfRenderArgs,
+) -> Result<PageRender, MediaError> {
+    use pdfium_render::prelude::*;
+
+    // 目標寬度（px）：指定寬度、DPI 或 1200px × scale
+    let width_for = |w_pt: f32| -> i32 {
+        let width_px = if let Some(w) = args.target_width {
+            w.max(1)
+        } else if let Some(dpi_val) = args.dpi {
+            ((w_pt * dpi_val / 72.0).ceil() as u32).max(1)
+        } else {
+            (1200.0_f32 * args.scale.unwrap_or(1.0).max(0.1)).round() as u32
+        };
+        i32::try_from(width_px).unwrap_or(i32::MAX)
+    };
+
+    // PdfPage 只能定址前 65,535 頁，且頁數超過時 pages().get 會以截斷後的頁數檢查索引；
+    // 此時改以原生 API 算繪（含註解，但無法繪製表單填寫值，表單控制代碼未公開）
+    let img = if total_pages(document) > u16::MAX as u32 {
+        render_raw_page(document, args.page_index, &width_for)?
+    } else {
+        let page = document
+            .pages()
+            .get(to_page_index(args.page_index)?)
+            .map_err(|_| {
+                MediaError::new("not_found", format!("頁索引不存在: {}", args.page_index))
+            })?;
+        // 一併繪製表單欄位與填寫值（缺少外觀串流的欄位由 Pdfium 產生）；不可與矩陣轉換或裁切同時使用
+        let cfg = PdfRenderConfig::new()
+            .render_annotations(true)
+            .render_form_data(true)
+            .set_target_width(width_for(page.width().value));
+        page.render_with_config(&cfg)
+            .map_err(|e| MediaError::new("parse_error", format!("渲染失敗: {e}")))?
+            .as_image()
+    };
+    let (w, h) = (img.width(), img.height());
+
+    let fmt = args
+        .format
+        .as_ref()
+        .cloned()
+        .unwrap_or_else(|| "png".to_string())
+        .to_lowercase();
+    let out_fmt = if fmt == "raw" {
+        "raw" // ⚡ 新增：直接傳 raw RGBA bitmap
+    } else if fmt == "webp" {
+        "webp"
+    } else if fmt == "jpeg" || fmt == "jpg" {
+        "jpeg"
+    } else {
+        "png"
+    };
+
+    let mut buf: Vec<u8> = Vec::new();
+    if out_fmt == "raw" {
+        // ⚡ 直接傳 RGBA raw bytes（無需編碼）
+        let rgba = img.to_rgba8();
+        buf = rgba.into_raw(); // 零開銷：直接取得底層 Vec<u8>
+    } else if out_fmt == "webp" {
+        // 使用 webp crate 支援有損編碼
+        let rgba = img.to_rgba8();
+        let quality = args.quality.unwrap_or(85).clamp(1, 100) as f32;
+        let encoder = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height());
+        let encoded = encoder.encode(quality);
+        buf = encoded.to_vec();
+    } else if out_fmt == "png" {
+        use image::ColorType;
+        use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+        let rgba = img.to_rgba8();
+        let comp = if args.quality.unwrap_or(100) <= 50 {
+            CompressionType::Fast
+        } else {
+            CompressionType::Default
+        };
+        let enc = PngEncoder::new_with_quality(Cursor::new(&mut buf), comp, FilterType::NoFilter);
+        enc.write_image(&rgba, rgba.width(), rgba.height(), ColorType::Rgba8.into())
+            .map_err(|e| MediaError::new("io_error", format!("編碼 PNG 失敗: {e}")))?;
+    } else if out_fmt == "jpeg" {
+        use image::ColorType;
+        use image::codecs::jpeg::JpegEncoder;
+        // JPEG 不支援帶 alpha 的 RGBA；需轉為 RGB 並丟棄 alpha
+        let rgb = img.to_rgb8();
+        let enc = JpegEncoder::new_with_quality(Cursor::new(&mut buf), args.quality.unwrap_or(82));
+        enc.write_image(&rgb, rgb.width(), rgb.height(), ColorType::Rgb8.into())
+            .map_err(|e| MediaError::new("io_error", format!("編碼 JPEG 失敗: {e}")))?;
+    } else {
+        use image::ColorType;
+        use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+        let rgba = img.to_rgba8();
+        let enc = PngEncoder::new_with_quality(
+            Cursor::new(&mut buf),
+            CompressionType::Default,
+            FilterType::NoFilter,
+        );
+        enc.write_image(&rgba, rgba.width(), rgba.height(), ColorType::Rgba8.into())
+            .map_err(|e| MediaError::new("io_error", format!("編碼 PNG 失敗: {e}")))?;
+    }
+
+    Ok(PageRender {
+        page_index: args.page_index,
+        width_px: w as u32,
+        height_px: h as u32,
+        scale: args.scale,
+        dpi: args.dpi,
+        format: out_fmt.to_string(),
+        image_bytes: buf,
+    })
+}
+
+// 以原生 API 算繪頁面（白底、含註解）為 RGBA 影像
+fn render_raw_page(
+    document: &pdfium_render::prelude::PdfDocument,
+    page_index: u32,
+    width_for: &dyn Fn(f32) -> i32,
+) -> Result<image::DynamicImage, MediaError> {
+    const FPDF_ANNOT: i32 = 0x01;
+    const FPDF_REVERSE_BYTE_ORDER: i32 = 0x10;
+    let page = load_raw_page(document, page_index)?;
+    let (bindings, handle) = (page.bindings, page.handle);
+    let (w_pt, h_pt) = (
+        bindings.FPDF_GetPageWidthF(handle),
+        bindings.FPDF_GetPageHeightF(handle),
+    );
+    let w = width_for(w_pt);
+    let h = ((w as f32 * h_pt / w_pt.max(0.01)).round() as i32).max(1);
+    let bitmap = bindings.FPDFBitmap_Create(w, h, 1);
+    if bitmap.is_null() {
+        return Err(MediaError::new("parse_error", "渲染失敗: 無法建立點陣圖"));
+    }
+    bindings.FPDFBitmap_FillRect(bitmap, 0, 0, w, h, 0xFFFF_FFFF);
+    // 反轉位元組順序：輸出為 RGBA 而非 BGRA
+    bindings.FPDF_RenderPageBitmap(
+        bitmap,
+        handle,
+        0,
+        0,
+        w,
+        h,
+        0,
+        FPDF_ANNOT | FPDF_REVERSE_BYTE_ORDER,
+    );
+    let stride = bindings.FPDFBitmap_GetStride(bitmap) as usize;
+    let row_len = w as usize * 4;
+    let mut rgba = Vec::with_capacity(row_len * h as usize);
+    for row in bindings
+        .FPDFBitmap_GetBuffer_as_slice(bitmap)
+        .chunks(stride)
+        .take(h as usize)
+    {
+        rgba.extend_from_slice(&row[..row_len]);
+    }
+    bindings.FPDFBitmap_Destroy(bitmap);
+    image::RgbaImage::from_raw(w as u32, h as u32, rgba)
+        .map(image::DynamicImage::ImageRgba8)
+        .ok_or_else(|| MediaError::new("parse_error", "渲染失敗: 點陣圖大小不符"))
+}
+
+// 通用回傳：僅回報頁數
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfPagesResult {
+    pub pages: usize,
+}
+
+// 儲存回傳：路徑、頁數與備份檔路徑
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfSaveResult {
+    pub path: String,
+    pub pages: usize,
+    pub backup_path: Option<String>,
+    // 檔案已寫入，但重新載入失敗（開啟中的文件沿用儲存前的內容）
+    pub reload_error: Option<String>,
+}
+
+// 權限（預設全部允許）
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfPermissionArgs {
+    pub print: Option<bool>,
+    pub copy: Option<bool>,
+    pub modify: Option<bool>,
+    pub annotate: Option<bool>,
+    pub fill_forms: Option<bool>,
+}
+
+// 儲存時套用 AES-256 加密；未提供擁有者密碼時以隨機值代替
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfEncryptArgs {
+    pub user_password: Option<String>,
+    pub owner_password: Option<String>,
+    pub permissions: Option<PdfPermissionArgs>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfExportImageResult {
+    pub path: String,
+    pub width_px: u32,
+    pub height_px: u32,
+    pub format: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfExportPdfResult {
+    pub path: String,
+}
+
+#[tauri::command]
+pub fn pdf_insert_blank(
+    doc_id: u64,
+    index: u32,
+    width_pt: f32,
+    height_pt: f32,
+) -> Result<PdfPagesResult, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::InsertBlank {
+            doc_id,
+            index,
+            width_pt,
+            height_pt,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    let pages = rrx
+        .recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))??;
+    Ok(PdfPagesResult { pages })
+}
+
+#[tauri::command]
+pub fn pdf_delete_pages(doc_id: u64, indices: Vec<u32>) -> Result<PdfPagesResult, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::DeletePages {
+            doc_id,
+            indices,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    let pages = rrx
+        .recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))??;
+    Ok(PdfPagesResult { pages })
+}
+
+#[tauri::command]
+pub fn pdf_rotate_page(doc_id: u64, index: u32, rotate_deg: u16) -> Result<(), MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::RotatePage {
+            doc_id,
+            index,
+            rotate_deg,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))??;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfRotatePagesArgs {
+    pub doc_id: u64,
+    pub range: Option<String>, // 1-based 頁碼範圍（含 odd/even），預設全部
+    pub indices: Option<Vec<u32>>, // 0-based 索引清單，優先於 range
+    pub angle: i16,            // 90 的倍數
+    pub relative: Option<bool>, // true：相對目前角度；預設設為絕對角度
+}
+
+// 批次旋轉，回傳所有頁面旋轉後的角度
+#[tauri::command]
+pub fn pdf_rotate_pages(args: PdfRotatePagesArgs) -> Result<Vec<u16>, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::RotatePages { args, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+// 書籤節點；pageIndex 為 0-based，left/top 為目的地位置（pt），無目標頁者可為外部連結 uri
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfOutlineItem {
+    pub title: String,
+    pub page_index: Option<u32>,
+    pub left: Option<f32>,
+    pub top: Option<f32>,
+    pub zoom: Option<f32>,
+    pub uri: Option<String>,
+    #[serde(default)]
+    pub open: bool,
+    #[serde(default)]
+    pub children: Vec<PdfOutlineItem>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfOutlineEntry {
+    pub title: String,
+    pub page_index: u32,
+}
+
+fn send_outline_request(
+    doc_id: u64,
+    edit: Option<OutlineEdit>,
+) -> Result<Vec<PdfOutlineItem>, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Outline {
+            doc_id,
+            edit,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
 
-                        // 讀取圖片以取得尺寸
-                        let bytes = fs::read(p).map_err(|e| {
-                            MediaError::new("io_error", format!("讀取圖片失敗: {e}"))
-                        })?;
-                        let dyn_img = image::load_from_memory(&bytes).map_err(|e| {
-                            MediaError::new("decode_error", format!("解碼圖片失敗: {e}"))
-                        })?;
-                        let (w_px, h_px) = GenericImageView::dimensions(&dyn_img);
+// 以下書籤命令皆回傳編輯後的完整書籤樹
+#[tauri::command]
+pub fn pdf_outline_get(doc_id: u64) -> Result<Vec<PdfOutlineItem>, MediaError> {
+    send_outline_request(doc_id, None)
+}
 
-                        // 經驗法則：以 72 DPI 對應 1 px = 1 pt，避免不必要縮放
-                        let width_pt = w_px as f32;
-                        let height_pt = h_px as f32;
+#[tauri::command]
+pub fn pdf_outline_set(
+    doc_id: u64,
+    items: Vec<PdfOutlineItem>,
+) -> Result<Vec<PdfOutlineItem>, MediaError> {
+    send_outline_request(doc_id, Some(OutlineEdit::Set(items)))
+}
 
-                        let mut doc = pdfium.create_new_pdf().map_err(|e| {
-                            MediaError::new("io_error", format!("建立 PDF 失敗: {e}"))
-                        })?;
+#[tauri::command]
+pub fn pdf_outline_add(
+    doc_id: u64,
+    parent_path: Option<Vec<u32>>,
+    index: Option<u32>,
+    item: PdfOutlineItem,
+) -> Result<Vec<PdfOutlineItem>, MediaError> {
+    send_outline_request(
+        doc_id,
+        Some(OutlineEdit::Add {
+            parent_path: parent_path.unwrap_or_default(),
+            index,
+            item,
+        }),
+    )
+}
 
-                        // 建立單一頁面
-                        let size = PdfPagePaperSize::Custom(
-                            PdfPoints::new(width_pt),
-                            PdfPoints::new(height_pt),
-                        );
-                        let mut page =
-                            doc.pages_mut().create_page_at_index(size, 0).map_err(|e| {
-                                MediaError::new("io_error", format!("建立頁面失敗: {e}"))
-                            })?;
+#[tauri::command]
+pub fn pdf_outline_rename(
+    doc_id: u64,
+    path: Vec<u32>,
+    title: String,
+) -> Result<Vec<PdfOutlineItem>, MediaError> {
+    send_outline_request(doc_id, Some(OutlineEdit::Rename { path, title }))
+}
 
-                        // 在頁面上放置圖片，鋪滿整頁
-                        {
-                            let objects = page.objects_mut();
-                            // 以 0,0 為左下角，指定輸出寬高為整頁
-                            let _obj = objects
-                                .create_image_object(
-                                    PdfPoints::new(0.0),
-                                    PdfPoints::new(0.0),
-                                    &dyn_img,
-                                    Some(PdfPoints::new(width_pt)),
-                                    Some(PdfPoints::new(height_pt)),
-                                )
-                                .map_err(|e| {
-                                    MediaError::new("unsupported", format!("建立影像物件失敗: {e}"))
-                                })?;
-                            // create_image_object 已自動加入頁面物件集合，無需再 add
-                        }
+// newParentPath 與 index 以移除原項目後的樹為準
+#[tauri::command]
+pub fn pdf_outline_move(
+    doc_id: u64,
+    path: Vec<u32>,
+    new_parent_path: Option<Vec<u32>>,
+    index: Option<u32>,
+) -> Result<Vec<PdfOutlineItem>, MediaError> {
+    send_outline_request(
+        doc_id,
+        Some(OutlineEdit::Move {
+            path,
+            new_parent_path: new_parent_path.unwrap_or_default(),
+            index,
+        }),
+    )
+}
+
+#[tauri::command]
+pub fn pdf_outline_delete(doc_id: u64, path: Vec<u32>) -> Result<Vec<PdfOutlineItem>, MediaError> {
+    send_outline_request(doc_id, Some(OutlineEdit::Delete { path }))
+}
+
+// 由 (標題, 頁) 清單產生單層書籤；append=true 時加在既有書籤之後
+#[tauri::command]
+pub fn pdf_outline_generate(
+    doc_id: u64,
+    entries: Vec<PdfOutlineEntry>,
+    append: Option<bool>,
+) -> Result<Vec<PdfOutlineItem>, MediaError> {
+    let mut items: Vec<PdfOutlineItem> = entries
+        .into_iter()
+        .map(|e| PdfOutlineItem {
+            title: e.title,
+            page_index: Some(e.page_index),
+            left: None,
+            top: None,
+            zoom: None,
+            uri: None,
+            open: false,
+            children: Vec::new(),
+        })
+        .collect();
+    if append.unwrap_or(false) {
+        let mut existing = send_outline_request(doc_id, None)?;
+        existing.append(&mut items);
+        items = existing;
+    }
+    send_outline_request(doc_id, Some(OutlineEdit::Set(items)))
+}
+
+// 文件中繼資料；日期為 ISO 8601
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfDocMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    pub creator: Option<String>,
+    pub producer: Option<String>,
+    pub creation_date: Option<String>,
+    pub mod_date: Option<String>,
+    pub custom: BTreeMap<String, String>,
+    pub pdf_version: String,
+    pub has_xmp: bool,
+}
+
+// 欄位為 None 表示不變、空字串表示移除；custom 僅更新列出的鍵
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfSetMetadataArgs {
+    pub doc_id: u64,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    pub creator: Option<String>,
+    pub producer: Option<String>,
+    pub creation_date: Option<String>,
+    pub mod_date: Option<String>,
+    pub custom: Option<BTreeMap<String, String>>,
+    pub touch_mod_date: Option<bool>, // 未指定 modDate 時更新為現在（預設 true）
+    pub xmp: Option<bool>,            // 同步寫入 XMP（預設 true）
+}
+
+#[tauri::command]
+pub fn pdf_get_metadata(doc_id: u64) -> Result<PdfDocMetadata, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Metadata {
+            doc_id,
+            set: None,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[tauri::command]
+pub fn pdf_set_metadata(args: PdfSetMetadataArgs) -> Result<PdfDocMetadata, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Metadata {
+            doc_id: args.doc_id,
+            set: Some(args),
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfWatermarkArgs {
+    pub doc_id: u64,
+    pub range: Option<String>, // 1-based 頁碼範圍，預設全部
+    pub text: Option<String>,  // 與 imagePath 擇一；可含換行
+    pub image_path: Option<String>,
+    pub font: Option<String>,        // 標準 14 字型名稱，預設 Helvetica
+    pub font_path: Option<String>,   // TrueType 字型檔（中文等非 Latin-1 文字需要）
+    pub font_size: Option<f32>,      // 預設 48
+    pub color: Option<String>,       // '#RRGGBB'，預設 #808080
+    pub opacity: Option<f32>,        // 0~1，預設 0.3
+    pub rotation: Option<f32>,       // 逆時針角度，相對於顯示方向
+    pub image_width_pt: Option<f32>, // 影像寬度，預設頁寬 40%，高度依比例
+    pub position: Option<String>, // 'center' | 'top-left' | 'top' | 'top-right' | 'left' | 'right' | 'bottom-left' | 'bottom' | 'bottom-right'
+    pub offset_x: Option<f32>,    // 位置微調（pt，向右/向上為正）
+    pub offset_y: Option<f32>,
+    pub margin_pt: Option<f32>,   // 非置中位置與頁緣的距離，預設 36
+    pub tile: Option<bool>,       // 鋪滿整頁（忽略 position）
+    pub tile_gap_pt: Option<f32>, // 鋪排間距，預設 72
+    pub layer: Option<String>,    // 'over'（預設）| 'under'
+}
+
+// 以 Pdfium 頁面物件加入文字/影像浮水印（文字保持向量且可搜尋）；回傳修改的頁數
+#[tauri::command]
+pub fn pdf_add_watermark(args: PdfWatermarkArgs) -> Result<usize, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Watermark { args, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfHeaderFooterArgs {
+    pub doc_id: u64,
+    pub range: Option<String>, // 1-based 頁碼範圍，預設全部
+    // 範本可用 {page}、{total}、{filename}、{date}、{bates}
+    pub header_left: Option<String>,
+    pub header_center: Option<String>,
+    pub header_right: Option<String>,
+    pub footer_left: Option<String>,
+    pub footer_center: Option<String>,
+    pub footer_right: Option<String>,
+    pub font: Option<String>,      // 標準 14 字型名稱，預設 Helvetica
+    pub font_path: Option<String>, // TrueType 字型檔（中文等非 Latin-1 文字需要）
+    pub font_size: Option<f32>,    // 預設 10
+    pub color: Option<String>,     // '#RRGGBB'，預設 #000000
+    pub margin_x_pt: Option<f32>,  // 左右對齊文字與頁緣的距離，預設 36
+    pub margin_y_pt: Option<f32>,  // 頁首/頁尾與上下頁緣的距離，預設 24
+    pub start_number: Option<u32>, // 範圍內第一頁的 {page}，預設 1；{total} 為最後一頁的編號
+    pub date: Option<String>,      // {date} 的內容，預設為本地時區的今日（YYYY-MM-DD）
+    pub bates_prefix: Option<String>,
+    pub bates_suffix: Option<String>,
+    pub bates_start: Option<u64>, // 預設 1
+    pub bates_digits: Option<u8>, // 補零位數，預設 6
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfHeaderFooterResult {
+    pub pages: usize,
+    // 下一份文件接續用的 Bates 起始號
+    pub next_bates: u64,
+}
+
+// 以文字頁面物件加入頁碼、頁首/頁尾與 Bates 編號
+#[tauri::command]
+pub fn pdf_add_header_footer(
+    args: PdfHeaderFooterArgs,
+) -> Result<PdfHeaderFooterResult, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::HeaderFooter { args, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfAnnotationInfo {
+    pub page_index: u32,
+    pub index: u32,           // 於該頁 /Annots 中的位置
+    pub kind: String, // 'highlight' | 'underline' | 'strikeout' | 'freeText' | 'ink' | 'square' | 'circle' | 'text' | 'link' | 'widget' ...
+    pub rect: Option<PdfBox>, // pt，頁面使用者空間
+    pub contents: Option<String>,
+    pub author: Option<String>,
+    pub color: Option<String>,      // '#RRGGBB'
+    pub fill_color: Option<String>, // 矩形/橢圓內部或 freeText 底色
+    pub opacity: Option<f32>,
+    pub modified: Option<String>, // ISO 8601
+    pub quads: Vec<[f32; 8]>,     // 文字標記範圍，格式同 PdfSearchHit.quads
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfAnnotationRef {
+    pub page_index: u32,
+    pub index: u32,
+}
+
+// 座標皆為頁面使用者空間（pt）
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfAddAnnotationArgs {
+    pub doc_id: u64,
+    pub page_index: u32,
+    pub kind: String, // 'highlight' | 'underline' | 'strikeout' | 'freeText' | 'ink' | 'square' | 'circle'
+    pub quads: Option<Vec<Vec<f32>>>, // 文字標記：每行 8 個值，格式同 PdfSearchHit.quads
+    pub rect: Option<PdfBox>, // freeText、square、circle
+    pub ink_list: Option<Vec<Vec<f32>>>, // ink：每筆 [x1, y1, x2, y2, ...]
+    pub contents: Option<String>, // 註解內容；freeText 為顯示文字
+    pub author: Option<String>,
+    pub color: Option<String>, // '#RRGGBB'；線條/文字色，預設螢光標記為黃色、freeText 為黑色、其餘為紅色
+    pub fill_color: Option<String>, // 矩形/橢圓內部或 freeText 底色，預設無
+    pub opacity: Option<f32>,  // 0~1，預設 1
+    pub border_width: Option<f32>, // 預設 ink 2、square/circle 1、freeText 0（無框）
+    pub font_size: Option<f32>, // freeText，預設 12
+    pub font: Option<String>,  // freeText：標準 14 字型名稱，預設 Helvetica
+    pub font_path: Option<String>, // freeText：TrueType 字型檔（中文等非 Latin-1 文字需要）
+}
+
+fn send_annotation_request(
+    doc_id: u64,
+    page_index: Option<u32>,
+    edit: Option<AnnotationEdit>,
+) -> Result<Vec<PdfAnnotationInfo>, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Annotations {
+            doc_id,
+            page_index,
+            edit,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+// 列出註解（不含 Popup）；未指定 pageIndex 時為全部頁面
+#[tauri::command]
+pub fn pdf_annotations(
+    doc_id: u64,
+    page_index: Option<u32>,
+) -> Result<Vec<PdfAnnotationInfo>, MediaError> {
+    send_annotation_request(doc_id, page_index, None)
+}
+
+#[tauri::command]
+pub fn pdf_annotation_add(args: PdfAddAnnotationArgs) -> Result<PdfAnnotationInfo, MediaError> {
+    send_annotation_request(args.doc_id, None, Some(AnnotationEdit::Add(Box::new(args))))?
+        .pop()
+        .ok_or_else(|| MediaError::new("io_error", "建立註解失敗"))
+}
+
+// 依 (pageIndex, index) 刪除；回傳剩餘的註解
+#[tauri::command]
+pub fn pdf_annotation_delete(
+    doc_id: u64,
+    targets: Vec<PdfAnnotationRef>,
+) -> Result<Vec<PdfAnnotationInfo>, MediaError> {
+    send_annotation_request(doc_id, None, Some(AnnotationEdit::Delete(targets)))
+}
+
+// 將 widget 與連結以外的註解畫入頁面內容；回傳剩餘的註解
+#[tauri::command]
+pub fn pdf_annotations_flatten(doc_id: u64) -> Result<Vec<PdfAnnotationInfo>, MediaError> {
+    send_annotation_request(doc_id, None, Some(AnnotationEdit::Flatten))
+}
+
+// 表單欄位值：文字/選項為字串，核取方塊可用布林，多選清單為字串陣列
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum PdfFormValue {
+    Bool(bool),
+    Text(String),
+    List(Vec<String>),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfFormOption {
+    pub value: String, // 匯出值（核取方塊/選項按鈕為開啟狀態名稱）
+    pub label: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfFormWidgetInfo {
+    pub page_index: Option<u32>,
+    pub rect: Option<PdfBox>, // pt，頁面使用者空間
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfFormFieldInfo {
+    pub name: String,       // 完整名稱（父欄位以 '.' 串接）
+    pub field_type: String, // 'text' | 'checkbox' | 'radio' | 'combo' | 'list' | 'button' | 'signature' | 'unknown'
+    pub value: Option<PdfFormValue>,
+    pub options: Vec<PdfFormOption>,
+    pub read_only: bool,
+    pub required: bool,
+    pub multiline: bool,
+    pub multi_select: bool,
+    pub widgets: Vec<PdfFormWidgetInfo>,
+}
+
+fn send_form_request(
+    doc_id: u64,
+    edit: Option<FormEdit>,
+) -> Result<Vec<PdfFormFieldInfo>, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Form {
+            doc_id,
+            edit,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[tauri::command]
+pub fn pdf_form_fields(doc_id: u64) -> Result<Vec<PdfFormFieldInfo>, MediaError> {
+    send_form_request(doc_id, None)
+}
+
+// 依完整欄位名稱填值；回傳填寫後的欄位清單
+#[tauri::command]
+pub fn pdf_form_fill(
+    doc_id: u64,
+    values: HashMap<String, PdfFormValue>,
+) -> Result<Vec<PdfFormFieldInfo>, MediaError> {
+    send_form_request(doc_id, Some(FormEdit::Fill(values)))
+}
+
+// 將欄位外觀畫入頁面內容並移除表單（其他註解保留）
+#[tauri::command]
+pub fn pdf_form_flatten(doc_id: u64) -> Result<Vec<PdfFormFieldInfo>, MediaError> {
+    send_form_request(doc_id, Some(FormEdit::Flatten))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfTextLayerArgs {
+    pub doc_id: u64,
+    pub page_index: u32,
+    pub width_px: Option<u32>, // 對應 PageRender.widthPx；預設為 1px = 1pt
+}
+
+// 座標與字級皆為像素，與同寬度的 PageRender 對齊；angle 為順時針角度
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfTextRun {
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub font_size: f32,
+    pub angle: f32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfTextLayer {
+    pub page_index: u32,
+    pub width_px: u32,
+    pub height_px: u32,
+    pub runs: Vec<PdfTextRun>,
+}
+
+// 供檢視器疊加可選取文字層
+#[tauri::command]
+pub fn pdf_page_text_layer(args: PdfTextLayerArgs) -> Result<PdfTextLayer, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::TextLayer { args, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfSearchArgs {
+    pub doc_id: u64,
+    pub query: String,
+    pub match_case: Option<bool>,
+    pub whole_word: Option<bool>,
+    pub regex: Option<bool>,
+    pub max_hits: Option<usize>, // 預設 10000
+    #[serde(alias = "gen")]
+    pub r#gen: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfSearchHit {
+    pub text: String,
+    pub quads: Vec<[f32; 8]>,
+}
+
+// 'pdf-search-hit' 事件：每頁一次
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfSearchPageHits {
+    pub doc_id: u64,
+    pub r#gen: u64,
+    pub page_index: u32,
+    pub hits: Vec<PdfSearchHit>,
+}
 
-                        doc.save_to_file(&dest_path).map_err(|e| {
-                            MediaError::new("io_error", format!("寫入 PDF 失敗: {e}"))
-                        })?;
-                        Ok(dest_path)
-                    })();
-                    let _ = reply.send(res);
-                }
-                Err(_) => break,
+// 'pdf-search-done' 事件與 pdf_search 回傳值
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfSearchSummary {
+    pub doc_id: u64,
+    pub r#gen: u64,
+    pub total: usize,
+    pub pages_searched: u32,
+    pub canceled: bool,
+}
+
+// 全文搜尋：結果以事件逐頁送出；新的 gen 會取代同文件進行中的搜尋。
+// 未指定 gen 時由此配發（目前最小有效 gen + 1），並於回傳值與事件中帶回
+#[tauri::command]
+pub async fn pdf_search(mut args: PdfSearchArgs) -> Result<PdfSearchSummary, MediaError> {
+    {
+        let mut gens = SEARCH_MIN_GEN.lock().unwrap();
+        let entry = gens.entry(args.doc_id).or_insert(0);
+        match args.r#gen {
+            Some(g) if g > *entry => *entry = g,
+            Some(_) => {}
+            None => {
+                *entry += 1;
+                args.r#gen = Some(*entry);
             }
         }
-    });
+    }
+    tokio::task::spawn_blocking(move || -> Result<PdfSearchSummary, MediaError> {
+        let (rtx, rrx) = mpsc::channel();
+        WORKER_TX
+            .lock()
+            .unwrap()
+            .as_ref()
+            .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+            .send(PdfRequest::Search { args, reply: rtx })
+            .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+        rrx.recv()
+            .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+    })
+    .await
+    .map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))?
 }
 
+// 取消 gen 小於 min_gen 的搜尋（直接生效，不經 worker 佇列）
 #[tauri::command]
-pub fn analyze_media(path: String) -> Result<MediaDescriptor, MediaError> {
-    let p = Path::new(&path);
-    if !p.exists() {
-        return Err(MediaError::new(
-            "not_found",
-            format!("檔案不存在: {}", path),
-        ));
+pub fn pdf_search_cancel(doc_id: u64, min_gen: u64) -> Result<(), MediaError> {
+    let mut gens = SEARCH_MIN_GEN.lock().unwrap();
+    let entry = gens.entry(doc_id).or_insert(0);
+    if min_gen > *entry {
+        *entry = min_gen;
     }
-    let kind = infer_media_type(p);
-    let name = file_name(p);
-    let size = try_stat_size(p);
-
-    let desc = MediaDescriptor {
-        path,
-        kind,
-        name,
-        size,
-        pages: None,
-        width: None,
-        height: None,
-        orientation: None,
-    };
-    Ok(desc)
+    Ok(())
 }
 
-// 移除 pdf_info：請改用 pdf_open 的回傳或 pdf_page_size。
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfExtractTextArgs {
+    pub doc_id: u64,
+    pub range: Option<String>,       // 1-based 頁碼範圍，預設全部
+    pub granularity: Option<String>, // 'page' | 'word' | 'char'，預設 'page'
+}
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PdfOpenResult {
-    pub doc_id: u64,
-    pub pages: usize,
+pub struct PdfTextItem {
+    pub text: String,
+    pub bbox: PdfBox,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PdfPageSize {
-    pub width_pt: f32,
-    pub height_pt: f32,
+pub struct PdfPageTextResult {
+    pub index: u32,
+    pub text: String,
+    pub chars: Option<Vec<PdfTextItem>>,
+    pub words: Option<Vec<PdfTextItem>>,
 }
 
 #[tauri::command]
-pub fn pdf_open(path: String) -> Result<PdfOpenResult, MediaError> {
+pub fn pdf_extract_text(args: PdfExtractTextArgs) -> Result<Vec<PdfPageTextResult>, MediaError> {
     let (rtx, rrx) = mpsc::channel();
     WORKER_TX
         .lock()
         .unwrap()
         .as_ref()
         .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
-        .send(PdfRequest::Open { path, reply: rtx })
+        .send(PdfRequest::ExtractText { args, reply: rtx })
         .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
     rrx.recv()
         .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
 }
 
+// 匯出文字為 .txt（UTF-8），頁與頁之間以換頁字元（\f）分隔
 #[tauri::command]
-pub fn pdf_close(doc_id: u64) -> Result<(), MediaError> {
-    let (rtx, rrx) = mpsc::channel();
-    WORKER_TX
-        .lock()
-        .unwrap()
-        .as_ref()
-        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
-        .send(PdfRequest::Close { doc_id, reply: rtx })
-        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
-    rrx.recv()
-        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+pub fn pdf_export_text(
+    doc_id: u64,
+    dest_path: String,
+    range: Option<String>,
+    overwrite: Option<bool>,
+) -> Result<String, MediaError> {
+    if !overwrite.unwrap_or(false) && Path::new(&dest_path).exists() {
+        return Err(MediaError::new(
+            "io_error",
+            format!("目的檔已存在：{}（overwrite=false）", dest_path),
+        ));
+    }
+    let pages = pdf_extract_text(PdfExtractTextArgs {
+        doc_id,
+        range,
+        granularity: None,
+    })?;
+    let body = pages
+        .iter()
+        .map(|p| p.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\u{000C}");
+    fs::write(&dest_path, body)
+        .map_err(|e| MediaError::new("io_error", format!("寫入文字檔失敗: {e}")))?;
+    Ok(dest_path)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfDetectBlankArgs {
+    pub doc_id: u64,
+    pub range: Option<String>,        // 1-based 頁碼範圍，預設全部
+    pub dpi: Option<u16>,             // 渲染解析度，預設 40
+    pub ink_threshold: Option<f32>,   // 覆蓋率不超過此值視為空白（0~1），預設 0.001
+    pub noise_threshold: Option<f32>, // 4x4 區塊墨點比例低於此值視為雜點，預設 0.25
+    pub tolerance: Option<u8>,        // 亮度低於 255-tolerance 視為墨跡，預設 64
+    pub edge_margin: Option<f32>,     // 忽略的邊緣比例（每側），預設 0.03
+    pub delete: Option<bool>,         // true：刪除偵測到的空白頁
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfBlankPageScore {
+    pub index: u32,
+    pub coverage: f32,
+    pub blank: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfBlankPagesResult {
+    pub pages: Vec<PdfBlankPageScore>,
+    pub deleted: Vec<u32>,
+    pub pages_after: Option<usize>,
 }
 
+// 偵測空白頁；delete=true 時一併刪除偵測到的空白頁
 #[tauri::command]
-pub fn pdf_page_size(doc_id: u64, page_index: u32) -> Result<PdfPageSize, MediaError> {
+pub fn pdf_detect_blank_pages(args: PdfDetectBlankArgs) -> Result<PdfBlankPagesResult, MediaError> {
     let (rtx, rrx) = mpsc::channel();
     WORKER_TX
         .lock()
         .unwrap()
         .as_ref()
         .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
-        .send(PdfRequest::Size {
-            doc_id,
-            page_index,
-            reply: rtx,
-        })
+        .send(PdfRequest::DetectBlankPages { args, reply: rtx })
         .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
     rrx.recv()
         .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
@@ -1752,233 +10063,161 @@ pub fn pdf_page_size(doc_id: u64, page_index: u32) -> Result<PdfPageSize, MediaE
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PdfRenderArgs {
+pub struct PdfDeskewArgs {
+    pub doc_id: u64,
+    pub range: Option<String>,  // 1-based 頁碼範圍，預設全部
+    pub max_angle: Option<f32>, // 搜尋與套用的最大角度（度），預設 5
+    pub min_angle: Option<f32>, // 小於此角度不處理，預設 0.1
+    pub apply: Option<bool>,    // true：重寫頁面影像；預設僅偵測
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfDeskewPreviewArgs {
     pub doc_id: u64,
-    #[serde(alias = "page_index")]
     pub page_index: u32,
-    pub scale: Option<f32>,
-    pub dpi: Option<f32>,
+    pub max_angle: Option<f32>,
+    pub angle: Option<f32>,        // 指定角度則略過偵測
+    pub target_width: Option<u32>, // 預設 800
     pub format: Option<String>,
-    pub target_width: Option<u32>,
     pub quality: Option<u8>,
-    #[serde(alias = "gen")]
-    pub r#gen: Option<u64>,
 }
 
+// angle 為偵測到的傾斜角（度，正值為順時針傾斜）；reason：not_image_page | no_content | above_max | below_min
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfDeskewResult {
+    pub index: u32,
+    pub angle: Option<f32>,
+    pub confidence: f32,
+    pub applied: bool,
+    pub reason: Option<String>,
+}
+
+// 掃描頁校正傾斜（僅處理只含單一影像的頁面）
 #[tauri::command]
-pub fn pdf_render_page(args: PdfRenderArgs) -> Result<PageRender, MediaError> {
+pub fn pdf_deskew(args: PdfDeskewArgs) -> Result<Vec<PdfDeskewResult>, MediaError> {
     let (rtx, rrx) = mpsc::channel();
     WORKER_TX
         .lock()
         .unwrap()
         .as_ref()
         .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
-        .send(PdfRequest::Render { args, reply: rtx })
+        .send(PdfRequest::Deskew { args, reply: rtx })
         .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
     rrx.recv()
         .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
 }
 
-// ⚡ 新增：異步版本（真正並行渲染）
-#[tauri::command]
-pub async fn pdf_render_page_async(args: PdfRenderArgs) -> Result<PageRender, MediaError> {
-    // 使用 Tokio 阻塞執行緒池，避免阻塞主執行緒
-    tokio::task::spawn_blocking(move || -> Result<PageRender, MediaError> {
-        // 複用現有的同步邏輯
-        let (rtx, rrx) = mpsc::channel();
-        WORKER_TX
-            .lock()
-            .unwrap()
-            .as_ref()
-            .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
-            .send(PdfRequest::Render { args, reply: rtx })
-            .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
-
-        rrx.recv()
-            .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))? // 加上 ? 展開
-    })
-    .await
-    .map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))?
-}
-
+// 預覽校正後的頁面，不變更文件
 #[tauri::command]
-pub fn pdf_render_cancel(doc_id: u64, page_index: u32, min_gen: u64) -> Result<(), MediaError> {
+pub fn pdf_deskew_preview(args: PdfDeskewPreviewArgs) -> Result<PageRender, MediaError> {
     let (rtx, rrx) = mpsc::channel();
     WORKER_TX
         .lock()
         .unwrap()
         .as_ref()
         .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
-        .send(PdfRequest::Cancel {
-            doc_id,
-            page_index,
-            min_gen,
-            reply: rtx,
-        })
+        .send(PdfRequest::DeskewPreview { args, reply: rtx })
         .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
     rrx.recv()
         .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
 }
 
-fn render_page_for_document(
-    document: &pdfium_render::prelude::PdfDocument,
-    args: &PdfRenderArgs,
-) -> Result<PageRender, MediaError> {
-    use pdfium_render::prelude::*;
-
-    let page_index_u16: u16 = args.page_index.try_into().map_err(|_| {
-        MediaError::new("invalid_input", format!("頁索引過大: {}", args.page_index))
-    })?;
-    let page = document
-        .pages()
-        .get(page_index_u16)
-        .map_err(|_| MediaError::new("not_found", format!("頁索引不存在: {}", args.page_index)))?;
-
-    let mut cfg = PdfRenderConfig::new();
-
-    if let Some(w) = args.target_width {
-        let width_px_i32 = i32::try_from(w.max(1)).unwrap_or(i32::MAX);
-        cfg = cfg.set_target_width(width_px_i32);
-    } else if let Some(dpi_val) = args.dpi {
-        let w_pt = page.width().value as f32;
-        let width_px = ((w_pt * dpi_val / 72.0).ceil() as u32).max(1);
-        let width_px_i32 = i32::try_from(width_px).unwrap_or(i32::MAX);
-        cfg = cfg.set_target_width(width_px_i32);
-    } else {
-        let base = 1200.0_f32;
-        let s = args.scale.unwrap_or(1.0).max(0.1);
-        let width_px = (base * s).round() as u32;
-        let width_px_i32 = i32::try_from(width_px).unwrap_or(i32::MAX);
-        cfg = cfg.set_target_width(width_px_i32);
-    }
-
-    let bitmap = page
-        .render_with_config(&cfg)
-        .map_err(|e| MediaError::new("parse_error", format!("渲染失敗: {e}")))?;
-
-    let img = bitmap.as_image();
-    let (w, h) = (img.width(), img.height());
-
-    let fmt = args
-        .format
-        .as_ref()
-        .cloned()
-        .unwrap_or_else(|| "png".to_string())
-        .to_lowercase();
-    let out_fmt = if fmt == "raw" {
-        "raw" // ⚡ 新增：直接傳 raw RGBA bitmap
-    } else if fmt == "webp" {
-        "webp"
-    } else if fmt == "jpeg" || fmt == "jpg" {
-        "jpeg"
-    } else {
-        "png"
-    };
-
-    let mut buf: Vec<u8> = Vec::new();
-    if out_fmt == "raw" {
-        // ⚡ 直接傳 RGBA raw bytes（無需編碼）
-        let rgba = img.to_rgba8();
-        buf = rgba.into_raw(); // 零開銷：直接取得底層 Vec<u8>
-    } else if out_fmt == "webp" {
-        // 使用 webp crate 支援有損編碼
-        let rgba = img.to_rgba8();
-        let quality = args.quality.unwrap_or(85).clamp(1, 100) as f32;
-        let encoder = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height());
-        let encoded = encoder.encode(quality);
-        buf = encoded.to_vec();
-    } else if out_fmt == "png" {
-        use image::ColorType;
-        use image::codecs::png::{CompressionType, FilterType, PngEncoder};
-        let rgba = img.to_rgba8();
-        let comp = if args.quality.unwrap_or(100) <= 50 {
-            CompressionType::Fast
-        } else {
-            CompressionType::Default
-        };
-        let enc = PngEncoder::new_with_quality(Cursor::new(&mut buf), comp, FilterType::NoFilter);
-        enc.write_image(&rgba, rgba.width(), rgba.height(), ColorType::Rgba8.into())
-            .map_err(|e| MediaError::new("io_error", format!("編碼 PNG 失敗: {e}")))?;
-    } else if out_fmt == "jpeg" {
-        use image::ColorType;
-        use image::codecs::jpeg::JpegEncoder;
-        // JPEG 不支援帶 alpha 的 RGBA；需轉為 RGB 並丟棄 alpha
-        let rgb = img.to_rgb8();
-        let enc = JpegEncoder::new_with_quality(Cursor::new(&mut buf), args.quality.unwrap_or(82));
-        enc.write_image(&rgb, rgb.width(), rgb.height(), ColorType::Rgb8.into())
-            .map_err(|e| MediaError::new("io_error", format!("編碼 JPEG 失敗: {e}")))?;
-    } else {
-        use image::ColorType;
-        use image::codecs::png::{CompressionType, FilterType, PngEncoder};
-        let rgba = img.to_rgba8();
-        let enc = PngEncoder::new_with_quality(
-            Cursor::new(&mut buf),
-            CompressionType::Default,
-            FilterType::NoFilter,
-        );
-        enc.write_image(&rgba, rgba.width(), rgba.height(), ColorType::Rgba8.into())
-            .map_err(|e| MediaError::new("io_error", format!("編碼 PNG 失敗: {e}")))?;
-    }
-
-    Ok(PageRender {
-        page_index: args.page_index,
-        width_px: w as u32,
-        height_px: h as u32,
-        scale: args.scale,
-        dpi: args.dpi,
-        format: out_fmt.to_string(),
-        image_bytes: buf,
-    })
-}
-
-// 通用回傳：僅回報頁數
-#[derive(Serialize)]
+#[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PdfPagesResult {
-    pub pages: usize,
+pub struct PdfAutoOrientArgs {
+    pub doc_id: u64,
+    pub range: Option<String>,       // 1-based 頁碼範圍，預設全部
+    pub apply: Option<bool>,         // true：套用信心值足夠的修正；預設僅建議
+    pub min_confidence: Option<f32>, // 套用門檻（0~1），預設 0.6
+    pub dpi: Option<u16>,            // 影像分析的渲染解析度，預設 72
 }
 
-// 儲存回傳：路徑與頁數
+// correction 為需順時針補轉的角度，suggested 為修正後的絕對角度
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PdfSaveResult {
-    pub path: String,
-    pub pages: usize,
+pub struct PdfOrientationGuess {
+    pub index: u32,
+    pub rotation: u16,
+    pub correction: u16,
+    pub suggested: u16,
+    pub confidence: f32,
+    pub method: String, // 'text' | 'image' | 'none'
+    pub applied: bool,
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct PdfExportImageResult {
-    pub path: String,
-    pub width_px: u32,
-    pub height_px: u32,
-    pub format: String,
+// 自動判斷頁面方向（文字角度或影像分析），可選擇直接套用
+#[tauri::command]
+pub fn pdf_auto_orient(args: PdfAutoOrientArgs) -> Result<Vec<PdfOrientationGuess>, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::AutoOrient { args, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct PdfExportPdfResult {
-    pub path: String,
+// 各頁目前的旋轉角度（0/90/180/270），依頁序
+#[tauri::command]
+pub fn pdf_page_rotations(doc_id: u64) -> Result<Vec<u16>, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::PageRotations { doc_id, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
 }
 
 #[tauri::command]
-pub fn pdf_insert_blank(
+pub fn pdf_rotate_page_relative(
     doc_id: u64,
     index: u32,
-    width_pt: f32,
-    height_pt: f32,
-) -> Result<PdfPagesResult, MediaError> {
+    delta_deg: i16,
+) -> Result<u16, MediaError> {
     let (rtx, rrx) = mpsc::channel();
     WORKER_TX
         .lock()
         .unwrap()
         .as_ref()
         .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
-        .send(PdfRequest::InsertBlank {
+        .send(PdfRequest::RotatePageRelative {
             doc_id,
             index,
-            width_pt,
-            height_pt,
+            delta_deg,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[tauri::command]
+pub fn pdf_copy_page(
+    src_doc_id: u64,
+    src_index: u32,
+    dest_doc_id: u64,
+    dest_index: u32,
+) -> Result<PdfPagesResult, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::CopyPage {
+            src_doc_id,
+            src_index,
+            dest_doc_id,
+            dest_index,
             reply: rtx,
         })
         .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
@@ -1988,17 +10227,25 @@ pub fn pdf_insert_blank(
     Ok(PdfPagesResult { pages })
 }
 
+// range 為 1-based 頁碼範圍字串；預設插入於所選最後一頁之後
 #[tauri::command]
-pub fn pdf_delete_pages(doc_id: u64, indices: Vec<u32>) -> Result<PdfPagesResult, MediaError> {
+pub fn pdf_duplicate_pages(
+    doc_id: u64,
+    range: String,
+    dest_index: Option<u32>,
+    copies: Option<u32>,
+) -> Result<PdfPagesResult, MediaError> {
     let (rtx, rrx) = mpsc::channel();
     WORKER_TX
         .lock()
         .unwrap()
         .as_ref()
         .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
-        .send(PdfRequest::DeletePages {
+        .send(PdfRequest::DuplicatePages {
             doc_id,
-            indices,
+            range,
+            dest_index,
+            copies: copies.unwrap_or(1),
             reply: rtx,
         })
         .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
@@ -2008,55 +10255,63 @@ pub fn pdf_delete_pages(doc_id: u64, indices: Vec<u32>) -> Result<PdfPagesResult
     Ok(PdfPagesResult { pages })
 }
 
+// 反轉所選頁面順序（未指定 range 則反轉整份文件）
 #[tauri::command]
-pub fn pdf_rotate_page(doc_id: u64, index: u32, rotate_deg: u16) -> Result<(), MediaError> {
+pub fn pdf_reverse_pages(doc_id: u64, range: Option<String>) -> Result<PdfPagesResult, MediaError> {
     let (rtx, rrx) = mpsc::channel();
     WORKER_TX
         .lock()
         .unwrap()
         .as_ref()
         .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
-        .send(PdfRequest::RotatePage {
+        .send(PdfRequest::ReversePages {
             doc_id,
-            index,
-            rotate_deg,
+            range,
             reply: rtx,
         })
         .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
-    rrx.recv()
+    let pages = rrx
+        .recv()
         .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))??;
-    Ok(())
+    Ok(PdfPagesResult { pages })
 }
 
+// 雙面掃描整理：與 otherDocId 交錯合併（結果寫回 docId），或未指定時交錯文件前後兩半。
+// 背面掃描為反序時設定 reverseSecond=true。
 #[tauri::command]
-pub fn pdf_rotate_page_relative(
+pub fn pdf_interleave_pages(
     doc_id: u64,
-    index: u32,
-    delta_deg: i16,
-) -> Result<u16, MediaError> {
+    other_doc_id: Option<u64>,
+    reverse_second: Option<bool>,
+) -> Result<PdfPagesResult, MediaError> {
     let (rtx, rrx) = mpsc::channel();
     WORKER_TX
         .lock()
         .unwrap()
         .as_ref()
         .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
-        .send(PdfRequest::RotatePageRelative {
+        .send(PdfRequest::InterleavePages {
             doc_id,
-            index,
-            delta_deg,
+            other_doc_id,
+            reverse_second: reverse_second.unwrap_or(false),
             reply: rtx,
         })
         .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
-    rrx.recv()
-        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+    let pages = rrx
+        .recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))??;
+    Ok(PdfPagesResult { pages })
 }
 
+// 自檔案插入頁面：srcPaths 可為單一 PDF（搭配 1-based range，如 "1,3,5-7"）或多張圖片，
+// 依序於 index 一次插入
 #[tauri::command]
-pub fn pdf_copy_page(
-    src_doc_id: u64,
-    src_index: u32,
-    dest_doc_id: u64,
-    dest_index: u32,
+pub fn pdf_insert_from_file(
+    doc_id: u64,
+    src_paths: Vec<String>,
+    index: u32,
+    range: Option<String>,
+    src_password: Option<String>,
 ) -> Result<PdfPagesResult, MediaError> {
     let (rtx, rrx) = mpsc::channel();
     WORKER_TX
@@ -2064,11 +10319,12 @@ pub fn pdf_copy_page(
         .unwrap()
         .as_ref()
         .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
-        .send(PdfRequest::CopyPage {
-            src_doc_id,
-            src_index,
-            dest_doc_id,
-            dest_index,
+        .send(PdfRequest::InsertFromFile {
+            doc_id,
+            src_paths,
+            src_password,
+            range,
+            index,
             reply: rtx,
         })
         .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
@@ -2078,11 +10334,106 @@ pub fn pdf_copy_page(
     Ok(PdfPagesResult { pages })
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfSetPageBoxesArgs {
+    pub doc_id: u64,
+    pub range: Option<String>, // 1-based 頁碼範圍，預設全部
+    pub media_box: Option<PdfBox>,
+    pub crop_box: Option<PdfBox>,
+    pub trim_box: Option<PdfBox>,
+    pub bleed_box: Option<PdfBox>,
+    pub art_box: Option<PdfBox>,
+    pub crop_to_content: Option<bool>, // 依渲染結果自動裁切至內容外框
+    pub margin_pt: Option<f32>,        // 自動裁切時保留的邊界
+    pub dpi: Option<f32>,              // 自動裁切的偵測解析度（預設 50）
+    pub tolerance: Option<u8>,         // 視為白色的容許值（預設 16）
+}
+
+// 回傳實際修改的頁數
+#[tauri::command]
+pub fn pdf_set_page_boxes(args: PdfSetPageBoxesArgs) -> Result<usize, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::SetPageBoxes { args, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfNormalizePageSizeArgs {
+    pub doc_id: u64,
+    pub range: Option<String>,       // 1-based 頁碼範圍，預設全部
+    pub paper: String,               // 'A4' | 'Letter' | 'A5' | 'Legal' | 'Tabloid' | 'Custom'
+    pub width_pt: Option<f32>,       // Custom 用
+    pub height_pt: Option<f32>,      // Custom 用
+    pub orientation: Option<String>, // 'auto'（依各頁原方向）| 'portrait' | 'landscape'
+    pub mode: Option<String>,        // 'fit' | 'fill' | 'none'
+    pub margin_pt: Option<f32>,
+}
+
+// 將頁面內容縮放並置中至指定紙張；回傳修改的頁數
+#[tauri::command]
+pub fn pdf_normalize_page_size(args: PdfNormalizePageSizeArgs) -> Result<usize, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::NormalizePageSize { args, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfImposeArgs {
+    pub doc_id: u64,
+    pub layout: String,              // '2up' | '4up' | 'grid' | 'booklet'
+    pub rows: Option<u32>,           // grid 用
+    pub cols: Option<u32>,           // grid 用
+    pub range: Option<String>,       // 1-based 頁碼範圍，預設全部
+    pub paper: Option<String>,       // 紙張預設（同 pdf_normalize_page_size），預設 A4
+    pub width_pt: Option<f32>,       // Custom 用
+    pub height_pt: Option<f32>,      // Custom 用
+    pub orientation: Option<String>, // 'portrait' | 'landscape'，預設依版面
+    pub margin_pt: Option<f32>,
+    pub gutter_pt: Option<f32>,
+    pub border: Option<bool>,
+    pub border_width_pt: Option<f32>,
+}
+
+// 多頁合一：以頁面 XObject 排版至新文件（保持向量），新文件登錄於 worker 且尚未存檔
+#[tauri::command]
+pub fn pdf_impose(args: PdfImposeArgs) -> Result<PdfOpenResult, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::Impose { args, reply: rtx })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
 #[tauri::command]
 pub fn pdf_save(
     doc_id: u64,
     dest_path: Option<String>,
     overwrite: Option<bool>,
+    backup: Option<bool>,
+    encryption: Option<PdfEncryptArgs>,
+    flatten_forms: Option<bool>,
 ) -> Result<PdfSaveResult, MediaError> {
     let (rtx, rrx) = mpsc::channel();
     WORKER_TX
@@ -2094,13 +10445,36 @@ pub fn pdf_save(
             doc_id,
             dest_path,
             overwrite,
+            backup: backup.unwrap_or(false),
+            encryption,
+            flatten_forms: flatten_forms.unwrap_or(false),
             reply: rtx,
         })
         .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
-    let (path, pages) = rrx
-        .recv()
-        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))??;
-    Ok(PdfSaveResult { path, pages })
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
+}
+
+// 移除加密（需擁有者密碼；未提供時使用開啟文件時的密碼），結果於下次儲存時寫入
+#[tauri::command]
+pub fn pdf_remove_encryption(
+    doc_id: u64,
+    owner_password: Option<String>,
+) -> Result<PdfSecurityInfo, MediaError> {
+    let (rtx, rrx) = mpsc::channel();
+    WORKER_TX
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or_else(|| MediaError::new("io_error", "PDF worker 未初始化"))?
+        .send(PdfRequest::RemoveEncryption {
+            doc_id,
+            owner_password,
+            reply: rtx,
+        })
+        .map_err(|e| MediaError::new("io_error", format!("worker 傳送失敗: {e}")))?;
+    rrx.recv()
+        .map_err(|e| MediaError::new("io_error", format!("worker 回應失敗: {e}")))?
 }
 
 #[tauri::command]
@@ -2243,3 +10617,525 @@ pub async fn image_to_pdf(src_path: String, dest_path: String) -> Result<String,
     .await
     .map_err(|e| MediaError::new("async_error", format!("異步任務失敗: {e}")))?
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdfium_render::prelude::*;
+
+    // 需先執行 npm run pdfium:fetch
+    fn test_pdfium() -> Pdfium {
+        get_pdfium().unwrap_or_else(|e| panic!("{}", e.message))
+    }
+
+    // 產生 pages 頁的文件（共用同一內容串流，200×200 pt）
+    fn generated_lopdf(pages: u32) -> lopdf::Document {
+        use lopdf::{Document, Object, Stream, dictionary};
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let content = doc.add_object(Stream::new(dictionary! {}, b"0 0 10 10 re f".to_vec()));
+        let kids: Vec<Object> = (0..pages)
+            .map(|_| {
+                let page =
+                    dictionary! { "Type" => "Page", "Parent" => pages_id, "Contents" => content };
+                Object::Reference(doc.add_object(page))
+            })
+            .collect();
+        let root = dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => pages as i64,
+            "MediaBox" => vec![0.into(), 0.into(), 200.into(), 200.into()],
+        };
+        doc.objects.insert(pages_id, Object::Dictionary(root));
+        let catalog = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog);
+        doc
+    }
+
+    fn generated_pdf(pages: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        generated_lopdf(pages).save_to(&mut out).unwrap();
+        out
+    }
+
+    fn outline_item(title: &str, page_index: u32) -> PdfOutlineItem {
+        PdfOutlineItem {
+            title: title.to_string(),
+            page_index: Some(page_index),
+            left: None,
+            top: None,
+            zoom: None,
+            uri: None,
+            open: false,
+            children: Vec::new(),
+        }
+    }
+
+    // 每頁一個書籤，標題為 prefix + 頁碼（1-based）
+    fn outlined_pdf(pages: u32, prefix: &str) -> Vec<u8> {
+        let mut doc = generated_lopdf(pages);
+        let items: Vec<PdfOutlineItem> = (0..pages)
+            .map(|p| outline_item(&format!("{}{}", prefix, p + 1), p))
+            .collect();
+        write_outline(&mut doc, &items).unwrap();
+        let mut out = Vec::new();
+        doc.save_to(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn edits_pages_beyond_u16_index_range() {
+        let pdfium = test_pdfium();
+        let doc = load_pdf_bytes(&pdfium, generated_pdf(70_000), None, "開啟 PDF 失敗").unwrap();
+        assert_eq!(total_pages(&doc), 70_000);
+        let last = 69_999;
+        let size = page_size_info(&doc, last).unwrap();
+        assert_eq!(
+            (size.width_pt.round(), size.height_pt.round()),
+            (200.0, 200.0)
+        );
+        assert!(page_size_info(&doc, 70_000).is_err());
+
+        let args = PdfRenderArgs {
+            doc_id: 0,
+            page_index: last,
+            scale: None,
+            dpi: None,
+            format: Some("raw".into()),
+            target_width: Some(100),
+            quality: None,
+            r#gen: None,
+        };
+        let render = render_page_for_document(&doc, &args).unwrap();
+        assert_eq!((render.width_px, render.height_px), (100, 100));
+        assert_eq!(render.image_bytes.len(), 100 * 100 * 4);
+
+        set_page_rotation(&doc, last, 90).unwrap();
+        assert_eq!(page_rotation(&doc, last).unwrap(), 90);
+
+        // 插入於末端，再複製末頁到開頭
+        assert_eq!(
+            insert_blank_page(&doc, 70_000, 300.0, 400.0).unwrap(),
+            70_001
+        );
+        assert_eq!(
+            page_size_info(&doc, 70_000).unwrap().width_pt.round(),
+            300.0
+        );
+        let other = generated_pdf(1);
+        let other = load_pdf_bytes(&pdfium, other, None, "開啟 PDF 失敗").unwrap();
+        import_pages(&other, &doc, &[70_000], 0).unwrap();
+        assert_eq!(page_size_info(&other, 0).unwrap().width_pt.round(), 300.0);
+
+        // 匯出單頁與刪除頁面都以重建文件完成
+        let exported = rebuild_document(&pdfium, &doc, &[last]).unwrap();
+        assert_eq!(total_pages(&exported), 1);
+        assert_eq!(page_rotation(&exported, 0).unwrap(), 90);
+        let (rebuilt, _) = delete_document_pages(&pdfium, &doc, None, None, vec![0]).unwrap();
+        assert_eq!(total_pages(&rebuilt), 70_000);
+        assert_eq!(page_rotation(&rebuilt, last - 1).unwrap(), 90);
+    }
+
+    #[test]
+    fn deskew_rotation_keeps_channels_without_alpha() {
+        let gray =
+            image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(40, 30, image::Luma([0])));
+        let out = rotate_image_small(&gray, 10.0, true);
+        assert_eq!(out.color(), image::ColorType::L8);
+        assert_eq!((out.width(), out.height()), (40, 30));
+        // 旋轉後露出的角落補白，中心維持原色
+        assert_eq!(out.as_luma8().unwrap().get_pixel(0, 0).0, [255]);
+        assert_eq!(out.as_luma8().unwrap().get_pixel(20, 15).0, [0]);
+        let color = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            40,
+            30,
+            image::Rgb([200, 10, 10]),
+        ));
+        assert_eq!(
+            rotate_image_small(&color, -2.0, false).color(),
+            image::ColorType::Rgb8
+        );
+    }
+
+    #[test]
+    fn delete_pages_keeps_source_on_error() {
+        let pdfium = test_pdfium();
+        let doc = load_pdf_bytes(&pdfium, generated_pdf(3), None, "開啟 PDF 失敗").unwrap();
+        for indices in [vec![0, 1, 2], vec![5]] {
+            let Err(err) = delete_document_pages(&pdfium, &doc, None, None, indices) else {
+                panic!("不可刪除所有頁面或超出範圍的頁");
+            };
+            assert_eq!(err.code, "invalid_input");
+        }
+        assert_eq!(total_pages(&doc), 3);
+        let (rebuilt, _) = delete_document_pages(&pdfium, &doc, None, None, vec![2, 0, 2]).unwrap();
+        assert_eq!(total_pages(&rebuilt), 1);
+    }
+
+    #[test]
+    fn text_units_join_surrogate_pairs() {
+        let b = |left: f32, right: f32| {
+            Some(PdfBox {
+                left,
+                bottom: 0.0,
+                right,
+                top: 10.0,
+            })
+        };
+        let out = decode_text_units([
+            (u32::from('A'), b(0.0, 5.0)),
+            (0xD840, b(5.0, 12.0)),
+            (0xDC0B, None),
+            (0x2000B, b(12.0, 20.0)),
+            (0xDC0B, b(20.0, 25.0)),
+            (0xD840, b(25.0, 30.0)),
+        ]);
+        let text: String = out.iter().map(|(c, _)| *c).collect();
+        assert_eq!(text, "A\u{2000B}\u{2000B}\u{FFFD}\u{FFFD}");
+        assert_eq!(out[1].1.map(|b| (b.left, b.right)), Some((5.0, 12.0)));
+        assert_eq!(out[4].1.map(|b| b.left), Some(25.0));
+    }
+
+    #[test]
+    fn outline_round_trips_through_lopdf() {
+        let mut doc = generated_lopdf(3);
+        let mut chapter = outline_item("第一章", 0);
+        chapter.open = true;
+        chapter.children = vec![outline_item("1.1", 1)];
+        let mut link = outline_item("網站", 0);
+        link.page_index = None;
+        link.uri = Some("https://example.com".to_string());
+        write_outline(&mut doc, &[chapter, outline_item("附錄", 2), link]).unwrap();
+        let items = read_outline(&doc);
+        assert_eq!(items.len(), 3);
+        assert_eq!(
+            (items[0].title.as_str(), items[0].page_index, items[0].open),
+            ("第一章", Some(0), true)
+        );
+        assert_eq!(items[0].children[0].page_index, Some(1));
+        assert_eq!(items[1].page_index, Some(2));
+        assert_eq!(items[2].uri.as_deref(), Some("https://example.com"));
+    }
+
+    #[test]
+    fn interleave_keeps_bookmarks_of_both_documents() {
+        let pdfium = test_pdfium();
+        let front =
+            load_pdf_bytes(&pdfium, outlined_pdf(2, "正面"), None, "開啟 PDF 失敗").unwrap();
+        let back = load_pdf_bytes(&pdfium, outlined_pdf(2, "背面"), None, "開啟 PDF 失敗").unwrap();
+        // 正面 0,1 與背面（反序）3,2 交錯
+        let items = carry_outline(&[(&front, None, 0), (&back, None, 2)], &[0, 3, 1, 2])
+            .unwrap()
+            .unwrap();
+        let pages: Vec<(&str, Option<u32>)> = items
+            .iter()
+            .map(|i| (i.title.as_str(), i.page_index))
+            .collect();
+        assert_eq!(
+            pages,
+            [
+                ("正面1", Some(0)),
+                ("正面2", Some(2)),
+                ("背面1", Some(3)),
+                ("背面2", Some(1))
+            ]
+        );
+    }
+
+    #[test]
+    fn xmp_update_keeps_unrelated_properties() {
+        let previous = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:xap="http://ns.adobe.com/xap/1.0/" xap:CreatorTool="Old Tool"
+    xmlns:xmpMM="http://ns.adobe.com/xap/1.0/mm/" xmpMM:DocumentID="uuid:1234"/>
+  <rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:pdfaid="http://www.aiim.org/pdfa/ns/id/" xmlns:acme="http://example.com/acme/">
+   <dc:title><rdf:Alt><rdf:li xml:lang="x-default">舊標題</rdf:li></rdf:Alt></dc:title>
+   <dc:description/>
+   <pdfaid:part>2</pdfaid:part>
+   <acme:Project>Apollo</acme:Project>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#;
+        let meta = PdfDocMetadata {
+            title: Some("新標題".to_string()),
+            author: None,
+            subject: None,
+            keywords: None,
+            creator: Some("New Tool".to_string()),
+            producer: None,
+            creation_date: None,
+            mod_date: None,
+            custom: BTreeMap::new(),
+            pdf_version: "1.7".to_string(),
+            has_xmp: true,
+        };
+        let packet = build_xmp(&meta, Some(previous));
+        assert!(packet.contains(r#"xmpMM:DocumentID="uuid:1234""#));
+        assert!(packet.contains("<pdfaid:part>2</pdfaid:part>"));
+        assert!(packet.contains("<acme:Project>Apollo</acme:Project>"));
+        assert!(
+            !packet.contains("舊標題")
+                && !packet.contains("Old Tool")
+                && !packet.contains("<dc:description")
+        );
+        assert_eq!(packet.matches("<dc:title>").count(), 1);
+        assert_eq!(xmp_value(&packet, "dc:title").as_deref(), Some("新標題"));
+        assert_eq!(
+            xmp_value(&packet, "xmp:CreatorTool").as_deref(),
+            Some("New Tool")
+        );
+        assert!(packet.ends_with("</x:xmpmeta>\n<?xpacket end=\"w\"?>"));
+    }
+
+    #[test]
+    fn image_watermark_embeds_one_xobject() {
+        let pdfium = test_pdfium();
+        let doc = load_pdf_bytes(&pdfium, generated_pdf(3), None, "開啟 PDF 失敗").unwrap();
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            2,
+            2,
+            image::Rgba([255, 0, 0, 128]),
+        ));
+        let unit = [10.0, 0.0, 0.0, 10.0, 5.0, 5.0];
+        let mut touched = false;
+        add_image_watermark(
+            &pdfium,
+            &doc,
+            &img,
+            &[(0, vec![unit, unit]), (2, vec![unit])],
+            true,
+            &mut touched,
+        )
+        .unwrap();
+        assert!(touched);
+
+        let lo = lopdf::Document::load_mem(&doc.save_to_bytes().unwrap()).unwrap();
+        let images = lo
+            .objects
+            .values()
+            .filter(|o| {
+                o.as_stream().is_ok_and(|s| {
+                    s.dict
+                        .get(b"Subtype")
+                        .and_then(|t| t.as_name())
+                        .is_ok_and(|t| t == b"Image")
+                })
+            })
+            .count();
+        // 影像本身與其 SMask
+        assert_eq!(images, 2);
+        let pages = lo.get_pages();
+        let mut forms = BTreeSet::new();
+        for (n, placed) in [(1, 2), (2, 0), (3, 1)] {
+            let page_id = pages[&n];
+            let content = String::from_utf8(lo.get_page_content(page_id).unwrap()).unwrap();
+            assert_eq!(content.matches(" Do").count(), placed, "page {n}");
+            if placed > 0 {
+                // 置於下方：浮水印在原內容之前
+                assert!(content.find(" Do").unwrap() < content.find("re f").unwrap());
+                let (resources, _) = lo.get_page_resources(page_id);
+                let xobjects = resources
+                    .unwrap()
+                    .get(b"XObject")
+                    .unwrap()
+                    .as_dict()
+                    .unwrap();
+                forms.extend(xobjects.iter().map(|(_, v)| v.as_reference().unwrap()));
+            }
+        }
+        assert_eq!(forms.len(), 1);
+    }
+
+    // 頁面（含其引用的 Form XObject）以 Tj/TJ 顯示的文字，僅解 Latin-1 單位元組字串
+    fn shown_text(lo: &lopdf::Document, page_id: lopdf::ObjectId) -> String {
+        fn walk(
+            lo: &lopdf::Document,
+            content: &[u8],
+            resources: Option<&lopdf::Dictionary>,
+            out: &mut String,
+        ) {
+            let Ok(content) = lopdf::content::Content::decode(content) else {
+                return;
+            };
+            for op in content.operations {
+                match op.operator.as_str() {
+                    "Tj" | "TJ" => {
+                        for operand in &op.operands {
+                            let items = operand
+                                .as_array()
+                                .cloned()
+                                .unwrap_or_else(|_| vec![operand.clone()]);
+                            for item in items {
+                                if let Ok(bytes) = item.as_str() {
+                                    out.extend(bytes.iter().map(|b| *b as char));
+                                }
+                            }
+                        }
+                    }
+                    "Do" => {
+                        let name = op.operands[0].as_name().unwrap();
+                        let xobject = resources
+                            .and_then(|r| r.get(b"XObject").ok())
+                            .and_then(|x| resolve_dict(lo, x))
+                            .and_then(|x| x.get(name).ok().and_then(|v| v.as_reference().ok()))
+                            .and_then(|id| lo.get_object(id).ok())
+                            .and_then(|o| o.as_stream().ok());
+                        if let Some(stream) = xobject {
+                            let data = stream
+                                .decompressed_content()
+                                .unwrap_or_else(|_| stream.content.clone());
+                            let inner = stream
+                                .dict
+                                .get(b"Resources")
+                                .ok()
+                                .and_then(|r| resolve_dict(lo, r));
+                            walk(lo, &data, inner.as_ref(), out);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut out = String::new();
+        let (resources, _) = lo.get_page_resources(page_id);
+        let resources = resources.cloned();
+        walk(
+            lo,
+            &lo.get_page_content(page_id).unwrap(),
+            resources.as_ref(),
+            &mut out,
+        );
+        out
+    }
+
+    #[test]
+    fn filled_text_survives_flatten() {
+        use lopdf::{Object, dictionary};
+        let mut lo = generated_lopdf(1);
+        let page_id = lo.get_pages()[&1];
+        let font = lo.add_object(dictionary! {
+            "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica", "Encoding" => "WinAnsiEncoding",
+        });
+        let field = lo.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Tx",
+            "T" => Object::string_literal("name"),
+            "Rect" => vec![20.into(), 100.into(), 180.into(), 120.into()],
+            "DA" => Object::string_literal("/Helv 12 Tf 0 g"),
+            "P" => page_id,
+        });
+        lo.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Annots", vec![Object::Reference(field)]);
+        let acroform = dictionary! {
+            "Fields" => vec![Object::Reference(field)],
+            "DR" => dictionary! { "Font" => dictionary! { "Helv" => font } },
+            "DA" => Object::string_literal("/Helv 0 Tf 0 g"),
+        };
+        lo.catalog_mut().unwrap().set("AcroForm", acroform);
+
+        let values = HashMap::from([("name".to_string(), PdfFormValue::Text("Hello".to_string()))]);
+        fill_form_fields(&mut lo, &values).unwrap();
+        let widget = lo.get_dictionary(field).unwrap();
+        assert!(widget.get(b"AP").is_ok());
+        assert_eq!(flatten_form_widgets(&mut lo).unwrap(), 1);
+
+        let page_id = lo.get_pages()[&1];
+        assert!(lo.catalog().unwrap().get(b"AcroForm").is_err());
+        assert!(shown_text(&lo, page_id).contains("Hello"));
+    }
+
+    #[test]
+    fn stamp_becomes_free_text_with_colors() {
+        use lopdf::{Object, Stream, dictionary};
+        let mut lo = generated_lopdf(1);
+        let page_id = lo.get_pages()[&1];
+        let font = lo.add_object(
+            dictionary! { "Type" => "Font", "Subtype" => "TrueType", "BaseFont" => "NotoSansTC" },
+        );
+        let appearance = lo.add_object(Stream::new(
+            dictionary! { "Resources" => dictionary! { "Font" => dictionary! { "FXF1" => font } } },
+            b"BT /FXF1 12 Tf (x) Tj ET".to_vec(),
+        ));
+        let stamp = lo.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Stamp",
+            "Rect" => vec![10.into(), 10.into(), 110.into(), 40.into()],
+            "IC" => vec![0.into(), 0.into(), 0.into()],
+            "AP" => dictionary! { "N" => appearance },
+        });
+        lo.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Annots", vec![Object::Reference(stamp)]);
+        let args: PdfAddAnnotationArgs = serde_json::from_value(serde_json::json!({
+            "docId": 1,
+            "pageIndex": 0,
+            "kind": "freeText",
+            "rect": { "left": 10.0, "bottom": 10.0, "right": 110.0, "top": 40.0 },
+            "contents": "備註",
+            "color": "#FF0000",
+            "fillColor": "#FFFF00",
+            "opacity": 0.5,
+        }))
+        .unwrap();
+        stamp_to_free_text(&mut lo, &args, 0).unwrap();
+
+        let annot = lo.get_dictionary(stamp).unwrap();
+        assert_eq!(
+            annot.get(b"Subtype").unwrap().as_name().unwrap(),
+            b"FreeText"
+        );
+        let da = decode_pdf_text(annot.get(b"DA").unwrap().as_str().unwrap());
+        assert_eq!(da, "/FXF1 12 Tf 1.000 0.000 0.000 rg");
+        let dr_fonts = annot
+            .get(b"DR")
+            .unwrap()
+            .as_dict()
+            .unwrap()
+            .get(b"Font")
+            .unwrap()
+            .as_dict()
+            .unwrap();
+        assert_eq!(dr_fonts.get(b"FXF1").unwrap().as_reference().unwrap(), font);
+        let fill: Vec<f32> = annot
+            .get(b"C")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(pdf_number)
+            .collect();
+        assert_eq!(fill, vec![1.0, 1.0, 0.0]);
+        assert_eq!(annot.get(b"CA").ok().and_then(pdf_number), Some(0.5));
+        assert!(annot.get(b"IC").is_err());
+        assert!(stamp_to_free_text(&mut lo, &args, 1).is_err());
+    }
+
+    #[test]
+    fn inspects_pdf_structure_without_pdfium() {
+        use lopdf::{Object, dictionary};
+        let mut lo = generated_lopdf(3);
+        let first = lo.get_pages()[&1];
+        let page = lo.get_dictionary_mut(first).unwrap();
+        page.set("CropBox", vec![0.into(), 0.into(), 100.into(), 150.into()]);
+        page.set("Rotate", 90);
+        let info = lo.add_object(dictionary! { "Title" => encode_pdf_text("報告"), "Author" => Object::string_literal("Kano") });
+        lo.trailer.set("Info", info);
+        let path = std::env::temp_dir().join(format!("inspect-{}.pdf", std::process::id()));
+        lo.save(&path).unwrap();
+        let res = inspect_pdf(path.to_str().unwrap());
+        let _ = fs::remove_file(&path);
+
+        let info = res.unwrap();
+        assert_eq!(info.pages, Some(3));
+        assert_eq!((info.width_pt, info.height_pt), (Some(150.0), Some(100.0)));
+        assert!(!info.encrypted);
+        assert_eq!(info.title.as_deref(), Some("報告"));
+        assert_eq!(info.author.as_deref(), Some("Kano"));
+    }
+}
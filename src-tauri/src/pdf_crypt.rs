@@ -0,0 +1,908 @@
+// PDF 標準安全處理常式：RC4（V1/V2）、AES-128（V4）與 AES-256（V5，R5/R6）的金鑰推導與加解密
+
+use crate::media::{
+    MediaError, PdfEncryptArgs, PdfPermissionArgs, dict_get_i64, resolve_dict, resolve_object,
+};
+
+type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+
+// 密碼補齊字串（ISO 32000-1 7.6.3.3）
+const PDF_PASSWORD_PADDING: [u8; 32] = [
+    0x28, 0xBF, 0x4E, 0x5E, 0x4E, 0x75, 0x8A, 0x41, 0x64, 0x00, 0x4E, 0x56, 0xFF, 0xFA, 0x01, 0x08,
+    0x2E, 0x2E, 0x00, 0xB6, 0xD0, 0x68, 0x3E, 0x80, 0x2F, 0x0C, 0xA9, 0xFE, 0x64, 0x53, 0x69, 0x7A,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum CryptMethod {
+    Identity,
+    Rc4,
+    Aes,
+}
+
+fn rc4(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut state: [u8; 256] = std::array::from_fn(|i| i as u8);
+    let mut j: u8 = 0;
+    for i in 0..256 {
+        j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+        state.swap(i, j as usize);
+    }
+    let (mut i, mut j) = (0u8, 0u8);
+    data.iter()
+        .map(|b| {
+            i = i.wrapping_add(1);
+            j = j.wrapping_add(state[i as usize]);
+            state.swap(i as usize, j as usize);
+            b ^ state[state[i as usize].wrapping_add(state[j as usize]) as usize]
+        })
+        .collect()
+}
+
+fn pad_password(password: &[u8]) -> [u8; 32] {
+    let n = password.len().min(32);
+    let mut out = [0u8; 32];
+    out[..n].copy_from_slice(&password[..n]);
+    out[n..].copy_from_slice(&PDF_PASSWORD_PADDING[..32 - n]);
+    out
+}
+
+// AES-256 密碼雜湊：R6 為 Algorithm 2.B，R5（Adobe 擴充）為單次 SHA-256
+fn hash_aes256_password(password: &[u8], salt: &[u8], udata: &[u8], revision: i64) -> [u8; 32] {
+    use aes::cipher::{BlockEncryptMut, KeyIvInit, block_padding::NoPadding};
+    use sha2::{Digest, Sha256, Sha384, Sha512};
+    let password = &password[..password.len().min(127)];
+    let mut k = Sha256::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(udata)
+        .finalize()
+        .to_vec();
+    if revision >= 6 {
+        let mut round = 0u32;
+        loop {
+            let mut k1 = Vec::with_capacity(64 * (password.len() + k.len() + udata.len()));
+            for _ in 0..64 {
+                k1.extend_from_slice(password);
+                k1.extend_from_slice(&k);
+                k1.extend_from_slice(udata);
+            }
+            let e = Aes128CbcEnc::new_from_slices(&k[..16], &k[16..32])
+                .unwrap()
+                .encrypt_padded_vec_mut::<NoPadding>(&k1);
+            let sum: u32 = e[..16].iter().map(|b| *b as u32).sum();
+            k = match sum % 3 {
+                0 => Sha256::digest(&e).to_vec(),
+                1 => Sha384::digest(&e).to_vec(),
+                _ => Sha512::digest(&e).to_vec(),
+            };
+            round += 1;
+            if round >= 64 && e.last().map(|b| *b as u32).unwrap_or(0) <= round - 32 {
+                break;
+            }
+        }
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&k[..32]);
+    out
+}
+
+// RC4/AES-128 的檔案金鑰（Algorithm 2）
+fn legacy_file_key(
+    password: &[u8],
+    owner: &[u8],
+    permissions: i32,
+    id0: &[u8],
+    revision: i64,
+    key_len: usize,
+    encrypt_metadata: bool,
+) -> Vec<u8> {
+    let mut ctx = md5::Context::new();
+    ctx.consume(pad_password(password));
+    ctx.consume(&owner[..owner.len().min(32)]);
+    ctx.consume(permissions.to_le_bytes());
+    ctx.consume(id0);
+    if revision >= 4 && !encrypt_metadata {
+        ctx.consume([0xFF; 4]);
+    }
+    let n = if revision == 2 { 5 } else { key_len };
+    let mut hash = ctx.compute().0.to_vec();
+    if revision >= 3 {
+        for _ in 0..50 {
+            hash = md5::compute(&hash[..n]).0.to_vec();
+        }
+    }
+    hash.truncate(n);
+    hash
+}
+
+// 以檔案金鑰驗證 U 值（Algorithm 6）
+fn legacy_user_key_valid(key: &[u8], user: &[u8], id0: &[u8], revision: i64) -> bool {
+    if revision == 2 {
+        return user.len() >= 32 && rc4(key, &PDF_PASSWORD_PADDING) == user[..32];
+    }
+    let mut ctx = md5::Context::new();
+    ctx.consume(PDF_PASSWORD_PADDING);
+    ctx.consume(id0);
+    let mut x = rc4(key, &ctx.compute().0);
+    for i in 1..=19u8 {
+        let k: Vec<u8> = key.iter().map(|b| b ^ i).collect();
+        x = rc4(&k, &x);
+    }
+    user.len() >= 16 && x[..16] == user[..16]
+}
+
+// 由擁有者密碼還原（補齊後的）使用者密碼（Algorithm 7）
+fn legacy_owner_to_user(password: &[u8], owner: &[u8], revision: i64, key_len: usize) -> Vec<u8> {
+    let mut hash = md5::compute(pad_password(password)).0.to_vec();
+    if revision >= 3 {
+        for _ in 0..50 {
+            hash = md5::compute(&hash).0.to_vec();
+        }
+    }
+    let key = &hash[..if revision == 2 { 5 } else { key_len.min(16) }];
+    let owner = &owner[..owner.len().min(32)];
+    if revision == 2 {
+        return rc4(key, owner);
+    }
+    let mut x = owner.to_vec();
+    for i in (0..=19u8).rev() {
+        let k: Vec<u8> = key.iter().map(|b| b ^ i).collect();
+        x = rc4(&k, &x);
+    }
+    x
+}
+
+fn crypt_filter_method(
+    doc: &lopdf::Document,
+    encrypt: &lopdf::Dictionary,
+    key: &[u8],
+) -> CryptMethod {
+    let version = dict_get_i64(doc, encrypt, b"V").unwrap_or(0);
+    if version < 4 {
+        return CryptMethod::Rc4;
+    }
+    let name = match encrypt.get(key).ok().and_then(|o| o.as_name().ok()) {
+        Some(n) if n != b"Identity" => n,
+        _ => return CryptMethod::Identity,
+    };
+    let cfm = encrypt
+        .get(b"CF")
+        .ok()
+        .and_then(|o| resolve_dict(doc, o))
+        .and_then(|cf| cf.get(name).ok().and_then(|o| resolve_dict(doc, o)))
+        .and_then(|f| {
+            f.get(b"CFM")
+                .ok()
+                .and_then(|o| o.as_name().ok())
+                .map(|n| n.to_vec())
+        });
+    match cfm.as_deref() {
+        Some(b"AESV2") | Some(b"AESV3") => CryptMethod::Aes,
+        Some(b"V2") => CryptMethod::Rc4,
+        _ => CryptMethod::Identity,
+    }
+}
+
+fn pdf_string_bytes(doc: &lopdf::Document, dict: &lopdf::Dictionary, key: &[u8]) -> Vec<u8> {
+    match dict.get(key).ok().and_then(|o| resolve_object(doc, o)) {
+        Some(lopdf::Object::String(bytes, _)) => bytes,
+        _ => Vec::new(),
+    }
+}
+
+// 遞迴轉換物件內的所有字串（串流僅處理其字典）
+fn transform_pdf_strings<F>(obj: &mut lopdf::Object, f: &F, hex: bool) -> Result<(), MediaError>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>, MediaError>,
+{
+    match obj {
+        lopdf::Object::String(bytes, format) => {
+            *bytes = f(bytes)?;
+            if hex {
+                *format = lopdf::StringFormat::Hexadecimal;
+            }
+        }
+        lopdf::Object::Array(items) => {
+            for item in items.iter_mut() {
+                transform_pdf_strings(item, f, hex)?;
+            }
+        }
+        lopdf::Object::Dictionary(dict) => {
+            for (_, v) in dict.iter_mut() {
+                transform_pdf_strings(v, f, hex)?;
+            }
+        }
+        lopdf::Object::Stream(stream) => {
+            for (_, v) in stream.dict.iter_mut() {
+                transform_pdf_strings(v, f, hex)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// 已驗證密碼的標準加密參數，可用於解密或以原參數重新加密
+pub(crate) struct PdfCrypt {
+    encrypt: lopdf::Dictionary,
+    id: Option<lopdf::Object>,
+    version: i64,
+    file_key: Vec<u8>,
+    stream_method: CryptMethod,
+    string_method: CryptMethod,
+    encrypt_metadata: bool,
+    pub(crate) is_owner: bool,
+    // 顯示用的加密方式與修訂版（R）
+    pub(crate) method: Option<String>,
+    pub(crate) revision: i64,
+}
+
+// 驗證密碼並推導檔案金鑰；支援 RC4（V1/V2）、AES-128（V4）與 AES-256（V5，R5/R6）
+pub(crate) fn read_pdf_crypt(
+    doc: &lopdf::Document,
+    password: &str,
+) -> Result<PdfCrypt, MediaError> {
+    use aes::cipher::{BlockDecryptMut, KeyIvInit, block_padding::NoPadding};
+    let encrypt = doc
+        .trailer
+        .get(b"Encrypt")
+        .ok()
+        .and_then(|o| resolve_dict(doc, o))
+        .ok_or_else(|| MediaError::new("invalid_input", "文件未加密"))?;
+    if encrypt.get(b"Filter").ok().and_then(|o| o.as_name().ok()) != Some(b"Standard") {
+        return Err(MediaError::new("unsupported", "不支援的加密處理常式"));
+    }
+    let version = dict_get_i64(doc, &encrypt, b"V").unwrap_or(0);
+    let revision = dict_get_i64(doc, &encrypt, b"R").unwrap_or(0);
+    let owner = pdf_string_bytes(doc, &encrypt, b"O");
+    let user = pdf_string_bytes(doc, &encrypt, b"U");
+    let encrypt_metadata = !matches!(
+        encrypt.get(b"EncryptMetadata"),
+        Ok(lopdf::Object::Boolean(false))
+    );
+    let id = doc
+        .trailer
+        .get(b"ID")
+        .ok()
+        .and_then(|o| resolve_object(doc, o));
+    let password = password.as_bytes();
+
+    let found = match version {
+        5 => {
+            let oe = pdf_string_bytes(doc, &encrypt, b"OE");
+            let ue = pdf_string_bytes(doc, &encrypt, b"UE");
+            if owner.len() < 48 || user.len() < 48 || oe.len() < 32 || ue.len() < 32 {
+                return Err(MediaError::new("parse_error", "加密字典不完整"));
+            }
+            let unwrap_key = |kek: [u8; 32], wrapped: &[u8]| {
+                Aes256CbcDec::new_from_slices(&kek, &[0u8; 16])
+                    .ok()
+                    .and_then(|c| c.decrypt_padded_vec_mut::<NoPadding>(&wrapped[..32]).ok())
+            };
+            if hash_aes256_password(password, &owner[32..40], &user[..48], revision) == owner[..32]
+            {
+                unwrap_key(
+                    hash_aes256_password(password, &owner[40..48], &user[..48], revision),
+                    &oe,
+                )
+                .map(|k| (k, true))
+            } else if hash_aes256_password(password, &user[32..40], &[], revision) == user[..32] {
+                unwrap_key(
+                    hash_aes256_password(password, &user[40..48], &[], revision),
+                    &ue,
+                )
+                .map(|k| (k, false))
+            } else {
+                None
+            }
+        }
+        1 | 2 | 4 => {
+            let permissions = dict_get_i64(doc, &encrypt, b"P").unwrap_or(0) as i32;
+            let key_len = if version == 1 {
+                5
+            } else {
+                (dict_get_i64(doc, &encrypt, b"Length").unwrap_or(40) as usize / 8).clamp(5, 16)
+            };
+            let id0 = match &id {
+                Some(lopdf::Object::Array(ids)) => match ids.first() {
+                    Some(lopdf::Object::String(b, _)) => b.clone(),
+                    _ => Vec::new(),
+                },
+                _ => Vec::new(),
+            };
+            let try_user = |pw: &[u8]| {
+                let key = legacy_file_key(
+                    pw,
+                    &owner,
+                    permissions,
+                    &id0,
+                    revision,
+                    key_len,
+                    encrypt_metadata,
+                );
+                legacy_user_key_valid(&key, &user, &id0, revision).then_some(key)
+            };
+            try_user(&legacy_owner_to_user(password, &owner, revision, key_len))
+                .map(|k| (k, true))
+                .or_else(|| try_user(password).map(|k| (k, false)))
+        }
+        _ => {
+            return Err(MediaError::new(
+                "unsupported",
+                format!("不支援的加密版本: V{}", version),
+            ));
+        }
+    };
+    let (file_key, is_owner) =
+        found.ok_or_else(|| MediaError::new("wrong_password", "密碼錯誤"))?;
+    Ok(PdfCrypt {
+        method: encryption_method(doc, &encrypt),
+        stream_method: crypt_filter_method(doc, &encrypt, b"StmF"),
+        string_method: crypt_filter_method(doc, &encrypt, b"StrF"),
+        encrypt,
+        id,
+        version,
+        file_key,
+        encrypt_metadata,
+        is_owner,
+        revision,
+    })
+}
+
+// 以單一物件的金鑰加密或解密資料（AES 加密時前置隨機 IV）
+fn crypt_pdf_data(
+    crypt: &PdfCrypt,
+    method: CryptMethod,
+    id: lopdf::ObjectId,
+    data: &[u8],
+    encrypt: bool,
+) -> Result<Vec<u8>, MediaError> {
+    use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit, block_padding::Pkcs7};
+    // V5 直接使用檔案金鑰；其餘版本依物件編號衍生
+    let key = if crypt.version == 5 {
+        crypt.file_key.clone()
+    } else {
+        let mut ctx = md5::Context::new();
+        ctx.consume(&crypt.file_key);
+        ctx.consume(&id.0.to_le_bytes()[..3]);
+        ctx.consume(&id.1.to_le_bytes()[..2]);
+        if method == CryptMethod::Aes {
+            ctx.consume(b"sAlT");
+        }
+        ctx.compute().0[..(crypt.file_key.len() + 5).min(16)].to_vec()
+    };
+    match method {
+        CryptMethod::Identity => Ok(data.to_vec()),
+        CryptMethod::Rc4 => Ok(rc4(&key, data)),
+        CryptMethod::Aes if encrypt => {
+            let iv: [u8; 16] = rand::random();
+            let mut out = iv.to_vec();
+            if key.len() == 32 {
+                out.extend(
+                    Aes256CbcEnc::new_from_slices(&key, &iv)
+                        .unwrap()
+                        .encrypt_padded_vec_mut::<Pkcs7>(data),
+                );
+            } else {
+                out.extend(
+                    Aes128CbcEnc::new_from_slices(&key, &iv)
+                        .unwrap()
+                        .encrypt_padded_vec_mut::<Pkcs7>(data),
+                );
+            }
+            Ok(out)
+        }
+        CryptMethod::Aes => {
+            // 部分產生器不加密空字串
+            if data.is_empty() {
+                return Ok(Vec::new());
+            }
+            let invalid = || {
+                MediaError::new(
+                    "parse_error",
+                    format!("物件 {} {} 的 AES 資料無效", id.0, id.1),
+                )
+            };
+            if data.len() < 32 || !data.len().is_multiple_of(16) {
+                return Err(invalid());
+            }
+            let (iv, body) = data.split_at(16);
+            let plain = if key.len() == 32 {
+                Aes256CbcDec::new_from_slices(&key, iv)
+                    .ok()
+                    .and_then(|c| c.decrypt_padded_vec_mut::<Pkcs7>(body).ok())
+            } else {
+                Aes128CbcDec::new_from_slices(&key, iv)
+                    .ok()
+                    .and_then(|c| c.decrypt_padded_vec_mut::<Pkcs7>(body).ok())
+            };
+            plain.ok_or_else(invalid)
+        }
+    }
+}
+
+// 加密或解密文件內所有字串與串流（略過加密字典、交互參照與物件串流）
+fn crypt_pdf_objects(
+    doc: &mut lopdf::Document,
+    crypt: &PdfCrypt,
+    encrypt: bool,
+) -> Result<(), MediaError> {
+    let encrypt_id = doc
+        .trailer
+        .get(b"Encrypt")
+        .and_then(lopdf::Object::as_reference)
+        .ok();
+    for (id, obj) in doc.objects.iter_mut() {
+        if Some(*id) == encrypt_id {
+            continue;
+        }
+        let id = *id;
+        if let lopdf::Object::Stream(stream) = obj
+            && (stream.dict.type_is(b"XRef") || stream.dict.type_is(b"ObjStm"))
+        {
+            continue;
+        }
+        transform_pdf_strings(
+            obj,
+            &|b| crypt_pdf_data(crypt, crypt.string_method, id, b, encrypt),
+            encrypt,
+        )?;
+        if let lopdf::Object::Stream(stream) = obj {
+            if stream.dict.type_is(b"Metadata") && !crypt.encrypt_metadata {
+                continue;
+            }
+            let data = crypt_pdf_data(crypt, crypt.stream_method, id, &stream.content, encrypt)?;
+            stream.set_content(data);
+        }
+    }
+    Ok(())
+}
+
+// 解密整份文件並移除 Encrypt；回傳的參數含密碼是否為擁有者密碼
+pub(crate) fn decrypt_document(
+    doc: &mut lopdf::Document,
+    password: &str,
+) -> Result<PdfCrypt, MediaError> {
+    let crypt = read_pdf_crypt(doc, password)?;
+    crypt_pdf_objects(doc, &crypt, false)?;
+    if let Ok(encrypt_id) = doc
+        .trailer
+        .get(b"Encrypt")
+        .and_then(lopdf::Object::as_reference)
+    {
+        doc.objects.remove(&encrypt_id);
+    }
+    doc.trailer.remove(b"Encrypt");
+    Ok(crypt)
+}
+
+// 以原文件的加密字典、檔案金鑰與 ID 重新加密（金鑰不變，原密碼仍可開啟）
+pub(crate) fn reencrypt_document(
+    doc: &mut lopdf::Document,
+    crypt: &PdfCrypt,
+) -> Result<(), MediaError> {
+    if doc.is_encrypted() {
+        return Err(MediaError::new("encrypted", "文件已加密"));
+    }
+    if let Some(id) = &crypt.id {
+        doc.trailer.set("ID", id.clone());
+    }
+    crypt_pdf_objects(doc, crypt, true)?;
+    let encrypt_id = doc.add_object(lopdf::Object::Dictionary(crypt.encrypt.clone()));
+    doc.trailer
+        .set("Encrypt", lopdf::Object::Reference(encrypt_id));
+    Ok(())
+}
+
+// 權限旗標 P（ISO 32000 表 22）：位元 7-8、13-32 固定為 1，位元 10（無障礙擷取）一律允許
+fn permission_value(p: Option<&PdfPermissionArgs>) -> i32 {
+    let allow = |v: Option<bool>| v.unwrap_or(true);
+    let (print, copy, modify, annotate, fill_forms) = match p {
+        Some(p) => (
+            allow(p.print),
+            allow(p.copy),
+            allow(p.modify),
+            allow(p.annotate),
+            allow(p.fill_forms),
+        ),
+        None => (true, true, true, true, true),
+    };
+    let bit = |n: u32| 1u32 << (n - 1);
+    let mut value: u32 = 0xFFFF_F000 | bit(7) | bit(8) | bit(10);
+    if print {
+        value |= bit(3) | bit(12);
+    }
+    if modify {
+        value |= bit(4) | bit(11);
+    }
+    if copy {
+        value |= bit(5);
+    }
+    if annotate {
+        value |= bit(6);
+    }
+    if fill_forms || annotate {
+        value |= bit(9);
+    }
+    value as i32
+}
+
+// 以 AES-256（V5/R6）加密文件；回傳重新開啟時使用的密碼（擁有者密碼優先）
+pub(crate) fn encrypt_document(
+    doc: &mut lopdf::Document,
+    args: &PdfEncryptArgs,
+) -> Result<String, MediaError> {
+    use aes::cipher::{
+        BlockEncrypt, BlockEncryptMut, KeyInit, KeyIvInit,
+        block_padding::{NoPadding, Pkcs7},
+    };
+    use lopdf::{Dictionary, Object, StringFormat};
+    if doc.is_encrypted() {
+        return Err(MediaError::new("encrypted", "文件已加密，請先移除加密"));
+    }
+    let user_pw = args.user_password.clone().unwrap_or_default();
+    // 未提供擁有者密碼時使用隨機值：權限限制仍有效，但無法再以擁有者身分解除
+    let owner_pw = match args.owner_password.as_deref() {
+        Some(p) if !p.is_empty() => p.to_string(),
+        _ => {
+            let random: [u8; 16] = rand::random();
+            random.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+    };
+    if user_pw.len() > 127 || owner_pw.len() > 127 {
+        return Err(MediaError::new(
+            "invalid_input",
+            "密碼長度不可超過 127 位元組",
+        ));
+    }
+    if user_pw.is_empty() && args.owner_password.as_deref().unwrap_or("").is_empty() {
+        return Err(MediaError::new(
+            "invalid_input",
+            "請至少提供開啟密碼或擁有者密碼",
+        ));
+    }
+    let file_key: [u8; 32] = rand::random();
+    let permissions = permission_value(args.permissions.as_ref());
+
+    let wrap_key = |kek: [u8; 32]| {
+        Aes256CbcEnc::new_from_slices(&kek, &[0u8; 16])
+            .unwrap()
+            .encrypt_padded_vec_mut::<NoPadding>(&file_key)
+    };
+    let (uvs, uks): ([u8; 8], [u8; 8]) = (rand::random(), rand::random());
+    let mut u = hash_aes256_password(user_pw.as_bytes(), &uvs, &[], 6).to_vec();
+    u.extend_from_slice(&uvs);
+    u.extend_from_slice(&uks);
+    let ue = wrap_key(hash_aes256_password(user_pw.as_bytes(), &uks, &[], 6));
+    let (ovs, oks): ([u8; 8], [u8; 8]) = (rand::random(), rand::random());
+    let mut o = hash_aes256_password(owner_pw.as_bytes(), &ovs, &u, 6).to_vec();
+    o.extend_from_slice(&ovs);
+    o.extend_from_slice(&oks);
+    let oe = wrap_key(hash_aes256_password(owner_pw.as_bytes(), &oks, &u, 6));
+    let mut perms = [0u8; 16];
+    perms[..4].copy_from_slice(&permissions.to_le_bytes());
+    perms[4..8].copy_from_slice(&[0xFF; 4]);
+    perms[8..12].copy_from_slice(b"Tadb");
+    perms[12..].copy_from_slice(&rand::random::<[u8; 4]>());
+    let mut block = aes::Block::clone_from_slice(&perms);
+    aes::Aes256::new_from_slice(&file_key)
+        .unwrap()
+        .encrypt_block(&mut block);
+
+    let encrypt_data = |data: &[u8]| -> Vec<u8> {
+        let iv: [u8; 16] = rand::random();
+        let mut out = iv.to_vec();
+        out.extend(
+            Aes256CbcEnc::new_from_slices(&file_key, &iv)
+                .unwrap()
+                .encrypt_padded_vec_mut::<Pkcs7>(data),
+        );
+        out
+    };
+    for obj in doc.objects.values_mut() {
+        // 交互參照與物件串流不會被寫出
+        if let Object::Stream(stream) = obj
+            && (stream.dict.type_is(b"XRef") || stream.dict.type_is(b"ObjStm"))
+        {
+            continue;
+        }
+        transform_pdf_strings(obj, &|b| Ok(encrypt_data(b)), true)?;
+        if let Object::Stream(stream) = obj {
+            let cipher = encrypt_data(&stream.content);
+            stream.set_content(cipher);
+        }
+    }
+
+    let mut std_cf = Dictionary::new();
+    std_cf.set("Type", Object::Name(b"CryptFilter".to_vec()));
+    std_cf.set("CFM", Object::Name(b"AESV3".to_vec()));
+    std_cf.set("AuthEvent", Object::Name(b"DocOpen".to_vec()));
+    std_cf.set("Length", 32);
+    let mut cf = Dictionary::new();
+    cf.set("StdCF", Object::Dictionary(std_cf));
+    let mut encrypt = Dictionary::new();
+    encrypt.set("Filter", Object::Name(b"Standard".to_vec()));
+    encrypt.set("V", 5);
+    encrypt.set("R", 6);
+    encrypt.set("Length", 256);
+    encrypt.set("CF", Object::Dictionary(cf));
+    encrypt.set("StmF", Object::Name(b"StdCF".to_vec()));
+    encrypt.set("StrF", Object::Name(b"StdCF".to_vec()));
+    encrypt.set("O", Object::String(o, StringFormat::Hexadecimal));
+    encrypt.set("U", Object::String(u, StringFormat::Hexadecimal));
+    encrypt.set("OE", Object::String(oe, StringFormat::Hexadecimal));
+    encrypt.set("UE", Object::String(ue, StringFormat::Hexadecimal));
+    encrypt.set("P", permissions as i64);
+    encrypt.set(
+        "Perms",
+        Object::String(block.to_vec(), StringFormat::Hexadecimal),
+    );
+    encrypt.set("EncryptMetadata", true);
+    let encrypt_id = doc.add_object(Object::Dictionary(encrypt));
+    doc.trailer.set("Encrypt", Object::Reference(encrypt_id));
+    if doc.trailer.get(b"ID").is_err() {
+        let id: [u8; 16] = rand::random();
+        doc.trailer.set(
+            "ID",
+            Object::Array(vec![
+                Object::String(id.to_vec(), StringFormat::Hexadecimal),
+                Object::String(id.to_vec(), StringFormat::Hexadecimal),
+            ]),
+        );
+    }
+    // AES-256 需 PDF 1.7 Adobe 擴充等級 8 或 PDF 2.0
+    if doc.version.as_str() < "2.0" {
+        doc.version = "1.7".to_string();
+        let mut adbe = Dictionary::new();
+        adbe.set("BaseVersion", Object::Name(b"1.7".to_vec()));
+        adbe.set("ExtensionLevel", 8);
+        let mut ext = Dictionary::new();
+        ext.set("ADBE", Object::Dictionary(adbe));
+        if let Ok(catalog) = doc.catalog_mut() {
+            catalog.set("Extensions", Object::Dictionary(ext));
+        }
+    }
+    Ok(if args.owner_password.as_deref().unwrap_or("").is_empty() {
+        user_pw
+    } else {
+        owner_pw
+    })
+}
+
+// 由 Encrypt 字典判斷加密方式
+pub(crate) fn encryption_method(
+    doc: &lopdf::Document,
+    encrypt: &lopdf::Dictionary,
+) -> Option<String> {
+    let length = dict_get_i64(doc, encrypt, b"Length").unwrap_or(40);
+    match dict_get_i64(doc, encrypt, b"V")? {
+        1 => Some("RC4-40".to_string()),
+        2 | 3 => Some(format!("RC4-{}", length)),
+        4 => {
+            // 以 StmF 指定的加密篩選器決定
+            let filters = encrypt.get(b"CF").ok().and_then(|o| resolve_dict(doc, o))?;
+            let name = encrypt
+                .get(b"StmF")
+                .ok()
+                .and_then(|o| o.as_name().ok())
+                .unwrap_or(b"StdCF");
+            let filter = filters.get(name).ok().and_then(|o| resolve_dict(doc, o))?;
+            match filter.get(b"CFM").ok().and_then(|o| o.as_name().ok())? {
+                b"AESV2" => Some("AES-128".to_string()),
+                b"V2" => Some("RC4-128".to_string()),
+                _ => None,
+            }
+        }
+        5 => Some("AES-256".to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 單頁文件：物件 2 為內容串流 "0 0 10 10 re f"
+    fn one_page_lopdf() -> lopdf::Document {
+        use lopdf::{Document, Object, Stream, dictionary};
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let content = doc.add_object(Stream::new(dictionary! {}, b"0 0 10 10 re f".to_vec()));
+        let page = doc.add_object(
+            dictionary! { "Type" => "Page", "Parent" => pages_id, "Contents" => content },
+        );
+        let pages = dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![Object::Reference(page)],
+            "Count" => 1,
+            "MediaBox" => vec![0.into(), 0.into(), 200.into(), 200.into()],
+        };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog);
+        doc
+    }
+
+    fn reload_lopdf(doc: &mut lopdf::Document) -> lopdf::Document {
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        lopdf::Document::load_mem(&bytes).unwrap()
+    }
+
+    fn first_stream_content(doc: &lopdf::Document) -> Vec<u8> {
+        doc.objects
+            .values()
+            .find_map(|o| o.as_stream().ok().map(|s| s.content.clone()))
+            .unwrap()
+    }
+
+    #[test]
+    fn reencrypt_keeps_original_passwords() {
+        let mut doc = one_page_lopdf();
+        let args = PdfEncryptArgs {
+            user_password: Some("user".into()),
+            owner_password: Some("owner".into()),
+            permissions: None,
+        };
+        encrypt_document(&mut doc, &args).unwrap();
+        let mut lo = reload_lopdf(&mut doc);
+        assert_ne!(first_stream_content(&lo), b"0 0 10 10 re f");
+        let crypt = decrypt_document(&mut lo, "owner").unwrap();
+        assert!(crypt.is_owner);
+        assert_eq!(first_stream_content(&lo), b"0 0 10 10 re f");
+
+        // 以原參數重新加密後，使用者密碼仍可開啟，內容不變
+        reencrypt_document(&mut lo, &crypt).unwrap();
+        let mut again = reload_lopdf(&mut lo);
+        assert!(again.is_encrypted());
+        let Err(err) = decrypt_document(&mut again.clone(), "wrong") else {
+            panic!("wrong password accepted");
+        };
+        assert_eq!(err.code, "wrong_password");
+        assert!(!decrypt_document(&mut again, "user").unwrap().is_owner);
+        assert_eq!(first_stream_content(&again), b"0 0 10 10 re f");
+    }
+
+    fn hex_bytes(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // 以 ISO 32000 演算法的獨立實作（Python + cryptography）產生的已知值：
+    // 使用者密碼 "user"、擁有者密碼 "owner"、P = -3904，物件 2 為內容串流、物件 5 為 Info
+    fn known_answer_pdf(encrypt: lopdf::Dictionary, content: &str, title: &str) -> lopdf::Document {
+        use lopdf::{Object, StringFormat, dictionary};
+        let mut doc = one_page_lopdf();
+        doc.get_object_mut((2, 0))
+            .unwrap()
+            .as_stream_mut()
+            .unwrap()
+            .set_content(hex_bytes(content));
+        let info = doc.add_object(
+            dictionary! { "Title" => Object::String(hex_bytes(title), StringFormat::Hexadecimal) },
+        );
+        let encrypt = doc.add_object(encrypt);
+        doc.trailer.set("Info", info);
+        doc.trailer.set("Encrypt", encrypt);
+        let id = Object::String(
+            hex_bytes("00112233445566778899aabbccddeeff"),
+            StringFormat::Hexadecimal,
+        );
+        doc.trailer.set("ID", vec![id.clone(), id]);
+        doc
+    }
+
+    fn info_title(doc: &lopdf::Document) -> Vec<u8> {
+        let info = doc
+            .trailer
+            .get(b"Info")
+            .and_then(lopdf::Object::as_reference)
+            .unwrap();
+        doc.get_dictionary(info)
+            .unwrap()
+            .get(b"Title")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_vec()
+    }
+
+    #[test]
+    fn decrypts_known_answer_vectors() {
+        use lopdf::{Object, StringFormat, dictionary};
+        let s = |h: &str| Object::String(hex_bytes(h), StringFormat::Hexadecimal);
+        let owner = "0ba3835f88f90388e74e54584125ce142be0de24c6b0d37746e075b891756671";
+        let user = "8b754d5ad600fecacdf06098f59f1d2e00000000000000000000000000000000";
+        let cases = [
+            (
+                "R3/RC4",
+                dictionary! { "Filter" => "Standard", "V" => 2, "R" => 3, "Length" => 128, "P" => -3904, "O" => s(owner), "U" => s(user) },
+                "786148715e9b4e91ca0e780f4e18",
+                "741647",
+            ),
+            (
+                "R4/AESV2",
+                dictionary! {
+                    "Filter" => "Standard", "V" => 4, "R" => 4, "Length" => 128, "P" => -3904, "O" => s(owner), "U" => s(user),
+                    "CF" => dictionary! { "StdCF" => dictionary! { "CFM" => "AESV2", "Length" => 16 } },
+                    "StmF" => "StdCF", "StrF" => "StdCF",
+                },
+                "000102030405060708090a0b0c0d0e0fbe124cff50a32e061bedbd592d982133",
+                "101112131415161718191a1b1c1d1e1f26854ff0efc22df56e1ae19ce53f0e6c",
+            ),
+            (
+                "R6/AESV3",
+                dictionary! {
+                    "Filter" => "Standard", "V" => 5, "R" => 6, "Length" => 256, "P" => -3904,
+                    "O" => s("8a315bf69dd058f051451db82b059609a733a4f9f4ebc58b17c6de8eccea32844f5653414c5430314f4b53414c543031"),
+                    "U" => s("2767bfdcbf33c97e7801a89cb7525250849f8643c4783ca21967491d65b0a9de555653414c543031554b53414c543031"),
+                    "OE" => s("4f51bbfc74514689bf824a1bff169ac6c8952c6c82b5054e66c975f78b54afbe"),
+                    "UE" => s("ebbebb341fcfebf9feaae39ec04a7f72209f6e197794307768d326bd9fbd641f"),
+                    "Perms" => s("ff45843751826a902ddc751eb343a031"),
+                    "CF" => dictionary! { "StdCF" => dictionary! { "CFM" => "AESV3", "Length" => 32 } },
+                    "StmF" => "StdCF", "StrF" => "StdCF",
+                },
+                "000102030405060708090a0b0c0d0e0f826a17d312cd72946e212271d1c6665f",
+                "101112131415161718191a1b1c1d1e1ff57678c8ed9d93c1f6cdd26440fef2c0",
+            ),
+        ];
+        for (name, encrypt, content, title) in cases {
+            let doc = known_answer_pdf(encrypt, content, title);
+            let mut by_user = doc.clone();
+            assert!(
+                !decrypt_document(&mut by_user, "user").unwrap().is_owner,
+                "{name}"
+            );
+            assert_eq!(first_stream_content(&by_user), b"0 0 10 10 re f", "{name}");
+            assert_eq!(info_title(&by_user), b"KAT", "{name}");
+            assert!(!by_user.is_encrypted(), "{name}");
+
+            let mut by_owner = doc.clone();
+            let crypt = decrypt_document(&mut by_owner, "owner").unwrap();
+            assert!(crypt.is_owner, "{name}");
+            assert_eq!(first_stream_content(&by_owner), b"0 0 10 10 re f", "{name}");
+
+            let Err(err) = decrypt_document(&mut doc.clone(), "nope") else {
+                panic!("{name}: wrong password accepted");
+            };
+            assert_eq!(err.code, "wrong_password", "{name}");
+
+            // 加密→解密往返；RC4 無 IV，密文應與已知值相同
+            reencrypt_document(&mut by_owner, &crypt).unwrap();
+            if name == "R3/RC4" {
+                assert_eq!(first_stream_content(&by_owner), hex_bytes(content));
+            }
+            let mut again = reload_lopdf(&mut by_owner);
+            decrypt_document(&mut again, "user").unwrap();
+            assert_eq!(first_stream_content(&again), b"0 0 10 10 re f", "{name}");
+            assert_eq!(info_title(&again), b"KAT", "{name}");
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_aes_data() {
+        let mut doc = one_page_lopdf();
+        let args = PdfEncryptArgs {
+            user_password: Some("user".into()),
+            owner_password: None,
+            permissions: None,
+        };
+        encrypt_document(&mut doc, &args).unwrap();
+        let mut lo = reload_lopdf(&mut doc);
+        // 截斷為非 16 位元組倍數的密文
+        lo.get_object_mut((2, 0))
+            .unwrap()
+            .as_stream_mut()
+            .unwrap()
+            .content
+            .truncate(24);
+        let Err(err) = decrypt_document(&mut lo, "user") else {
+            panic!("corrupt AES data accepted");
+        };
+        assert_eq!(err.code, "parse_error");
+    }
+}
@@ -0,0 +1,83 @@
+import { listen } from '@tauri-apps/api/event'
+import { getCurrentWindow } from '@tauri-apps/api/window'
+import { confirm as confirmDialog } from '@tauri-apps/plugin-dialog'
+import { useMediaStore } from '@/modules/media/store'
+import { pdfClose, pdfDocState } from '@/modules/media/service'
+import type { PdfDocState } from '@/modules/media/types'
+
+function isTauriEnv() {
+  return typeof window !== 'undefined' &&
+    ('__TAURI_INTERNALS__' in window || '__TAURI__' in window)
+}
+
+let stopGuard: (() => void) | null = null
+
+// 關閉視窗前以後端狀態檢查所有已開啟文件；儲存/放棄/取消三態同 ensureCanSwitch
+async function confirmCloseWithDirtyDocs(): Promise<boolean> {
+  const media = useMediaStore()
+  let dirty: PdfDocState[]
+  try {
+    dirty = (await pdfDocState()).filter(s => s.dirty)
+  } catch (err) {
+    console.error('[closeGuard] pdf_doc_state failed', err)
+    return true
+  }
+  if (!dirty.length) return true
+  const wantsSave = await confirmDialog(`有 ${dirty.length} 份文件尚未儲存，是否先儲存？`, {
+    title: '未儲存的變更',
+    okLabel: '儲存',
+    cancelLabel: '不儲存',
+  })
+  if (wantsSave) {
+    try {
+      await media.saveCurrentIfNeeded()
+      dirty = (await pdfDocState()).filter(s => s.dirty)
+    } catch (_) {
+      // 使用者取消了存檔或儲存失敗：不關閉
+      return false
+    }
+    if (!dirty.length) return true
+  }
+  const discard = await confirmDialog(
+    wantsSave ? `仍有 ${dirty.length} 份文件未儲存，放棄變更並關閉？` : '放棄變更並關閉？',
+    { title: '放棄變更', okLabel: '放棄', cancelLabel: '取消' },
+  )
+  if (!discard) return false
+  // 明確放棄：強制關閉文件一併移除自動儲存檔，下次啟動不再提示復原
+  for (const s of dirty) {
+    try {
+      if (s.docId === media.docId) await media.closeDoc(true)
+      else await pdfClose(s.docId, true)
+    } catch (err) {
+      console.error('[closeGuard] Failed to close document', s.docId, err)
+    }
+  }
+  return true
+}
+
+// 同步 pdf-doc-state 事件到 media store，並攔截視窗關閉
+export async function initCloseGuard(): Promise<() => void> {
+  if (!isTauriEnv()) return () => {}
+  if (stopGuard) return stopGuard
+  const media = useMediaStore()
+
+  try {
+    const unlistenState = await listen<PdfDocState>('pdf-doc-state', event => {
+      media.applyDocState(event.payload)
+    })
+    const unlistenClose = await getCurrentWindow().onCloseRequested(async event => {
+      if (!(await confirmCloseWithDirtyDocs())) event.preventDefault()
+    })
+
+    stopGuard = () => {
+      unlistenState()
+      unlistenClose()
+      stopGuard = null
+    }
+  } catch (err) {
+    console.error('[closeGuard] Failed to register listeners', err)
+    return () => {}
+  }
+
+  return stopGuard || (() => {})
+}
@@ -0,0 +1,59 @@
+import { confirm as confirmDialog, message as messageDialog } from '@tauri-apps/plugin-dialog'
+import { useMediaStore } from '@/modules/media/store'
+import { recoveryDiscard, recoveryList, recoveryRestore } from '@/modules/media/service'
+import type { RecoveryEntry } from '@/modules/media/types'
+
+function isTauriEnv() {
+  return typeof window !== 'undefined' &&
+    ('__TAURI_INTERNALS__' in window || '__TAURI__' in window)
+}
+
+let prompted = false
+
+// 啟動時詢問是否復原上次未儲存的文件。檢視器一次只開一份文件，
+// 因此只處理最近的一份，其餘復原檔保留到下次啟動
+export async function promptRecovery(): Promise<void> {
+  if (!isTauriEnv() || prompted) return
+  prompted = true
+
+  let entries: RecoveryEntry[]
+  try {
+    entries = await recoveryList()
+  } catch (err) {
+    console.error('[recoveryPrompt] recovery_list failed', err)
+    return
+  }
+  const latest = entries[0]
+  if (!latest) return
+
+  const name = latest.originalPath?.split('/').pop() || '未命名文件'
+  const when = new Date(latest.savedAt * 1000).toLocaleString()
+  const rest = entries.length > 1 ? `\n另有 ${entries.length - 1} 份將於下次啟動時詢問。` : ''
+  const restore = await confirmDialog(`發現上次未儲存的「${name}」（${when}），是否復原？${rest}`, {
+    title: '復原未儲存的文件',
+    okLabel: '復原',
+    cancelLabel: '不復原',
+  })
+  if (!restore) {
+    const discard = await confirmDialog('捨棄這份自動儲存的內容？', {
+      title: '捨棄復原檔',
+      okLabel: '捨棄',
+      cancelLabel: '保留',
+    })
+    if (discard) {
+      try { await recoveryDiscard(latest.id) } catch (err) {
+        console.error('[recoveryPrompt] recovery_discard failed', latest.id, err)
+      }
+    }
+    return
+  }
+
+  try {
+    const opened = await recoveryRestore(latest.id)
+    await useMediaStore().openRestored(opened, latest.originalPath ?? '')
+  } catch (err: any) {
+    // 失敗時復原檔仍保留（加密文件需密碼，目前無輸入介面）
+    console.error('[recoveryPrompt] recovery_restore failed', latest.id, err)
+    await messageDialog(`無法復原「${name}」：${err?.message || String(err)}`, { title: '復原失敗', kind: 'error' })
+  }
+}
@@ -1,12 +1,17 @@
 import { invoke } from '@tauri-apps/api/core'
-import type { MediaDescriptor, PageRender, PageRenderBytesRaw, PdfOpenResult } from './types'
-import type { PdfPageSize } from './types'
+import type { MediaBatchItem, MediaDescriptor, PageRender, PageRenderBytesRaw, PdfOpenResult } from './types'
+import type { PdfAddAnnotationArgs, PdfAnnotation, PdfBlankPagesResult, PdfBox, PdfDeskewResult, PdfDocMetadata, PdfDocState, PdfEncryptOptions, PdfFormField, PdfFormValue, PdfHeaderFooterResult, PdfOrientationGuess, PdfOutlineItem, PdfPageSize, PdfPageText, PdfSearchSummary, PdfSecurityInfo, PdfTextLayer, RecoveryEntry } from './types'
 
 export async function analyzeMedia(path: string): Promise<MediaDescriptor> {
   const res = await invoke<MediaDescriptor>('analyze_media', { path })
   return res
 }
 
+// 批次分析多個檔案；結果順序與 paths 相同，個別檔案失敗時該項的 error 有值
+export async function analyzeMediaBatch(paths: string[]): Promise<MediaBatchItem[]> {
+  return invoke<MediaBatchItem[]>('analyze_media_batch', { paths })
+}
+
 export interface ImageReadResult {
   width: number
   height: number
@@ -71,12 +76,36 @@ export async function pdfRenderPage(opts: {
   }
 }
 
-export async function pdfOpen(path: string): Promise<PdfOpenResult> {
-  return invoke<PdfOpenResult>('pdf_open', { path })
+// 加密文件：未提供密碼時以 code='password_required' 拒絕，密碼錯誤為 code='wrong_password'
+export async function pdfOpen(path: string, password?: string): Promise<PdfOpenResult> {
+  return invoke<PdfOpenResult>('pdf_open', { path, password })
+}
+
+export async function pdfSecurityInfo(docId: number): Promise<PdfSecurityInfo> {
+  return invoke<PdfSecurityInfo>('pdf_security_info', { docId })
+}
+
+// 文件有未儲存變更時會以 code='dirty' 拒絕；force=true 則捨棄變更
+export async function pdfClose(docId: number, force?: boolean): Promise<void> {
+  await invoke('pdf_close', { docId, force })
+}
+
+// 省略 docId 時回傳所有已開啟文件（離開程式前檢查）
+export async function pdfDocState(docId?: number): Promise<PdfDocState[]> {
+  return invoke<PdfDocState[]>('pdf_doc_state', { docId })
 }
 
-export async function pdfClose(docId: number): Promise<void> {
-  await invoke('pdf_close', { docId })
+// 啟動時查詢先前崩潰或未儲存即關閉留下的自動儲存檔
+export async function recoveryList(): Promise<RecoveryEntry[]> {
+  return invoke<RecoveryEntry[]>('recovery_list')
+}
+
+export async function recoveryRestore(id: string, password?: string): Promise<PdfOpenResult> {
+  return invoke<PdfOpenResult>('recovery_restore', { id, password })
+}
+
+export async function recoveryDiscard(id: string): Promise<void> {
+  await invoke('recovery_discard', { id })
 }
 
 // 已統一為 `pdf_render_page` 回傳 bytes，移除 *Bytes API。
@@ -98,9 +127,17 @@ export async function pdfDeletePagesDoc(opts: { docId: number, indices: number[]
 }
 
 // Save current document to path (overwrite when destPath omitted and overwrite=true)
-export async function pdfSave(opts: { docId: number, destPath?: string, overwrite?: boolean }): Promise<{ path: string, pages: number }> {
-  const { docId, destPath, overwrite } = opts
-  return invoke<{ path: string, pages: number }>('pdf_save', { docId, destPath, overwrite })
+// encryption：以 AES-256 加密輸出（未指定時沿用文件原有的加密狀態）；已加密文件須以擁有者密碼開啟，否則回傳 code='owner_password_required'
+// encryption：以 AES-256 加密輸出（未指定時沿用文件原有的加密狀態）
+// flattenForms：儲存前將表單平面化（寫入成功後開啟中的文件才一併變更）
+export async function pdfSave(opts: { docId: number, destPath?: string, overwrite?: boolean, backup?: boolean, encryption?: PdfEncryptOptions, flattenForms?: boolean }): Promise<{ path: string, pages: number, backupPath?: string, reloadError?: string | null }> {
+  const { docId, destPath, overwrite, backup, encryption, flattenForms } = opts
+  return invoke<{ path: string, pages: number, backupPath?: string, reloadError?: string | null }>('pdf_save', { docId, destPath, overwrite, backup, encryption, flattenForms })
+}
+
+// 需擁有者密碼（未提供時使用開啟時的密碼），否則回傳 code='owner_password_required'
+export async function pdfRemoveEncryption(docId: number, ownerPassword?: string): Promise<PdfSecurityInfo> {
+  return invoke<PdfSecurityInfo>('pdf_remove_encryption', { docId, ownerPassword })
 }
 
 export async function pdfExportPageImage(opts: {
@@ -155,6 +192,7 @@ export interface CompressPdfLosslessOpts {
   srcPath: string
   destPath: string
   linearize?: boolean
+  encryption?: PdfEncryptOptions
 }
 
 export async function compressPdfLossless(opts: CompressPdfLosslessOpts): Promise<{ path: string, beforeSize: number, afterSize: number }>{
@@ -171,6 +209,7 @@ export interface CompressPdfSmartOpts {
   quality?: number
   losslessOptimize?: boolean
   removeMetadata?: boolean
+  encryption?: PdfEncryptOptions
 }
 
 export async function compressPdfSmart(opts: CompressPdfSmartOpts): Promise<{
@@ -202,3 +241,332 @@ export async function pdfCopyPage(opts: { srcDocId: number, srcIndex: number, de
   const { srcDocId, srcIndex, destDocId, destIndex } = opts
   return invoke<{ pages: number }>('pdf_copy_page', { srcDocId, srcIndex, destDocId, destIndex } as any)
 }
+
+// range: 1-based 頁碼範圍字串，如 "1,3,5-7"
+export async function pdfDuplicatePages(opts: { docId: number, range: string, destIndex?: number, copies?: number }): Promise<{ pages: number }> {
+  const { docId, range, destIndex, copies } = opts
+  return invoke<{ pages: number }>('pdf_duplicate_pages', { docId, range, destIndex, copies })
+}
+
+export async function pdfReversePages(opts: { docId: number, range?: string }): Promise<{ pages: number }> {
+  const { docId, range } = opts
+  return invoke<{ pages: number }>('pdf_reverse_pages', { docId, range })
+}
+
+// 未指定 otherDocId 時交錯同一文件的前後兩半（正面 + 背面）
+export async function pdfInterleavePages(opts: { docId: number, otherDocId?: number, reverseSecond?: boolean }): Promise<{ pages: number }> {
+  const { docId, otherDocId, reverseSecond } = opts
+  return invoke<{ pages: number }>('pdf_interleave_pages', { docId, otherDocId, reverseSecond })
+}
+
+// srcPaths: 單一 PDF（可搭配 range，如 "1,3,5-7"）或多張圖片，於 index 一次插入；加密的來源 PDF 以 srcPassword 開啟
+export async function pdfInsertFromFile(opts: { docId: number, srcPaths: string[], index: number, range?: string, srcPassword?: string }): Promise<{ pages: number }> {
+  const { docId, srcPaths, index, range, srcPassword } = opts
+  return invoke<{ pages: number }>('pdf_insert_from_file', { docId, srcPaths, index, range, srcPassword })
+}
+
+// 設定頁面框；cropToContent=true 時依渲染結果自動裁切。回傳實際修改的頁數
+export async function pdfSetPageBoxes(args: {
+  docId: number
+  range?: string
+  mediaBox?: PdfBox
+  cropBox?: PdfBox
+  trimBox?: PdfBox
+  bleedBox?: PdfBox
+  artBox?: PdfBox
+  cropToContent?: boolean
+  marginPt?: number
+  dpi?: number
+  tolerance?: number
+}): Promise<number> {
+  return invoke<number>('pdf_set_page_boxes', { args })
+}
+
+// 將頁面內容與註解縮放並置中至指定紙張（Custom 需提供 widthPt/heightPt）；含圖章或表單欄位的頁面會被拒絕；回傳修改的頁數
+export async function pdfNormalizePageSize(args: {
+  docId: number
+  range?: string
+  paper: 'A4' | 'Letter' | 'A5' | 'Legal' | 'Tabloid' | 'Custom'
+  widthPt?: number
+  heightPt?: number
+  orientation?: 'auto' | 'portrait' | 'landscape'
+  mode?: 'fit' | 'fill' | 'none'
+  marginPt?: number
+}): Promise<number> {
+  return invoke<number>('pdf_normalize_page_size', { args })
+}
+
+// 多頁合一（2-up / 4-up / grid / 騎馬釘小冊子），結果為新的未存檔文件
+export async function pdfImpose(args: {
+  docId: number
+  layout: '2up' | '4up' | 'grid' | 'booklet'
+  rows?: number
+  cols?: number
+  range?: string
+  paper?: 'A4' | 'Letter' | 'A5' | 'Legal' | 'Tabloid' | 'Custom'
+  widthPt?: number
+  heightPt?: number
+  orientation?: 'portrait' | 'landscape'
+  marginPt?: number
+  gutterPt?: number
+  border?: boolean
+  borderWidthPt?: number
+}): Promise<PdfOpenResult> {
+  return invoke<PdfOpenResult>('pdf_impose', { args })
+}
+
+// 文字/影像浮水印（text 與 imagePath 擇一）；非 Latin-1 文字需指定 TrueType 字型 fontPath。回傳修改的頁數
+export async function pdfAddWatermark(args: {
+  docId: number
+  range?: string
+  text?: string
+  imagePath?: string
+  font?: string
+  fontPath?: string
+  fontSize?: number
+  color?: string
+  opacity?: number
+  // 逆時針角度
+  rotation?: number
+  imageWidthPt?: number
+  position?: 'center' | 'top-left' | 'top' | 'top-right' | 'left' | 'right' | 'bottom-left' | 'bottom' | 'bottom-right'
+  offsetX?: number
+  offsetY?: number
+  marginPt?: number
+  tile?: boolean
+  tileGapPt?: number
+  layer?: 'over' | 'under'
+}): Promise<number> {
+  return invoke<number>('pdf_add_watermark', { args })
+}
+
+// 頁首/頁尾與頁碼：範本可用 {page}、{total}、{filename}、{date}、{bates}（prefix + 補零編號 + suffix）
+export async function pdfAddHeaderFooter(args: {
+  docId: number
+  range?: string
+  headerLeft?: string
+  headerCenter?: string
+  headerRight?: string
+  footerLeft?: string
+  footerCenter?: string
+  footerRight?: string
+  font?: string
+  fontPath?: string
+  fontSize?: number
+  color?: string
+  marginXPt?: number
+  marginYPt?: number
+  startNumber?: number
+  date?: string
+  batesPrefix?: string
+  batesSuffix?: string
+  batesStart?: number
+  batesDigits?: number
+}): Promise<PdfHeaderFooterResult> {
+  return invoke<PdfHeaderFooterResult>('pdf_add_header_footer', { args })
+}
+
+export async function pdfFormFields(docId: number): Promise<PdfFormField[]> {
+  return invoke<PdfFormField[]>('pdf_form_fields', { docId })
+}
+
+// 以完整欄位名稱填值；核取方塊可用 true/false 或開啟狀態名稱，選項按鈕為選項值。回傳填寫後的欄位
+export async function pdfFormFill(docId: number, values: Record<string, PdfFormValue>): Promise<PdfFormField[]> {
+  return invoke<PdfFormField[]>('pdf_form_fill', { docId, values })
+}
+
+// 將欄位畫入頁面內容並移除表單（其他註解保留）
+export async function pdfFormFlatten(docId: number): Promise<PdfFormField[]> {
+  return invoke<PdfFormField[]>('pdf_form_flatten', { docId })
+}
+
+// 未指定 pageIndex 時列出全部頁面（不含 Popup）
+export async function pdfAnnotations(docId: number, pageIndex?: number): Promise<PdfAnnotation[]> {
+  return invoke<PdfAnnotation[]>('pdf_annotations', { docId, pageIndex })
+}
+
+// 文字標記可直接使用 PdfSearchHit.quads；freeText 含非 Latin-1 文字時需指定 fontPath（否則回傳 font_required）。回傳建立的註解
+export async function pdfAnnotationAdd(args: PdfAddAnnotationArgs): Promise<PdfAnnotation> {
+  return invoke<PdfAnnotation>('pdf_annotation_add', { args })
+}
+
+// 依 (pageIndex, index) 刪除，連同其 Popup；回傳剩餘的註解
+export async function pdfAnnotationDelete(docId: number, targets: { pageIndex: number; index: number }[]): Promise<PdfAnnotation[]> {
+  return invoke<PdfAnnotation[]>('pdf_annotation_delete', { docId, targets })
+}
+
+// 將表單欄位與連結以外的註解畫入頁面內容；回傳剩餘的註解
+export async function pdfAnnotationsFlatten(docId: number): Promise<PdfAnnotation[]> {
+  return invoke<PdfAnnotation[]>('pdf_annotations_flatten', { docId })
+}
+
+// 批次旋轉：range 可用 "1,3,5-7"、"odd"、"even"；indices（0-based）優先。回傳所有頁面旋轉角度
+export async function pdfRotatePages(args: {
+  docId: number
+  range?: string
+  indices?: number[]
+  angle: number
+  relative?: boolean
+}): Promise<number[]> {
+  return invoke<number[]>('pdf_rotate_pages', { args })
+}
+
+// 各頁目前旋轉角度（0/90/180/270）
+export async function pdfPageRotations(docId: number): Promise<number[]> {
+  return invoke<number[]>('pdf_page_rotations', { docId })
+}
+
+// 自動判斷頁面方向；apply=true 時套用信心值 >= minConfidence 的修正
+export async function pdfAutoOrient(args: {
+  docId: number
+  range?: string
+  apply?: boolean
+  minConfidence?: number
+  dpi?: number
+}): Promise<PdfOrientationGuess[]> {
+  return invoke<PdfOrientationGuess[]>('pdf_auto_orient', { args })
+}
+
+// 掃描頁校正傾斜（僅處理只含單一影像的頁面）；apply=false 時僅偵測角度
+export async function pdfDeskew(args: {
+  docId: number
+  range?: string
+  maxAngle?: number
+  minAngle?: number
+  apply?: boolean
+}): Promise<PdfDeskewResult[]> {
+  return invoke<PdfDeskewResult[]>('pdf_deskew', { args })
+}
+
+// 預覽校正結果（不變更文件）；angle 指定時略過偵測
+export async function pdfDeskewPreview(args: {
+  docId: number
+  pageIndex: number
+  maxAngle?: number
+  angle?: number
+  targetWidth?: number
+  format?: 'png' | 'webp' | 'jpeg'
+  quality?: number
+}): Promise<PageRender> {
+  const raw = await invoke<PageRenderBytesRaw>('pdf_deskew_preview', { args })
+  const mime = raw.format === 'webp' ? 'image/webp' : (raw.format === 'jpeg' ? 'image/jpeg' : 'image/png')
+  const url = URL.createObjectURL(new Blob([new Uint8Array(raw.imageBytes)], { type: mime }))
+  return {
+    pageIndex: raw.pageIndex,
+    widthPx: raw.widthPx,
+    heightPx: raw.heightPx,
+    scale: raw.scale,
+    dpi: raw.dpi,
+    format: raw.format,
+    imagePath: '',
+    contentUrl: url,
+  }
+}
+
+// 偵測空白頁（回傳各頁墨跡覆蓋率）；delete=true 時一併刪除
+export async function pdfDetectBlankPages(args: {
+  docId: number
+  range?: string
+  dpi?: number
+  inkThreshold?: number
+  noiseThreshold?: number
+  tolerance?: number
+  edgeMargin?: number
+  delete?: boolean
+}): Promise<PdfBlankPagesResult> {
+  return invoke<PdfBlankPagesResult>('pdf_detect_blank_pages', { args })
+}
+
+// 擷取頁面文字；granularity 為 'word' / 'char' 時附帶外框（頁面座標，pt）
+export async function pdfExtractText(args: {
+  docId: number
+  range?: string
+  granularity?: 'page' | 'word' | 'char'
+}): Promise<PdfPageText[]> {
+  return invoke<PdfPageText[]>('pdf_extract_text', { args })
+}
+
+// 匯出文字為 .txt，頁與頁之間以換頁字元分隔
+export async function pdfExportText(docId: number, destPath: string, range?: string, overwrite?: boolean): Promise<string> {
+  return invoke<string>('pdf_export_text', { docId, destPath, range, overwrite })
+}
+
+// 全文搜尋：結果經 'pdf-search-hit' 事件逐頁送出，完成時送 'pdf-search-done'；未指定 gen 時由後端配發並於回傳值帶回
+export async function pdfSearch(args: {
+  docId: number
+  query: string
+  matchCase?: boolean
+  wholeWord?: boolean
+  regex?: boolean
+  maxHits?: number
+  gen?: number
+}): Promise<PdfSearchSummary> {
+  return invoke<PdfSearchSummary>('pdf_search', { args })
+}
+
+// 取消 gen < minGen 的搜尋
+export async function pdfSearchCancel(docId: number, minGen: number): Promise<void> {
+  await invoke('pdf_search_cancel', { docId, minGen })
+}
+
+// 文字層：座標與字級為像素，widthPx 傳入 PageRender.widthPx 即可與渲染結果對齊
+export async function pdfPageTextLayer(docId: number, pageIndex: number, widthPx?: number): Promise<PdfTextLayer> {
+  return invoke<PdfTextLayer>('pdf_page_text_layer', { args: { docId, pageIndex, widthPx } })
+}
+
+// 書籤：path 為各層子項目索引（0-based），各編輯命令皆回傳編輯後的完整書籤樹
+export async function pdfOutlineGet(docId: number): Promise<PdfOutlineItem[]> {
+  return invoke<PdfOutlineItem[]>('pdf_outline_get', { docId })
+}
+
+export async function pdfOutlineSet(docId: number, items: PdfOutlineItem[]): Promise<PdfOutlineItem[]> {
+  return invoke<PdfOutlineItem[]>('pdf_outline_set', { docId, items })
+}
+
+export async function pdfOutlineAdd(docId: number, item: PdfOutlineItem, parentPath?: number[], index?: number): Promise<PdfOutlineItem[]> {
+  return invoke<PdfOutlineItem[]>('pdf_outline_add', { docId, item, parentPath, index })
+}
+
+export async function pdfOutlineRename(docId: number, path: number[], title: string): Promise<PdfOutlineItem[]> {
+  return invoke<PdfOutlineItem[]>('pdf_outline_rename', { docId, path, title })
+}
+
+// newParentPath 與 index 以移除原項目後的樹為準
+export async function pdfOutlineMove(docId: number, path: number[], newParentPath?: number[], index?: number): Promise<PdfOutlineItem[]> {
+  return invoke<PdfOutlineItem[]>('pdf_outline_move', { docId, path, newParentPath, index })
+}
+
+export async function pdfOutlineDelete(docId: number, path: number[]): Promise<PdfOutlineItem[]> {
+  return invoke<PdfOutlineItem[]>('pdf_outline_delete', { docId, path })
+}
+
+// 由 (標題, 頁) 清單產生單層書籤；append 為 true 時保留既有書籤
+export async function pdfOutlineGenerate(
+  docId: number,
+  entries: { title: string; pageIndex: number }[],
+  append?: boolean,
+): Promise<PdfOutlineItem[]> {
+  return invoke<PdfOutlineItem[]>('pdf_outline_generate', { docId, entries, append })
+}
+
+export async function pdfGetMetadata(docId: number): Promise<PdfDocMetadata> {
+  return invoke<PdfDocMetadata>('pdf_get_metadata', { docId })
+}
+
+// 欄位未指定表示不變、空字串表示移除；Info 與 XMP 同步寫入
+export async function pdfSetMetadata(args: {
+  docId: number
+  title?: string
+  author?: string
+  subject?: string
+  keywords?: string
+  creator?: string
+  producer?: string
+  creationDate?: string
+  modDate?: string
+  custom?: Record<string, string>
+  touchModDate?: boolean
+  xmp?: boolean
+}): Promise<PdfDocMetadata> {
+  return invoke<PdfDocMetadata>('pdf_set_metadata', { args })
+}
@@ -1,7 +1,7 @@
 import { defineStore } from 'pinia'
 import { ref, computed } from 'vue'
 import type { FileItem } from '@/components/FileList/types'
-import type { MediaDescriptor, PageRender } from './types'
+import type { MediaDescriptor, PageRender, PdfDocState, PdfOpenResult } from './types'
 import { analyzeMedia, imageRead, pdfRenderPage, pdfOpen, pdfClose, pdfPageSize, pdfRenderCancel, pdfSave } from './service'
 import { useSettingsStore } from '@/modules/settings/store'
 import { useFileListStore } from '@/modules/filelist/store'
@@ -127,19 +127,29 @@ export const useMediaStore = defineStore('media', () => {
     await loadDescriptor(path)
   }
 
-  async function loadDescriptor(path: string) {
+  // 以復原的文件 session 取代目前文件（啟動時使用，原檔可能已不存在）；復原內容視為未儲存
+  async function openRestored(opened: PdfOpenResult, path: string) {
+    if (path) {
+      try { useFileListStore().add(path) } catch {}
+    }
+    selected.value = { id: path || `recovery-${opened.docId}`, name: path.split('/').pop() || '未命名文件', path }
+    await loadDescriptor(path, opened)
+  }
+
+  // 呼叫端需已確認捨棄未儲存變更（ensureCanSwitch 或「捨棄」按鈕）；restored 為已開啟的復原 session
+  async function loadDescriptor(path: string, restored?: PdfOpenResult) {
+    // 關閉上一份文件 session；失敗時保留 docId，避免後端文件遺失追蹤
+    if (docId.value != null) {
+      await pdfClose(docId.value, true)
+      docId.value = null
+    }
     loading.value = true
     error.value = null
     descriptor.value = null
     pdfFirstPage.value = null
     pdfPages.value = []
-    // 切換或重新載入文件時，未儲存變更不再有效（舊 session 會被關閉）
+    // 切換或重新載入文件時，未儲存變更不再有效（舊 session 已關閉）
     dirty.value = false
-    // 關閉上一份文件 session
-    if (docId.value != null) {
-      try { await pdfClose(docId.value) } catch(_) {}
-      docId.value = null
-    }
     // 釋放舊 PDF blob URLs
     try {
       for (const p of pdfPages.value) {
@@ -154,15 +164,17 @@ export const useMediaStore = defineStore('media', () => {
       imageObjectUrl.value = null
     }
     try {
-      const d = await analyzeMedia(path)
+      const fallback: MediaDescriptor = { path, type: 'pdf', name: path.split('/').pop() || '未命名文件' }
+      const d = restored ? await analyzeMedia(path).catch(() => fallback) : await analyzeMedia(path)
       descriptor.value = d
       if (d.type === 'image') {
         // 直接以 RAM bytes 建立 blob URL（避免 asset://）
         await fallbackLoadImageBlob()
       } else if (d.type === 'pdf') {
         // 開啟 session
-        const opened = await pdfOpen(d.path)
+        const opened = restored ?? await pdfOpen(d.path)
         docId.value = opened.docId
+        if (restored) dirty.value = true
         descriptor.value = { ...d, pages: opened.pages }
         // 初始化頁框
         pdfPages.value = Array.from({ length: opened.pages }, () => null)
@@ -187,15 +199,21 @@ export const useMediaStore = defineStore('media', () => {
     }
   }
 
-  async function closeDoc() {
+  // force：使用者已確認捨棄變更；未強制時後端對未儲存文件回傳 dirty 錯誤並保留 docId
+  async function closeDoc(force = false) {
     if (docId.value != null) {
-      try { await pdfClose(docId.value) } catch (_) {}
+      await pdfClose(docId.value, force)
       docId.value = null
+      dirty.value = false
     }
   }
 
   function markDirty() { dirty.value = true }
   function clearDirty() { dirty.value = false }
+  // 後端 pdf-doc-state 事件：目前文件的 dirty 以後端 revision 為準
+  function applyDocState(state: PdfDocState) {
+    if (state.docId === docId.value) dirty.value = state.dirty
+  }
 
   // 儲存目前文件（若是 PDF 且有變更）。拋出例外代表使用者取消或失敗。
   async function saveCurrentIfNeeded(): Promise<void> {
@@ -205,10 +223,10 @@ export const useMediaStore = defineStore('media', () => {
     if (!d || d.type !== 'pdf' || id == null) return
     const filelist = useFileListStore()
     const del = settings.s.deleteBehavior
-    if (del === 'saveAsNew') {
+    // 復原的文件可能沒有原檔路徑，只能另存
+    if (del === 'saveAsNew' || !d.path) {
       const base = (d.name?.replace(/\.pdf$/i, '') || 'output') + ' (edited).pdf'
-      const dir = await dirname(d.path)
-      const suggested = await join(dir, base)
+      const suggested = d.path ? await join(await dirname(d.path), base) : base
       const picked = await saveDialog({ defaultPath: suggested, filters: [{ name: 'PDF', extensions: ['pdf'] }] })
       if (!picked) throw new Error('SAVE_CANCELLED')
       const res = await pdfSave({ docId: id, destPath: picked, overwrite: true })
@@ -486,6 +504,7 @@ export const useMediaStore = defineStore('media', () => {
     ensureCanSwitch,
     saveCurrentIfNeeded,
     loadDescriptor,
+    openRestored,
     ensurePdfFirstPage,
     renderPdfPage,
     cancelQueued,
@@ -501,5 +520,6 @@ export const useMediaStore = defineStore('media', () => {
     baseCssWidthAt100,
     markDirty,
     clearDirty,
+    applyDocState,
   }
 })
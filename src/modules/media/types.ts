@@ -6,9 +6,23 @@ export interface MediaDescriptor {
   name: string
   size?: number
   pages?: number
+  // 影像為像素；PDF 為第一頁尺寸（pt）
   width?: number
   height?: number
   orientation?: 1|2|3|4|5|6|7|8
+  // 影像色彩格式（如 'rgb8'、'rgba8'、'l8'）
+  colorType?: string | null
+  encrypted?: boolean | null
+  title?: string | null
+  author?: string | null
+  pdfVersion?: string | null
+}
+
+// 批次分析的單筆結果：成功時 descriptor 有值，失敗時 error 有值
+export interface MediaBatchItem {
+  path: string
+  descriptor: MediaDescriptor | null
+  error: { code: string; message: string } | null
 }
 
 export interface PageRender {
@@ -40,9 +54,245 @@ export interface PageRenderBytesRaw {
 export interface PdfOpenResult {
   docId: number
   pages: number
+  // 僅 pdfOpen 回傳
+  security?: PdfSecurityInfo
+}
+
+export interface PdfPermissionFlags {
+  print: boolean
+  printHighQuality: boolean
+  copy: boolean
+  modify: boolean
+  annotate: boolean
+  fillForms: boolean
+  assemble: boolean
+}
+
+// 儲存時加密（AES-256）；權限未指定者皆允許，未提供擁有者密碼時以隨機值代替
+export interface PdfEncryptOptions {
+  userPassword?: string
+  ownerPassword?: string
+  permissions?: {
+    print?: boolean
+    copy?: boolean
+    modify?: boolean
+    annotate?: boolean
+    fillForms?: boolean
+  }
+}
+
+export interface PdfSecurityInfo {
+  encrypted: boolean
+  // 'RC4-40' | 'RC4-128' | 'AES-128' | 'AES-256'
+  method?: string | null
+  revision?: number | null
+  permissions: PdfPermissionFlags
+}
+
+// pt，PDF 使用者空間（左下為原點）
+export interface PdfBox {
+  left: number
+  bottom: number
+  right: number
+  top: number
 }
 
 export interface PdfPageSize {
+  // 顯示尺寸（已套用裁切框與旋轉）
   widthPt: number
   heightPt: number
+  rotation: 0|90|180|270
+  mediaBox: PdfBox
+  cropBox?: PdfBox
+  trimBox?: PdfBox
+  bleedBox?: PdfBox
+  artBox?: PdfBox
+}
+
+// 亦為 'pdf-doc-state' 事件的 payload
+export interface PdfDocState {
+  docId: number
+  path?: string
+  dirty: boolean
+  revision: number
+}
+
+export interface RecoveryEntry {
+  id: string
+  originalPath?: string
+  // unix 秒
+  savedAt: number
+  pages: number
+  size?: number
+}
+
+export interface PdfOrientationGuess {
+  index: number
+  rotation: 0|90|180|270
+  // 需順時針補轉的角度；suggested 為修正後角度
+  correction: 0|90|180|270
+  suggested: 0|90|180|270
+  confidence: number
+  method: 'text' | 'image' | 'none'
+  applied: boolean
+}
+
+export interface PdfDeskewResult {
+  index: number
+  // 偵測到的傾斜角（度，正值為順時針傾斜）
+  angle?: number
+  confidence: number
+  applied: boolean
+  reason?: 'not_image_page' | 'no_content' | 'above_max' | 'below_min'
+}
+
+export interface PdfBlankPagesResult {
+  // coverage：墨跡覆蓋率（0~1）
+  pages: { index: number; coverage: number; blank: boolean }[]
+  deleted: number[]
+  pagesAfter?: number
+}
+
+export interface PdfTextItem {
+  text: string
+  bbox: PdfBox
+}
+
+export interface PdfPageText {
+  index: number
+  text: string
+  chars?: PdfTextItem[]
+  words?: PdfTextItem[]
+}
+
+// quads：每行一組 [左上x, 左上y, 右上x, 右上y, 左下x, 左下y, 右下x, 右下y]（頁面座標 pt）
+export interface PdfSearchHit {
+  text: string
+  quads: number[][]
+}
+
+// 'pdf-search-hit' 事件 payload
+export interface PdfSearchPageHits {
+  docId: number
+  gen: number
+  pageIndex: number
+  hits: PdfSearchHit[]
+}
+
+// 'pdf-search-done' 事件 payload
+export interface PdfSearchSummary {
+  docId: number
+  gen: number
+  total: number
+  pagesSearched: number
+  canceled: boolean
+}
+
+export interface PdfTextRun {
+  text: string
+  x: number
+  y: number
+  width: number
+  height: number
+  fontSize: number
+  // 順時針角度（同 CSS rotate）
+  angle: number
+}
+
+export interface PdfTextLayer {
+  pageIndex: number
+  widthPx: number
+  heightPx: number
+  runs: PdfTextRun[]
+}
+
+// 書籤節點；left/top 為目的地位置（pt），無 pageIndex 者可能為外部連結
+export interface PdfOutlineItem {
+  title: string
+  pageIndex?: number | null
+  left?: number | null
+  top?: number | null
+  zoom?: number | null
+  uri?: string | null
+  open?: boolean
+  children?: PdfOutlineItem[]
+}
+
+// 文件中繼資料；日期為 ISO 8601
+export interface PdfDocMetadata {
+  title?: string | null
+  author?: string | null
+  subject?: string | null
+  keywords?: string | null
+  creator?: string | null
+  producer?: string | null
+  creationDate?: string | null
+  modDate?: string | null
+  custom: Record<string, string>
+  pdfVersion: string
+  hasXmp: boolean
+}
+
+export interface PdfHeaderFooterResult {
+  pages: number
+  // 下一份文件接續用的 Bates 起始號
+  nextBates: number
+}
+
+export type PdfFormValue = string | boolean | string[]
+
+// 表單欄位；name 為完整名稱（父欄位以 '.' 串接），rect 為頁面座標（pt）
+export interface PdfFormField {
+  name: string
+  fieldType: 'text' | 'checkbox' | 'radio' | 'combo' | 'list' | 'button' | 'signature' | 'unknown'
+  value?: PdfFormValue | null
+  // 選擇欄位為 /Opt；核取方塊/選項按鈕為開啟狀態名稱
+  options: { value: string; label: string }[]
+  readOnly: boolean
+  required: boolean
+  multiline: boolean
+  multiSelect: boolean
+  widgets: { pageIndex?: number | null; rect?: PdfBox | null }[]
+}
+
+// 註解；index 為該頁 /Annots 中的位置（刪除後會變動），座標為頁面使用者空間（pt）
+export interface PdfAnnotation {
+  pageIndex: number
+  index: number
+  // PDF /Subtype 首字小寫：'highlight' | 'underline' | 'strikeout' | 'freeText' | 'ink' | 'square' | 'circle' | 'text' | 'link' | 'widget' ...
+  kind: string
+  rect?: PdfBox | null
+  contents?: string | null
+  author?: string | null
+  // '#RRGGBB'；fillColor 為矩形/橢圓內部或 freeText 底色
+  color?: string | null
+  fillColor?: string | null
+  opacity?: number | null
+  modified?: string | null
+  // 文字標記範圍，格式同 PdfSearchHit.quads
+  quads: number[][]
+}
+
+export interface PdfAddAnnotationArgs {
+  docId: number
+  pageIndex: number
+  kind: 'highlight' | 'underline' | 'strikeout' | 'freeText' | 'ink' | 'square' | 'circle'
+  // highlight/underline/strikeout
+  quads?: number[][]
+  // freeText/square/circle
+  rect?: PdfBox
+  // ink：每筆 [x1, y1, x2, y2, ...]
+  inkList?: number[][]
+  // freeText 為顯示文字
+  contents?: string
+  author?: string
+  color?: string
+  fillColor?: string
+  opacity?: number
+  // 預設 ink 2、square/circle 1、freeText 0
+  borderWidth?: number
+  // freeText：非 Latin-1 文字需指定 TrueType 字型 fontPath
+  fontSize?: number
+  font?: string
+  fontPath?: string
 }